@@ -0,0 +1,106 @@
+/*
+ * Times single threaded renders of a finely tessellated grid lit by several lights, which is
+ * dominated by per-vertex lighting in setup_triangles. The built in bench harness is nightly
+ * only, so this is a plain timing loop run with `cargo bench --bench lighting`.
+ */
+use rasterboy::math::*;
+use rasterboy::mesh::*;
+use rasterboy::rasterizer::RenderOptions;
+use rasterboy::scene::*;
+use std::hint::black_box;
+use std::time::Instant;
+
+const GRID_SIZE: usize = 200;
+const NUM_LIGHTS: usize = 8;
+const NUM_FRAMES: u32 = 10;
+
+// a GRID_SIZE by GRID_SIZE grid of quads facing +z, spanning -1 to 1 on x and y
+fn grid_mesh() -> Mesh {
+    let mut mesh = Mesh::default();
+    let step = 2.0 / GRID_SIZE as f32;
+    for y in 0..=GRID_SIZE {
+        for x in 0..=GRID_SIZE {
+            mesh.verticies.push(Vector3 {
+                x: -1.0 + x as f32 * step,
+                y: -1.0 + y as f32 * step,
+                z: 0.0,
+            });
+        }
+    }
+    mesh.vertex_normals.push(Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    });
+
+    let idx = |x: usize, y: usize| y * (GRID_SIZE + 1) + x;
+    for y in 0..GRID_SIZE {
+        for x in 0..GRID_SIZE {
+            for (a, b, c) in [
+                (idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)),
+                (idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)),
+            ] {
+                mesh.face_indicies.push(Triangle {
+                    a,
+                    b,
+                    c,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    mesh
+}
+
+fn main() {
+    let mut scene = Scene {
+        camera: Camera::new(320, 240, 60_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    scene.models.push(Model {
+        mesh: grid_mesh(),
+        transform: Mat4::translation(0.0, 0.0, -3.0),
+        ..Default::default()
+    });
+    for i in 0..NUM_LIGHTS {
+        let angle = i as f32 / NUM_LIGHTS as f32 * std::f32::consts::TAU;
+        scene.lights.push(Light {
+            position: Vector3 {
+                x: angle.cos() * 2.0,
+                y: angle.sin() * 2.0,
+                z: 1.0,
+            },
+            color: Color {
+                r: 40,
+                g: 30,
+                b: 20,
+            }
+            .to_vector3(),
+            ambient_strength: 0.01,
+        });
+    }
+
+    let options = RenderOptions {
+        threads: 1,
+        ..Default::default()
+    };
+    let num_pixels = (scene.camera.canvas_width * scene.camera.canvas_height) as usize;
+    let mut pixels = vec![Color::default(); num_pixels];
+    let mut depths = vec![f32::MAX; num_pixels];
+
+    // one untimed frame to warm up caches
+    scene.render(options, &mut pixels, &mut depths);
+
+    let start = Instant::now();
+    for _ in 0..NUM_FRAMES {
+        depths.fill(f32::MAX);
+        scene.render(options, black_box(&mut pixels), black_box(&mut depths));
+    }
+    let per_frame = start.elapsed() / NUM_FRAMES;
+    println!(
+        "{} triangles, {} lights: {:?} per frame",
+        scene.models[0].mesh.face_indicies.len(),
+        NUM_LIGHTS,
+        per_frame
+    );
+}
@@ -0,0 +1,62 @@
+/*
+ * Times loading a large OBJ file, a finely tessellated grid with normals and texture coordinates
+ * written to the temp directory first. Run with `cargo bench --bench obj_loading`.
+ */
+use rasterboy::mesh::*;
+use std::fmt::Write;
+use std::fs;
+use std::time::Instant;
+
+const GRID_SIZE: usize = 700;
+const NUM_LOADS: u32 = 5;
+
+// a GRID_SIZE by GRID_SIZE grid of quads, every vertex with its own normal and uv
+fn grid_obj() -> String {
+    let mut obj = String::new();
+    for y in 0..=GRID_SIZE {
+        for x in 0..=GRID_SIZE {
+            let (u, v) = (x as f32 / GRID_SIZE as f32, y as f32 / GRID_SIZE as f32);
+            let height = (u * 20.0).sin() * (v * 20.0).cos() * 0.05;
+            writeln!(obj, "v {} {} {}", u * 2.0 - 1.0, v * 2.0 - 1.0, height).unwrap();
+            writeln!(obj, "vn 0 0 1").unwrap();
+            writeln!(obj, "vt {} {}", u, v).unwrap();
+        }
+    }
+    // obj indices start at 1
+    let idx = |x: usize, y: usize| y * (GRID_SIZE + 1) + x + 1;
+    for y in 0..GRID_SIZE {
+        for x in 0..GRID_SIZE {
+            for [a, b, c] in [
+                [idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)],
+                [idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)],
+            ] {
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}").unwrap();
+            }
+        }
+    }
+    obj
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join("rasterboy_bench_obj_loading");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("grid.obj");
+    fs::write(&path, grid_obj()).unwrap();
+
+    // one untimed load to warm up the file cache
+    let mesh = Mesh::from_obj_file(&path).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..NUM_LOADS {
+        std::hint::black_box(Mesh::from_obj_file(&path).unwrap());
+    }
+    let per_load = start.elapsed() / NUM_LOADS;
+    println!(
+        "{} triangles, {} MB: {:?} per load",
+        mesh.face_indicies.len(),
+        fs::metadata(&path).unwrap().len() / 1_000_000,
+        per_load
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,40 @@
+use crate::image::*;
+use crate::math::*;
+use crate::rasterizer::RenderOptions;
+use crate::scene::*;
+use std::error::Error;
+use std::path::Path;
+
+/*
+ * Renders num_frames evenly spaced views of the scene while orbiting the camera around the vertical
+ * axis through the center of the scene's bounding box. Frames are written to output_dir as
+ * frame_0000.ppm, frame_0001.ppm, etc.
+ */
+pub fn render_turntable(
+    scene: &Scene,
+    num_frames: usize,
+    options: RenderOptions,
+    output_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let center = scene.bounding_box().center();
+    let image_width = scene.camera.canvas_width as usize;
+    let image_height = scene.camera.canvas_height as usize;
+
+    let mut frame_scene = scene.clone();
+    for frame in 0..num_frames {
+        // orbiting the camera is the same as spinning the world the other way around the center,
+        // so the authored camera framing is kept for every frame
+        let angle = -std::f32::consts::TAU * (frame as f32 / num_frames as f32);
+        frame_scene.camera.view_mat = scene.camera.view_mat
+            * Mat4::translation(center.x, center.y, center.z)
+            * Mat4::euler_angles(0.0, 0.0, angle)
+            * Mat4::translation(-center.x, -center.y, -center.z);
+
+        let mut output_image = Image::new(image_width, image_height);
+        let mut depth_buffer = vec![f32::MAX; image_width * image_height];
+        frame_scene.render(options, &mut output_image.data, &mut depth_buffer);
+        output_image.save_to_ppm(&output_dir.join(format!("frame_{:04}.ppm", frame)))?;
+    }
+
+    Ok(())
+}
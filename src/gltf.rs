@@ -0,0 +1,1039 @@
+/*
+ * A small glTF 2.0 importer, built with the gltf feature. It reads .gltf files (with external or
+ * base64 embedded buffers) and binary .glb files, but only static triangle meshes: positions,
+ * normals, the first set of texture coordinates, indices, a base color factor or PNG texture, and
+ * the node hierarchy's transforms. Skins, morph targets, animations, and extensions are ignored.
+ */
+use crate::image::*;
+use crate::math::*;
+use crate::mesh::*;
+use crate::png::decode_png;
+use crate::scene::*;
+use core::fmt;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+const GLB_MAGIC: u32 = 0x4654_6C67; // "glTF"
+const GLB_JSON_CHUNK: u32 = 0x4E4F_534A; // "JSON"
+const GLB_BIN_CHUNK: u32 = 0x004E_4942; // "BIN\0"
+
+// glTF has no resolution, so cameras get this many rows and a width from their aspect ratio
+const DEFAULT_CANVAS_HEIGHT: i32 = 480;
+
+#[derive(Debug)]
+pub struct GLTFLoadError {
+    pub msg: String,
+}
+impl Error for GLTFLoadError {}
+
+impl fmt::Display for GLTFLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed Loading glTF With Error {}", self.msg,)
+    }
+}
+
+fn gltf_error(msg: &str) -> GLTFLoadError {
+    GLTFLoadError {
+        msg: msg.to_string(),
+    }
+}
+
+/*
+ * Loads every mesh reachable from the default scene (or the first one) as a Model with its world
+ * transform. The first camera in the hierarchy becomes the scene camera, otherwise a 60 degree
+ * camera is framed around the geometry. Core glTF has no lights, so the scene gets one white
+ * light at the camera.
+ */
+pub fn load_gltf(path: &Path) -> Result<Scene, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let parent_dir = path.parent().unwrap_or(Path::new(""));
+
+    let (json_text, glb_bin) = if bytes.len() >= 4 && read_u32_le(&bytes[0..4]) == GLB_MAGIC {
+        split_glb(&bytes)?
+    } else {
+        (String::from_utf8(bytes)?, None)
+    };
+    let document = parse_json(&json_text)?;
+
+    let version = document
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(Json::as_str)
+        .ok_or(gltf_error("asset.version is missing"))?;
+    if !version.starts_with("2.") {
+        return Err(Box::new(GLTFLoadError {
+            msg: format!("glTF version {} is not supported", version),
+        }));
+    }
+
+    let buffers = load_buffers(&document, parent_dir, glb_bin)?;
+    let loader = Loader {
+        document: &document,
+        buffers,
+        parent_dir,
+    };
+
+    // walk the node hierarchy from the roots of the chosen scene
+    let nodes = document.get("nodes").map(Json::items).unwrap_or(&[]);
+    let roots: Vec<usize> = match document.get("scenes") {
+        Some(scenes) => {
+            let scene_idx = document.get("scene").and_then(Json::as_usize).unwrap_or(0);
+            let scene = scenes
+                .items()
+                .get(scene_idx)
+                .ok_or(gltf_error("default scene does not exist"))?;
+            loader.indices(scene, "nodes")?
+        }
+        // without scenes every node that is nobody's child is a root
+        None => {
+            let mut is_child = vec![false; nodes.len()];
+            for node in nodes {
+                for child in loader.indices(node, "children")? {
+                    if let Some(flag) = is_child.get_mut(child) {
+                        *flag = true;
+                    }
+                }
+            }
+            (0..nodes.len()).filter(|&idx| !is_child[idx]).collect()
+        }
+    };
+
+    let mut scene = Scene::default();
+    let mut camera: Option<Camera> = None;
+    let mut visited = vec![false; nodes.len()];
+    let mut stack: Vec<(usize, Mat4)> = roots
+        .into_iter()
+        .rev()
+        .map(|root| (root, Mat4::identity()))
+        .collect();
+    while let Some((node_idx, parent_transform)) = stack.pop() {
+        let node = nodes
+            .get(node_idx)
+            .ok_or(gltf_error("node index is out of range"))?;
+        if visited[node_idx] {
+            return Err(Box::new(gltf_error("node hierarchy is not a tree")));
+        }
+        visited[node_idx] = true;
+
+        let transform = parent_transform * node_transform(node)?;
+        if let Some(mesh_idx) = node.get("mesh").and_then(Json::as_usize) {
+            for mesh in loader.mesh(mesh_idx)? {
+                scene.models.push(Model {
+                    mesh,
+                    transform,
+                    color_override: None,
+                });
+            }
+        }
+        if let (None, Some(camera_idx)) = (camera, node.get("camera").and_then(Json::as_usize)) {
+            camera = Some(loader.camera(camera_idx, transform)?);
+        }
+        for child in loader.indices(node, "children")?.into_iter().rev() {
+            stack.push((child, transform));
+        }
+    }
+
+    scene.camera = match camera {
+        Some(camera) => camera,
+        None => {
+            let mut camera = Camera::new(
+                DEFAULT_CANVAS_HEIGHT * 4 / 3,
+                DEFAULT_CANVAS_HEIGHT,
+                60_f32.to_radians(),
+                0.1,
+                f32::INFINITY,
+            );
+            camera.frame(&scene.bounding_box());
+            camera
+        }
+    };
+
+    let camera_position = scene
+        .camera
+        .view_mat
+        .inverse()
+        .map(Mat4::translation_part)
+        .unwrap_or_default();
+    scene.lights.push(Light {
+        position: camera_position,
+        color: Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+        },
+        ambient_strength: 0.2,
+    });
+    Ok(scene)
+}
+
+// pulls the JSON text and the optional binary chunk out of a .glb container
+fn split_glb(bytes: &[u8]) -> Result<(String, Option<Vec<u8>>), GLTFLoadError> {
+    if bytes.len() < 12 {
+        return Err(gltf_error("GLB header is truncated"));
+    }
+    if read_u32_le(&bytes[4..8]) != 2 {
+        return Err(gltf_error("GLB container version is not 2"));
+    }
+    let total_length = (read_u32_le(&bytes[8..12]) as usize).min(bytes.len());
+
+    let mut json_text: Option<String> = None;
+    let mut bin: Option<Vec<u8>> = None;
+    let mut pos = 12;
+    while pos + 8 <= total_length {
+        let chunk_length = read_u32_le(&bytes[pos..pos + 4]) as usize;
+        let chunk_type = read_u32_le(&bytes[pos + 4..pos + 8]);
+        let data_start = pos + 8;
+        let data_end = data_start + chunk_length;
+        if data_end > total_length {
+            return Err(gltf_error("GLB chunk is truncated"));
+        }
+        let data = &bytes[data_start..data_end];
+
+        match chunk_type {
+            GLB_JSON_CHUNK if json_text.is_none() => {
+                json_text = Some(
+                    String::from_utf8(data.to_vec())
+                        .map_err(|_| gltf_error("GLB JSON chunk is not UTF-8"))?,
+                );
+            }
+            GLB_BIN_CHUNK if bin.is_none() => bin = Some(data.to_vec()),
+            _ => {}
+        }
+        pos = data_end;
+    }
+
+    let json_text = json_text.ok_or(gltf_error("GLB file did not contain a JSON chunk"))?;
+    Ok((json_text, bin))
+}
+
+fn load_buffers(
+    document: &Json,
+    parent_dir: &Path,
+    mut glb_bin: Option<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    let mut buffers = vec![];
+    for (buffer_idx, buffer) in document
+        .get("buffers")
+        .map(Json::items)
+        .unwrap_or(&[])
+        .iter()
+        .enumerate()
+    {
+        let data = match buffer.get("uri").and_then(Json::as_str) {
+            Some(uri) => load_uri(uri, parent_dir)?,
+            // only the first buffer of a .glb may leave out its uri and use the binary chunk
+            None if buffer_idx == 0 => glb_bin.take().ok_or(gltf_error(
+                "buffer has no uri and there is no GLB binary chunk",
+            ))?,
+            None => return Err(Box::new(gltf_error("buffer has no uri"))),
+        };
+
+        let byte_length = buffer
+            .get("byteLength")
+            .and_then(Json::as_usize)
+            .ok_or(gltf_error("buffer.byteLength is missing"))?;
+        if data.len() < byte_length {
+            return Err(Box::new(GLTFLoadError {
+                msg: format!(
+                    "buffer {} holds {} bytes but claims {}",
+                    buffer_idx,
+                    data.len(),
+                    byte_length
+                ),
+            }));
+        }
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+// reads base64 data uris in place and everything else as a path relative to the glTF file
+fn load_uri(uri: &str, parent_dir: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, payload) = data
+            .split_once(";base64,")
+            .ok_or(gltf_error("only base64 data uris are supported"))?;
+        return Ok(decode_base64(payload)?);
+    }
+    Ok(fs::read(parent_dir.join(decode_percent(uri)?))?)
+}
+
+// the glTF document plus everything it references, for looking up meshes and materials
+struct Loader<'a> {
+    document: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    parent_dir: &'a Path,
+}
+
+impl Loader<'_> {
+    fn element(&self, array: &str, idx: usize) -> Result<&Json, GLTFLoadError> {
+        self.document
+            .get(array)
+            .and_then(|items| items.items().get(idx))
+            .ok_or(GLTFLoadError {
+                msg: format!("{} index {} is out of range", array, idx),
+            })
+    }
+
+    // an optional array of indices like node.children
+    fn indices(&self, object: &Json, key: &str) -> Result<Vec<usize>, GLTFLoadError> {
+        object
+            .get(key)
+            .map(Json::items)
+            .unwrap_or(&[])
+            .iter()
+            .map(|idx| {
+                idx.as_usize().ok_or(GLTFLoadError {
+                    msg: format!("{} contains an invalid index", key),
+                })
+            })
+            .collect()
+    }
+
+    // one Mesh per primitive since each primitive can have its own material
+    fn mesh(&self, mesh_idx: usize) -> Result<Vec<Mesh>, Box<dyn Error>> {
+        let primitives = self
+            .element("meshes", mesh_idx)?
+            .get("primitives")
+            .map(Json::items)
+            .unwrap_or(&[]);
+
+        let mut meshes = vec![];
+        for primitive in primitives {
+            // mode 4 is TRIANGLES, and also the default
+            if primitive.get("mode").and_then(Json::as_usize).unwrap_or(4) != 4 {
+                return Err(Box::new(gltf_error(
+                    "only triangle primitives are supported",
+                )));
+            }
+            let attributes = primitive
+                .get("attributes")
+                .ok_or(gltf_error("primitive has no attributes"))?;
+            let attribute = |name: &str| attributes.get(name).and_then(Json::as_usize);
+
+            let position_accessor =
+                attribute("POSITION").ok_or(gltf_error("primitive has no POSITION attribute"))?;
+            let verticies = to_vectors(self.accessor(position_accessor, 3)?);
+            let vertex_normals = match attribute("NORMAL") {
+                Some(accessor) => to_vectors(self.accessor(accessor, 3)?),
+                None => vec![],
+            };
+            let vertex_texture_coords = match attribute("TEXCOORD_0") {
+                Some(accessor) => to_vectors(self.accessor(accessor, 2)?),
+                None => vec![],
+            };
+            // colors come as rgb or rgba, the alpha is dropped
+            let vertex_colors: Vec<Color> = match attribute("COLOR_0") {
+                Some(accessor) => to_vectors(
+                    self.accessor(accessor, 3)
+                        .or_else(|_| self.accessor(accessor, 4))?,
+                )
+                .into_iter()
+                .map(Vector3::to_color)
+                .collect(),
+                None => vec![],
+            };
+            if (!vertex_normals.is_empty() && vertex_normals.len() != verticies.len())
+                || (!vertex_texture_coords.is_empty()
+                    && vertex_texture_coords.len() != verticies.len())
+                || (!vertex_colors.is_empty() && vertex_colors.len() != verticies.len())
+            {
+                return Err(Box::new(gltf_error(
+                    "primitive attributes have different counts",
+                )));
+            }
+
+            let indices: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+                Some(accessor) => self
+                    .accessor(accessor, 1)?
+                    .into_iter()
+                    .map(|idx| idx[0] as usize)
+                    .collect(),
+                None => (0..verticies.len()).collect(),
+            };
+            if indices.len() % 3 != 0 || indices.iter().any(|&idx| idx >= verticies.len()) {
+                return Err(Box::new(gltf_error(
+                    "primitive indices do not form triangles of existing verticies",
+                )));
+            }
+
+            // every attribute is indexed like the verticies, and b/c are swapped since glTF
+            // front faces wind counter-clockwise where ours wind clockwise
+            let face_indicies = indices
+                .chunks_exact(3)
+                .map(|face| Triangle {
+                    a: face[0],
+                    b: face[2],
+                    c: face[1],
+                    a_normal: face[0],
+                    b_normal: face[2],
+                    c_normal: face[1],
+                    a_texture: face[0],
+                    b_texture: face[2],
+                    c_texture: face[1],
+                })
+                .collect();
+
+            let mut material = match primitive.get("material").and_then(Json::as_usize) {
+                Some(material_idx) => self.material(material_idx)?,
+                None => Material::default(),
+            };
+            if vertex_texture_coords.is_empty() {
+                material.diffuse_texture = None;
+            }
+
+            let mut mesh = Mesh {
+                verticies,
+                face_indicies,
+                vertex_normals,
+                vertex_texture_coords,
+                vertex_colors,
+                material,
+                edges: Vec::new(),
+                points: Vec::new(),
+                bvh: None,
+            };
+            if mesh.vertex_normals.is_empty() {
+                mesh.compute_smooth_normals();
+            }
+            mesh.build_bvh();
+            meshes.push(mesh);
+        }
+        Ok(meshes)
+    }
+
+    fn material(&self, material_idx: usize) -> Result<Material, Box<dyn Error>> {
+        let mut material = Material {
+            // glTF texture coordinates start at the top left of the image
+            uv_origin: UvOrigin::TopLeft,
+            ..Default::default()
+        };
+        let gltf_material = self.element("materials", material_idx)?;
+        if let Some(factor) = gltf_material.get("emissiveFactor") {
+            material.emissive_color = factor_color(factor, "emissiveFactor")?;
+        }
+        let Some(pbr) = gltf_material.get("pbrMetallicRoughness") else {
+            return Ok(material);
+        };
+
+        if let Some(factor) = pbr.get("baseColorFactor") {
+            material.diffuse_color = factor_color(factor, "baseColorFactor")?;
+        }
+
+        if let Some(texture_idx) = pbr
+            .get("baseColorTexture")
+            .and_then(|texture| texture.get("index"))
+            .and_then(Json::as_usize)
+        {
+            let image_idx = self
+                .element("textures", texture_idx)?
+                .get("source")
+                .and_then(Json::as_usize)
+                .ok_or(gltf_error("base color texture has no source image"))?;
+            material.diffuse_texture = Some(Arc::new(self.image(image_idx)?));
+        }
+        Ok(material)
+    }
+
+    fn image(&self, image_idx: usize) -> Result<Image, Box<dyn Error>> {
+        let image = self.element("images", image_idx)?;
+        if let Some(uri) = image.get("uri").and_then(Json::as_str) {
+            if uri.starts_with("data:") {
+                return Ok(decode_png(&load_uri(uri, self.parent_dir)?)?);
+            }
+            return Image::load_from_file(&self.parent_dir.join(decode_percent(uri)?));
+        }
+
+        let view_idx = image
+            .get("bufferView")
+            .and_then(Json::as_usize)
+            .ok_or(gltf_error("image has neither a uri nor a bufferView"))?;
+        if image.get("mimeType").and_then(Json::as_str) != Some("image/png") {
+            return Err(Box::new(gltf_error("only PNG images are supported")));
+        }
+        Ok(decode_png(self.buffer_view(view_idx)?.0)?)
+    }
+
+    // the bytes of a buffer view and its stride, if it has one
+    fn buffer_view(&self, view_idx: usize) -> Result<(&[u8], Option<usize>), GLTFLoadError> {
+        let view = self.element("bufferViews", view_idx)?;
+        let buffer_idx = view
+            .get("buffer")
+            .and_then(Json::as_usize)
+            .ok_or(gltf_error("bufferView.buffer is missing"))?;
+        let buffer = self
+            .buffers
+            .get(buffer_idx)
+            .ok_or(gltf_error("bufferView.buffer is out of range"))?;
+        let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let length = view
+            .get("byteLength")
+            .and_then(Json::as_usize)
+            .ok_or(gltf_error("bufferView.byteLength is missing"))?;
+        let bytes = buffer
+            .get(offset..offset + length)
+            .ok_or(gltf_error("bufferView runs past the end of its buffer"))?;
+        Ok((bytes, view.get("byteStride").and_then(Json::as_usize)))
+    }
+
+    /*
+     * Reads an accessor into one [f32; 4] per element, leaving unused components at zero. Errors
+     * unless the accessor has the expected number of components, integer components are read as
+     * is unless the accessor is normalized.
+     */
+    fn accessor(
+        &self,
+        accessor_idx: usize,
+        components: usize,
+    ) -> Result<Vec<[f32; 4]>, Box<dyn Error>> {
+        let accessor = self.element("accessors", accessor_idx)?;
+        if accessor.get("sparse").is_some() {
+            return Err(Box::new(gltf_error("sparse accessors are not supported")));
+        }
+
+        let accessor_components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(Box::new(gltf_error("accessor type is not supported"))),
+        };
+        if accessor_components != components {
+            return Err(Box::new(GLTFLoadError {
+                msg: format!(
+                    "accessor {} has {} components but {} were expected",
+                    accessor_idx, accessor_components, components
+                ),
+            }));
+        }
+
+        let count = accessor
+            .get("count")
+            .and_then(Json::as_usize)
+            .ok_or(gltf_error("accessor.count is missing"))?;
+        let component_type = accessor
+            .get("componentType")
+            .and_then(Json::as_usize)
+            .ok_or(gltf_error("accessor.componentType is missing"))?;
+        let normalized = accessor
+            .get("normalized")
+            .map(|normalized| *normalized == Json::Bool(true))
+            .unwrap_or(false);
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => {
+                return Err(Box::new(gltf_error(
+                    "accessor componentType is not supported",
+                )))
+            }
+        };
+
+        // an accessor without a buffer view is all zeros
+        let Some(view_idx) = accessor.get("bufferView").and_then(Json::as_usize) else {
+            return Ok(vec![[0.0; 4]; count]);
+        };
+        let (view_bytes, stride) = self.buffer_view(view_idx)?;
+        let offset = accessor
+            .get("byteOffset")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let element_size = component_size * components;
+        let stride = stride.unwrap_or(element_size);
+        if count > 0 && offset + stride * (count - 1) + element_size > view_bytes.len() {
+            return Err(Box::new(GLTFLoadError {
+                msg: format!(
+                    "accessor {} runs past the end of its bufferView",
+                    accessor_idx
+                ),
+            }));
+        }
+
+        let read_component = |bytes: &[u8]| -> f32 {
+            match (component_type, normalized) {
+                (5120, false) => bytes[0] as i8 as f32,
+                (5120, true) => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+                (5121, false) => bytes[0] as f32,
+                (5121, true) => bytes[0] as f32 / 255.0,
+                (5122, false) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                (5122, true) => {
+                    (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0)
+                }
+                (5123, false) => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                (5123, true) => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+                (5125, _) => read_u32_le(bytes) as f32,
+                _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            }
+        };
+
+        Ok((0..count)
+            .map(|element| {
+                let start = offset + element * stride;
+                let mut values = [0.0; 4];
+                for (component, value) in values.iter_mut().take(components).enumerate() {
+                    let component_start = start + component * component_size;
+                    *value = read_component(
+                        &view_bytes[component_start..component_start + component_size],
+                    );
+                }
+                values
+            })
+            .collect())
+    }
+
+    // a Camera for a perspective camera sitting at the given world transform
+    fn camera(&self, camera_idx: usize, transform: Mat4) -> Result<Camera, Box<dyn Error>> {
+        let perspective = self
+            .element("cameras", camera_idx)?
+            .get("perspective")
+            .ok_or(gltf_error("only perspective cameras are supported"))?;
+        let number = |key: &str| {
+            perspective
+                .get(key)
+                .and_then(Json::as_f64)
+                .map(|v| v as f32)
+        };
+
+        let fov = number("yfov").ok_or(gltf_error("camera.yfov is missing"))?;
+        let near = number("znear").ok_or(gltf_error("camera.znear is missing"))?;
+        let far = number("zfar").unwrap_or(f32::INFINITY);
+        if !(near > 0.0 && far > near) {
+            return Err(Box::new(gltf_error("camera needs 0 < znear < zfar")));
+        }
+        let aspect_ratio = number("aspectRatio").unwrap_or(4.0 / 3.0);
+        let canvas_width = ((DEFAULT_CANVAS_HEIGHT as f32 * aspect_ratio).round() as i32).max(1);
+
+        let mut camera = Camera::new(canvas_width, DEFAULT_CANVAS_HEIGHT, fov, near, far);
+        camera.view_mat = transform
+            .inverse()
+            .ok_or(gltf_error("camera transform is not invertible"))?;
+        Ok(camera)
+    }
+}
+
+// a node's local transform from either its matrix or its translation, rotation, and scale
+fn node_transform(node: &Json) -> Result<Mat4, GLTFLoadError> {
+    let numbers = |key: &str, len: usize| -> Result<Option<Vec<f32>>, GLTFLoadError> {
+        let Some(value) = node.get(key) else {
+            return Ok(None);
+        };
+        let values: Option<Vec<f32>> = value
+            .items()
+            .iter()
+            .map(|v| v.as_f64().map(|v| v as f32))
+            .collect();
+        match values {
+            Some(values) if values.len() == len => Ok(Some(values)),
+            _ => Err(GLTFLoadError {
+                msg: format!("node.{} should be {} numbers", key, len),
+            }),
+        }
+    };
+
+    // glTF matrices are column-major arrays
+    if let Some(matrix) = numbers("matrix", 16)? {
+        let mut ret = Mat4::identity();
+        for col in 0..4 {
+            for row in 0..4 {
+                *ret.mut_at(col, row) = matrix[col * 4 + row];
+            }
+        }
+        return Ok(ret);
+    }
+
+    let t = numbers("translation", 3)?.unwrap_or(vec![0.0, 0.0, 0.0]);
+    let r = numbers("rotation", 4)?.unwrap_or(vec![0.0, 0.0, 0.0, 1.0]);
+    let s = numbers("scale", 3)?.unwrap_or(vec![1.0, 1.0, 1.0]);
+    Ok(Mat4::translation(t[0], t[1], t[2])
+        * quaternion_to_mat4(r[0], r[1], r[2], r[3])
+        * Mat4::scale(s[0], s[1], s[2]))
+}
+
+fn quaternion_to_mat4(x: f32, y: f32, z: f32, w: f32) -> Mat4 {
+    let mut ret = Mat4::identity();
+    *ret.mut_at(0, 0) = 1.0 - 2.0 * (y * y + z * z);
+    *ret.mut_at(1, 0) = 2.0 * (x * y - z * w);
+    *ret.mut_at(2, 0) = 2.0 * (x * z + y * w);
+
+    *ret.mut_at(0, 1) = 2.0 * (x * y + z * w);
+    *ret.mut_at(1, 1) = 1.0 - 2.0 * (x * x + z * z);
+    *ret.mut_at(2, 1) = 2.0 * (y * z - x * w);
+
+    *ret.mut_at(0, 2) = 2.0 * (x * z - y * w);
+    *ret.mut_at(1, 2) = 2.0 * (y * z + x * w);
+    *ret.mut_at(2, 2) = 1.0 - 2.0 * (x * x + y * y);
+    ret
+}
+
+// the rgb of a 0 to 1 color factor, any alpha is ignored
+fn factor_color(factor: &Json, name: &str) -> Result<Color, GLTFLoadError> {
+    let channel = |idx: usize| -> Result<u8, GLTFLoadError> {
+        let value = factor
+            .items()
+            .get(idx)
+            .and_then(Json::as_f64)
+            .ok_or(GLTFLoadError {
+                msg: format!("{} is not a color", name),
+            })?;
+        Ok((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+    };
+    Ok(Color {
+        r: channel(0)?,
+        g: channel(1)?,
+        b: channel(2)?,
+    })
+}
+
+fn to_vectors(values: Vec<[f32; 4]>) -> Vec<Vector3> {
+    values
+        .into_iter()
+        .map(|v| Vector3 {
+            x: v[0],
+            y: v[1],
+            z: v[2],
+        })
+        .collect()
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, GLTFLoadError> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    for byte in text.bytes().take_while(|&byte| byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(gltf_error("data uri is not valid base64")),
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// uris may escape characters like spaces as %20
+fn decode_percent(uri: &str) -> Result<String, GLTFLoadError> {
+    let bytes = uri.as_bytes();
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'%' {
+            let hex = uri
+                .get(pos + 1..pos + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(gltf_error("uri has an invalid percent escape"))?;
+            out.push(hex);
+            pos += 3;
+        } else {
+            out.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| gltf_error("uri is not UTF-8"))
+}
+
+// a small JSON parser, just enough of the format for reading glTF files
+
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // the elements of an array, or nothing for any other value
+    fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => {
+                Some(*number as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+fn parse_json(text: &str) -> Result<Json, GLTFLoadError> {
+    let mut parser = JsonParser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(parser.error("trailing characters after the document"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, msg: &str) -> GLTFLoadError {
+        GLTFLoadError {
+            msg: format!("invalid JSON at byte {}, {}", self.pos, msg),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), GLTFLoadError> {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<Json, GLTFLoadError> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b't') => self.parse_literal("true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Json::Null),
+            Some(_) => self.parse_number(),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, GLTFLoadError> {
+        if !self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            return Err(self.error("unknown literal"));
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, GLTFLoadError> {
+        let start = self.pos;
+        while self.pos < self.bytes.len()
+            && matches!(
+                self.bytes[self.pos],
+                b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'
+            )
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|number| number.parse::<f64>().ok())
+            .map(Json::Number)
+            .ok_or(self.error("invalid number"))
+    }
+
+    fn parse_string(&mut self) -> Result<String, GLTFLoadError> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => return Ok(out),
+                b'\\' => {
+                    let escape = *self
+                        .bytes
+                        .get(self.pos)
+                        .ok_or(self.error("unterminated escape"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => {
+                            let mut code = self.parse_hex4()?;
+                            // a high surrogate is followed by an escaped low surrogate
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.bytes[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xD800) << 10)
+                                    + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        }
+                        _ => return Err(self.error("unknown escape")),
+                    }
+                }
+                _ => {
+                    // copy the rest of a multi-byte character along with its first byte
+                    let start = self.pos - 1;
+                    while self.pos < self.bytes.len() && (self.bytes[self.pos] & 0xC0) == 0x80 {
+                        self.pos += 1;
+                    }
+                    out.push_str(
+                        std::str::from_utf8(&self.bytes[start..self.pos])
+                            .map_err(|_| self.error("string is not UTF-8"))?,
+                    );
+                }
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, GLTFLoadError> {
+        let code = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4).unwrap_or(&[]))
+            .ok()
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or(self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn parse_array(&mut self) -> Result<Json, GLTFLoadError> {
+        self.expect(b'[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, GLTFLoadError> {
+        self.expect(b'{')?;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.expect(b':')?;
+            members.push((name, self.parse_value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_json() {
+        let document =
+            parse_json(r#" { "a": [1, -2.5e1, true, null], "b": { "c": "x\"é😀" } } "#).unwrap();
+        assert_eq!(
+            document.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(
+            document
+                .get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Json::as_str),
+            Some("x\"\u{e9}\u{1F600}")
+        );
+        assert_eq!(document.get("a").unwrap().items()[0].as_usize(), Some(1));
+        assert_eq!(document.get("a").unwrap().items()[1].as_usize(), None);
+
+        assert!(parse_json("{\"a\": 1,}").is_err());
+        assert!(parse_json("[1 2]").is_err());
+        assert!(parse_json("{} x").is_err());
+        assert!(parse_json("\"open").is_err());
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert!(decode_base64("TW!u").is_err());
+        assert_eq!(decode_percent("my%20mesh.bin").unwrap(), "my mesh.bin");
+    }
+}
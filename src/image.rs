@@ -1,10 +1,12 @@
 use crate::math::*;
+use crate::png::*;
 use core::fmt;
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Image {
@@ -13,15 +15,218 @@ pub struct Image {
     pub height: usize,
 }
 
+// a chain of successively half-sized copies of an image, level 0 being the original
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mipmap {
+    pub levels: Vec<Image>,
+}
+
+/*
+ * Holds on to the mipmap last built by Mipmap::cached along with the texture it was built from, so
+ * drawing the same texture again doesn't rebuild it (or expand a PalettedImage) every time. Clones
+ * share what's cached, and caches always compare equal since they don't change what's drawn.
+ */
+#[derive(Default, Clone)]
+pub struct MipmapCache {
+    cached: Arc<Mutex<Option<CachedMipmap>>>,
+}
+
+// a texture and the mipmap built from it
+type CachedMipmap = (Arc<dyn Texture>, Arc<Mipmap>);
+
+impl fmt::Debug for MipmapCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MipmapCache").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for MipmapCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+// how a texture is sampled between texel centers
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/*
+ * What a texture gives for coordinates outside of 0 to 1. Clamp repeats the edge texels, Repeat
+ * tiles the texture, and Mirror tiles it flipping every other copy so the tiles meet seamlessly.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl WrapMode {
+    // a texture coordinate brought into 0 to 1
+    pub fn wrap_coordinate(self, t: f32) -> f32 {
+        match self {
+            WrapMode::Clamp => t.clamp(0.0, 1.0),
+            WrapMode::Repeat => t - t.floor(),
+            WrapMode::Mirror => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+
+    // a texel index that may be past either edge brought onto a row or column of size texels
+    pub fn wrap_index(self, idx: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Clamp => idx.clamp(0, size - 1),
+            WrapMode::Repeat => idx.rem_euclid(size),
+            WrapMode::Mirror => {
+                let idx = idx.rem_euclid(2 * size);
+                if idx >= size {
+                    2 * size - 1 - idx
+                } else {
+                    idx
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+/*
+ * Which corner of a texture uv (0, 0) refers to. OBJ files and most modelling tools put it at the
+ * bottom left, while images (and formats like glTF) count rows from the top.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum UvOrigin {
+    #[default]
+    BottomLeft,
+    TopLeft,
+}
+
+impl UvOrigin {
+    // converts a v coordinate with this origin into the bottom left one the samplers expect
+    pub fn to_bottom_left(self, v: f32) -> f32 {
+        match self {
+            UvOrigin::BottomLeft => v,
+            UvOrigin::TopLeft => 1.0 - v,
+        }
+    }
+}
+
+// the ways loading a PPM can fail, the strings say what exactly was wrong
+#[derive(Debug)]
+pub enum PpmError {
+    NotP3,
+    BadHeader(String),
+    BadDimensions(String),
+    ValueCountMismatch(String),
+    BadValue(String),
+    Io(std::io::Error),
+}
+
+impl Error for PpmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PpmError::Io(why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PpmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            PpmError::NotP3 => "PPM File was not in P3 Format".to_string(),
+            PpmError::BadHeader(msg)
+            | PpmError::BadDimensions(msg)
+            | PpmError::ValueCountMismatch(msg)
+            | PpmError::BadValue(msg) => msg.clone(),
+            PpmError::Io(why) => why.to_string(),
+        };
+        write!(f, "Failed Loading PPM Image With Error {}", msg)
+    }
+}
+
+impl From<std::io::Error> for PpmError {
+    fn from(why: std::io::Error) -> PpmError {
+        PpmError::Io(why)
+    }
+}
+
 #[derive(Debug)]
-pub struct PPMLoadError {
+pub struct ImageError {
     pub msg: String,
 }
-impl Error for PPMLoadError {}
+impl Error for ImageError {}
 
-impl fmt::Display for PPMLoadError {
+impl fmt::Display for ImageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Failed Loading PPM Image With Error {}", self.msg,)
+        write!(f, "Image operation failed with error {}", self.msg,)
+    }
+}
+
+// pixels whose neighborhood spans less luma than either threshold aren't treated as edges
+const FXAA_EDGE_THRESHOLD: f32 = 0.125;
+const FXAA_EDGE_THRESHOLD_MIN: f32 = 0.0312;
+
+/*
+ * Image::apply_fxaa over a width by height row major buffer. Only pixels inside of region
+ * (x0, y0, x1, y1) are written, but neighbors outside of it are still read.
+ */
+pub(crate) fn fxaa(
+    data: &mut [Color],
+    width: usize,
+    height: usize,
+    region: (usize, usize, usize, usize),
+) {
+    let source = data.to_vec();
+    let luma = |x: usize, y: usize| source[y * width + x].luminance() / 255.0;
+    let (x0, y0, x1, y1) = region;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            let middle = luma(x, y);
+            let (north, south, west, east) =
+                (luma(x, up), luma(x, down), luma(left, y), luma(right, y));
+            let lowest = middle.min(north).min(south).min(west).min(east);
+            let highest = middle.max(north).max(south).max(west).max(east);
+            let range = highest - lowest;
+            if range < FXAA_EDGE_THRESHOLD_MIN.max(highest * FXAA_EDGE_THRESHOLD) {
+                continue;
+            }
+
+            // luma changing more from row to row than from column to column means a horizontal
+            // edge, which is blended with the pixel above or below it (whichever differs most)
+            let horizontal =
+                (north + south - 2.0 * middle).abs() >= (west + east - 2.0 * middle).abs();
+            let (a, b, luma_a, luma_b) = if horizontal {
+                ((x, up), (x, down), north, south)
+            } else {
+                ((left, y), (right, y), west, east)
+            };
+            let (nx, ny) = if (luma_a - middle).abs() >= (luma_b - middle).abs() {
+                a
+            } else {
+                b
+            };
+
+            // how far the pixel is from the average of its neighbors, smoothstepped
+            let contrast =
+                (((north + south + west + east) * 0.25 - middle).abs() / range).clamp(0.0, 1.0);
+            let blend = contrast * contrast * (3.0 - 2.0 * contrast) * 0.75;
+            data[y * width + x] = source[y * width + x].blend(source[ny * width + nx], blend * 0.5);
+        }
     }
 }
 
@@ -34,130 +239,911 @@ impl Image {
         }
     }
 
-    pub fn load_ppm(path: &Path) -> Result<Image, Box<dyn Error>> {
+    pub fn solid(width: usize, height: usize, color: Color) -> Image {
+        Image {
+            data: vec![color; width * height],
+            width,
+            height,
+        }
+    }
+
+    // tile by tile squares alternating between color_a (top left) and color_b, handy for checking
+    // uvs
+    pub fn checkerboard(
+        width: usize,
+        height: usize,
+        tile: usize,
+        color_a: Color,
+        color_b: Color,
+    ) -> Image {
+        let tile = tile.max(1);
+        let mut ret = Image::new(width, height);
+        for (x, y, pixel) in ret.pixels_mut() {
+            *pixel = if (x / tile + y / tile) % 2 == 0 {
+                color_a
+            } else {
+                color_b
+            };
+        }
+        ret
+    }
+
+    pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> Result<Image, ImageError> {
+        if x.checked_add(w).is_none_or(|end| end > self.width)
+            || y.checked_add(h).is_none_or(|end| end > self.height)
+        {
+            return Err(ImageError {
+                msg: format!(
+                    "crop region {}x{} at ({}, {}) exceeds the {}x{} image",
+                    w, h, x, y, self.width, self.height
+                ),
+            });
+        }
+
+        let mut ret = Image::new(w, h);
+        for row in 0..h {
+            let src_start = ((y + row) * self.width) + x;
+            ret.data[(row * w)..((row + 1) * w)]
+                .copy_from_slice(&self.data[src_start..(src_start + w)]);
+        }
+        Ok(ret)
+    }
+
+    /*
+     * Merges two renders of the same size by keeping whichever pixel is nearer (the smaller depth)
+     * along with its depth. Ties go to self.
+     */
+    pub fn composite_depth(
+        &self,
+        depths: &[f32],
+        other: &Image,
+        other_depths: &[f32],
+    ) -> Result<(Image, Vec<f32>), ImageError> {
+        let num_pixels = self.data.len();
+        if self.width != other.width
+            || self.height != other.height
+            || depths.len() != num_pixels
+            || other_depths.len() != num_pixels
+        {
+            return Err(ImageError {
+                msg: format!(
+                    "cannot composite a {}x{} render with {} depths onto a {}x{} render with {} depths",
+                    other.width,
+                    other.height,
+                    other_depths.len(),
+                    self.width,
+                    self.height,
+                    depths.len()
+                ),
+            });
+        }
+
+        let mut ret = Image::new(self.width, self.height);
+        let mut ret_depths = Vec::with_capacity(num_pixels);
+        for idx in 0..num_pixels {
+            if other_depths[idx] < depths[idx] {
+                ret.data[idx] = other.data[idx];
+                ret_depths.push(other_depths[idx]);
+            } else {
+                ret.data[idx] = self.data[idx];
+                ret_depths.push(depths[idx]);
+            }
+        }
+        Ok((ret, ret_depths))
+    }
+
+    /*
+     * Composites top over self with the usual over operator, top * alpha + self * (1 - alpha).
+     * Images don't carry an alpha channel so the one alpha, clamped to [0, 1], covers every pixel.
+     */
+    pub fn blend_over(&mut self, top: &Image, alpha: f32) -> Result<(), ImageError> {
+        if self.width != top.width || self.height != top.height {
+            return Err(ImageError {
+                msg: format!(
+                    "cannot blend a {}x{} layer over a {}x{} image",
+                    top.width, top.height, self.width, self.height
+                ),
+            });
+        }
+
+        let alpha = alpha.clamp(0.0, 1.0);
+        for (bottom, top) in self.data.iter_mut().zip(top.data.iter()) {
+            *bottom = bottom.blend(*top, alpha);
+        }
+        Ok(())
+    }
+
+    /*
+     * Mean squared error between the two images over every channel of every pixel, in 0-255
+     * units. The images must be the same size.
+     */
+    pub fn mse(&self, other: &Image) -> Result<f32, ImageError> {
+        if self.width != other.width || self.height != other.height {
+            return Err(ImageError {
+                msg: format!(
+                    "cannot compare a {}x{} image to a {}x{} image",
+                    self.width, self.height, other.width, other.height
+                ),
+            });
+        }
+        if self.data.is_empty() {
+            return Ok(0.0);
+        }
+
+        let squared_error: f64 = self
+            .data
+            .iter()
+            .zip(other.data.iter())
+            .flat_map(|(a, b)| [(a.r, b.r), (a.g, b.g), (a.b, b.b)])
+            .map(|(a, b)| (a as f64 - b as f64).powi(2))
+            .sum();
+        Ok((squared_error / (self.data.len() * 3) as f64) as f32)
+    }
+
+    /*
+     * Peak signal to noise ratio against other in decibels, higher is closer and identical
+     * images are infinitely close. The images must be the same size.
+     */
+    pub fn psnr(&self, other: &Image) -> Result<f32, ImageError> {
+        Ok(10.0 * (255.0 * 255.0 / self.mse(other)?).log10())
+    }
+
+    /*
+     * Box blur over a (2 * radius + 1) square, done as a horizontal then a vertical pass so each
+     * pixel costs O(radius) rather than O(radius^2). Samples past the edge are clamped back onto
+     * the image.
+     */
+    pub fn blur(&self, radius: usize) -> Image {
+        let horizontal = self.box_blur_pass(radius, true);
+        horizontal.box_blur_pass(radius, false)
+    }
+
+    fn box_blur_pass(&self, radius: usize, horizontal: bool) -> Image {
+        let mut ret = Image::new(self.width, self.height);
+        let radius = radius as isize;
+        let weight = 1.0 / (2 * radius + 1) as f32;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = [0.0_f32; 3];
+                for offset in -radius..=radius {
+                    let (sx, sy) = if horizontal {
+                        (
+                            (x as isize + offset).clamp(0, self.width as isize - 1) as usize,
+                            y,
+                        )
+                    } else {
+                        (
+                            x,
+                            (y as isize + offset).clamp(0, self.height as isize - 1) as usize,
+                        )
+                    };
+                    let sample = self.data[(sy * self.width) + sx];
+                    sum[0] += sample.r as f32;
+                    sum[1] += sample.g as f32;
+                    sum[2] += sample.b as f32;
+                }
+                ret.data[(y * self.width) + x] = Color {
+                    r: (sum[0] * weight).round() as u8,
+                    g: (sum[1] * weight).round() as u8,
+                    b: (sum[2] * weight).round() as u8,
+                };
+            }
+        }
+        ret
+    }
+
+    /*
+     * A cheap stand in for supersampling in the spirit of FXAA. Pixels whose luma stands out
+     * sharply from their neighbors are taken to be on an edge and blended toward the neighbor
+     * across it, more so the more they stand out. Flat regions are left untouched. It only sees
+     * colors, so sharp texture detail gets softened along with the geometry's edges.
+     */
+    pub fn apply_fxaa(&mut self) {
+        fxaa(
+            &mut self.data,
+            self.width,
+            self.height,
+            (0, 0, self.width, self.height),
+        );
+    }
+
+    pub fn to_grayscale(&self) -> Image {
+        let mut ret = Image::new(self.width, self.height);
+        for (gray, pixel) in ret.data.iter_mut().zip(self.data.iter()) {
+            let luma = pixel.luminance().round().clamp(0.0, 255.0) as u8;
+            *gray = Color {
+                r: luma,
+                g: luma,
+                b: luma,
+            };
+        }
+        ret
+    }
+
+    // the pixel at (x, y), or None outside of the image
+    pub fn get(&self, x: usize, y: usize) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.data[y * self.width + x])
+    }
+
+    // writes the pixel at (x, y), returning false and leaving the image alone when out of bounds
+    pub fn set(&mut self, x: usize, y: usize, color: Color) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        self.data[y * self.width + x] = color;
+        true
+    }
+
+    // every pixel with its (x, y) coordinate, row by row from the top left
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, &Color)> {
+        let width = self.width;
+        self.data
+            .iter()
+            .enumerate()
+            .map(move |(idx, pixel)| (idx % width, idx / width, pixel))
+    }
+
+    pub fn pixels_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut Color)> {
+        let width = self.width;
+        self.data
+            .iter_mut()
+            .enumerate()
+            .map(move |(idx, pixel)| (idx % width, idx / width, pixel))
+    }
+
+    // the pixels as tightly packed row-major RGB8, width * height * 3 bytes
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b])
+            .collect()
+    }
+
+    // the inverse of to_rgb_bytes, errors unless there are exactly width * height * 3 bytes
+    pub fn from_rgb_bytes(bytes: &[u8], width: usize, height: usize) -> Result<Image, ImageError> {
+        if bytes.len() != width * height * 3 {
+            return Err(ImageError {
+                msg: format!(
+                    "{} bytes cannot be a {}x{} RGB image, expected {}",
+                    bytes.len(),
+                    width,
+                    height,
+                    width * height * 3
+                ),
+            });
+        }
+        Ok(Image {
+            data: bytes
+                .chunks_exact(3)
+                .map(|rgb| Color {
+                    r: rgb[0],
+                    g: rgb[1],
+                    b: rgb[2],
+                })
+                .collect(),
+            width,
+            height,
+        })
+    }
+
+    // picks the decoder from the file extension
+    pub fn load_from_file(path: &Path) -> Result<Image, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ppm") => Ok(Image::load_ppm(path)?),
+            Some("png") => Image::load_png(path),
+            _ => Err(Box::new(ImageError {
+                msg: format!(
+                    "{} is not a supported image format (expected .ppm or .png)",
+                    path.display()
+                ),
+            })),
+        }
+    }
+
+    pub fn load_png(path: &Path) -> Result<Image, Box<dyn Error>> {
+        let bytes = std::fs::read(path)?;
+        Ok(decode_png(&bytes)?)
+    }
+
+    pub fn save_to_png(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut output_file = File::create(path)?;
+        output_file.write_all(&encode_png(self))?;
+        Ok(())
+    }
+
+    // values are rescaled from the file's max value to 0-255, use Image16 to keep them exactly
+    pub fn load_ppm(path: &Path) -> Result<Image, PpmError> {
+        Ok(Image16::load_ppm(path)?.to_image())
+    }
+
+    pub fn save_to_ppm(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let output_file = File::create(path)?;
+        self.write_ppm(&mut BufWriter::new(output_file))
+    }
+
+    // writes the image as a plain text PPM to anything writable, e.g. stdout
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let ppm_header = format!("P3\n{} {}\n255\n", self.width, self.height);
+        writer.write_all(ppm_header.as_bytes())?;
+
+        let samples = self
+            .data
+            .iter()
+            .flat_map(|pixel| [pixel.r as u16, pixel.g as u16, pixel.b as u16]);
+        writer.write_all(wrap_ppm_samples(samples).as_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn save_to_ppm_binary(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let output_file = File::create(path)?;
+        self.write_ppm_binary(&mut BufWriter::new(output_file))
+    }
+
+    // writes the image as a binary (P6) PPM, a third the size of the plain text one
+    pub fn write_ppm_binary<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let ppm_header = format!("P6\n{} {}\n255\n", self.width, self.height);
+        writer.write_all(ppm_header.as_bytes())?;
+        writer.write_all(&self.to_rgb_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/*
+ * Anything the rasterizer can pull texels from, so materials can hold more compact
+ * representations than a plain Image. Implementors only need to give their size and a way to
+ * look up a texel, the filtered sampling is built on top of that. Textures are compared texel by
+ * texel.
+ */
+pub trait Texture: fmt::Debug + Send + Sync {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    // the color at column x and row y, counted from the top left
+    fn texel(&self, x: usize, y: usize) -> Color;
+
+    fn sample(&self, u: f32, v: f32, filter: Filter, wrap: WrapMode) -> Color {
+        match filter {
+            Filter::Nearest => self.sample_nearest_neighbor(u, v, wrap),
+            Filter::Bilinear => self.sample_bilinear(u, v, wrap),
+        }
+    }
+
+    fn sample_bilinear(&self, u: f32, v: f32, wrap: WrapMode) -> Color {
+        let v = 1.0 - v;
+
+        // move into texel space where texel centers sit on whole numbers
+        let x = u * self.width() as f32 - 0.5;
+        let y = v * self.height() as f32 - 0.5;
+        let x_weight = x - x.floor();
+        let y_weight = y - y.floor();
+
+        // neighbors past the edge of the image are wrapped back onto it, so repeating textures
+        // blend across the seam
+        let wrap_x = |idx: f32| wrap.wrap_index(idx as i64, self.width());
+        let wrap_y = |idx: f32| wrap.wrap_index(idx as i64, self.height());
+        let x_low_idx = wrap_x(x.floor());
+        let x_high_idx = wrap_x(x.floor() + 1.0);
+        let y_low_idx = wrap_y(y.floor());
+        let y_high_idx = wrap_y(y.floor() + 1.0);
+
+        let q11 = self.texel(x_low_idx, y_low_idx).to_vector3();
+        let q21 = self.texel(x_high_idx, y_low_idx).to_vector3();
+        let q12 = self.texel(x_low_idx, y_high_idx).to_vector3();
+        let q22 = self.texel(x_high_idx, y_high_idx).to_vector3();
+
+        let low_row = q11 * (1.0 - x_weight) + q21 * x_weight;
+        let high_row = q12 * (1.0 - x_weight) + q22 * x_weight;
+        (low_row * (1.0 - y_weight) + high_row * y_weight).to_color()
+    }
+
+    fn sample_nearest_neighbor(&self, u: f32, v: f32, wrap: WrapMode) -> Color {
+        let max_x = self.width() - 1;
+        let max_y = self.height() - 1;
+        let u = wrap.wrap_coordinate(u);
+        let v = 1.0 - wrap.wrap_coordinate(v);
+
+        let nearest_x = ((u * max_x as f32).round() as usize).clamp(0, max_x);
+        let nearest_y = ((v * max_y as f32).round() as usize).clamp(0, max_y);
+        self.texel(nearest_x, nearest_y)
+    }
+
+    // expands the texture into a plain Image
+    fn to_image(&self) -> Image {
+        let mut ret = Image::new(self.width(), self.height());
+        for (x, y, color) in ret.pixels_mut() {
+            *color = self.texel(x, y);
+        }
+        ret
+    }
+}
+
+impl PartialEq for dyn Texture {
+    fn eq(&self, other: &Self) -> bool {
+        self.width() == other.width()
+            && self.height() == other.height()
+            && (0..self.height())
+                .all(|y| (0..self.width()).all(|x| self.texel(x, y) == other.texel(x, y)))
+    }
+}
+
+impl Texture for Image {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        self.data[(y * self.width) + x]
+    }
+}
+
+/*
+ * An image of at most 256 distinct colors stored as one byte per texel indexing into a palette,
+ * taking about a third of the memory of the same Image. Samples exactly like its expanded form.
+ * Trilinear filtering only expands it once, into the mipmap cached on its material.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PalettedImage {
+    pub indices: Vec<u8>,
+    pub palette: Vec<Color>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl PalettedImage {
+    // fails if image has more colors than a u8 can index
+    pub fn from_image(image: &Image) -> Result<PalettedImage, ImageError> {
+        let mut palette: Vec<Color> = Vec::new();
+        let mut indices = Vec::with_capacity(image.data.len());
+        for color in image.data.iter() {
+            let idx = match palette.iter().position(|entry| entry == color) {
+                Some(idx) => idx,
+                None => {
+                    palette.push(*color);
+                    palette.len() - 1
+                }
+            };
+            indices.push(u8::try_from(idx).map_err(|_| ImageError {
+                msg: format!(
+                    "a {}x{} image with more than 256 colors cannot be paletted",
+                    image.width, image.height
+                ),
+            })?);
+        }
+
+        Ok(PalettedImage {
+            indices,
+            palette,
+            width: image.width,
+            height: image.height,
+        })
+    }
+}
+
+impl Texture for PalettedImage {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        self.palette[self.indices[(y * self.width) + x] as usize]
+    }
+}
+
+/*
+ * A procedural tiles by tiles checkerboard over the whole uv square, alternating between color_a
+ * (top left) and color_b like Image::checkerboard with one texel per tile. Sampling is exact at
+ * any resolution, only mipmapping (RenderOptions::trilinear_filtering) bakes it down to its
+ * tiles.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Checker {
+    pub tiles: usize,
+    pub color_a: Color,
+    pub color_b: Color,
+}
+
+impl Texture for Checker {
+    fn width(&self) -> usize {
+        self.tiles.max(1)
+    }
+
+    fn height(&self) -> usize {
+        self.tiles.max(1)
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        if (x + y) % 2 == 0 {
+            self.color_a
+        } else {
+            self.color_b
+        }
+    }
+
+    // the tile edges stay sharp, so there's nothing for the filter to do
+    fn sample(&self, u: f32, v: f32, _filter: Filter, wrap: WrapMode) -> Color {
+        let tiles = self.width();
+        let tile = |t: f32| ((wrap.wrap_coordinate(t) * tiles as f32) as usize).min(tiles - 1);
+        self.texel(tile(u), tile(1.0 - v))
+    }
+}
+
+/*
+ * An image with up to 16 bits per channel, for exports like depth or normals that need more
+ * precision than Image. Channels go from 0 to max_value (at most 65535) as in a PPM file.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image16 {
+    pub data: Vec<Color16>,
+    pub width: usize,
+    pub height: usize,
+    pub max_value: u16,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Color16 {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+}
+
+impl Image16 {
+    pub fn new(width: usize, height: usize, max_value: u16) -> Image16 {
+        Image16 {
+            data: vec![Color16::default(); width * height],
+            width,
+            height,
+            max_value,
+        }
+    }
+
+    // rescales every channel from 0-max_value to 0-255
+    pub fn to_image(&self) -> Image {
+        let rescale = |value: u16| {
+            ((value as f32 / self.max_value as f32) * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+        Image {
+            data: self
+                .data
+                .iter()
+                .map(|pixel| Color {
+                    r: rescale(pixel.r),
+                    g: rescale(pixel.g),
+                    b: rescale(pixel.b),
+                })
+                .collect(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn load_ppm(path: &Path) -> Result<Image16, PpmError> {
         // load in file line by line
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         let mut lines = reader.lines();
+        let mut next_header_line = || -> Result<String, PpmError> {
+            Ok(lines.next().ok_or(PpmError::BadHeader(
+                "PPM file did not contain header".to_string(),
+            ))??)
+        };
 
         // parse header, assert P3
-        if lines.next().ok_or(Box::new(PPMLoadError {
-            msg: "PPM file did not contain header".to_string(),
-        }))??
-            != "P3"
-        {
-            return Err(Box::new(PPMLoadError {
-                msg: "PPM File was not in P3 Format".to_string(),
-            }));
+        if next_header_line()?.trim() != "P3" {
+            return Err(PpmError::NotP3);
         }
 
         // get width, height, max value from the header
-        let size_line: String = lines.next().ok_or(Box::new(PPMLoadError {
-            msg: "PPM file did not contain header".to_string(),
-        }))??;
+        let size_line = next_header_line()?;
+        let max_val_line = next_header_line()?;
         let split_size_line: Vec<&str> = size_line.split_whitespace().collect();
-        let max_val_line: String = lines.next().ok_or(Box::new(PPMLoadError {
-            msg: "PPM file did not contain header".to_string(),
-        }))??;
         if split_size_line.len() != 2 {
-            return Err(Box::new(PPMLoadError {
-                msg: "PPM File did not contain two numbers to define size in the header"
-                    .to_string(),
-            }));
+            return Err(PpmError::BadDimensions(
+                "PPM File did not contain two numbers to define size in the header".to_string(),
+            ));
         }
-
-        let width = split_size_line[0].parse::<usize>()?;
-        let height = split_size_line[1].parse::<usize>()?;
-        let max_value = max_val_line.trim().parse::<f32>()?;
+        let parse_dimension = |dimension: &str| {
+            dimension.parse::<usize>().map_err(|_| {
+                PpmError::BadDimensions(format!("PPM size {} is not a whole number", dimension))
+            })
+        };
+        let width = parse_dimension(split_size_line[0])?;
+        let height = parse_dimension(split_size_line[1])?;
+        let max_value = match max_val_line.trim().parse::<u16>() {
+            Ok(max_value) if max_value > 0 => max_value,
+            _ => {
+                return Err(PpmError::BadHeader(
+                    "PPM max value must be between 1 and 65535".to_string(),
+                ))
+            }
+        };
 
         // allocate the pixel buffer
-        let mut data = vec![Color::default(); width * height];
+        let mut data = vec![Color16::default(); width * height];
+        let mismatch = || {
+            PpmError::ValueCountMismatch(format!(
+                "PPM file did not contain exactly {} values for its {}x{} size",
+                width * height * 3,
+                width,
+                height
+            ))
+        };
 
-        // for all lines read and push data, we enforce that lines are multiples of three numbers
-        let mut idx: usize = 0;
+        // the values are one stream of whitespace separated numbers, lines (and so pixels) may
+        // break anywhere in it
+        let mut values = Vec::with_capacity(data.len() * 3);
         for maybe_line in lines {
             let line = maybe_line?;
-            let split_line: Vec<&str> = line.split_whitespace().collect();
-            if split_line.len() % 3 != 0 {
-                return Err(Box::new(PPMLoadError{msg: "the number of values in the PPM file is not a multiple of three (cannot create colors)".to_string()}));
-            }
-
-            for color_str in split_line.chunks(3) {
-                data[idx].r = ((color_str[0].parse::<f32>()? / max_value) * 255.0) as u8;
-                data[idx].g = ((color_str[1].parse::<f32>()? / max_value) * 255.0) as u8;
-                data[idx].b = ((color_str[2].parse::<f32>()? / max_value) * 255.0) as u8;
-                idx += 1;
+            for value_str in line.split_whitespace() {
+                if values.len() >= data.len() * 3 {
+                    return Err(mismatch());
+                }
+                match value_str.parse::<u16>() {
+                    Ok(value) if value <= max_value => values.push(value),
+                    _ => {
+                        return Err(PpmError::BadValue(format!(
+                            "PPM value {} is not between 0 and the max value {}",
+                            value_str, max_value
+                        )))
+                    }
+                }
             }
         }
+        if values.len() != data.len() * 3 {
+            return Err(mismatch());
+        }
+        for (pixel, channels) in data.iter_mut().zip(values.chunks(3)) {
+            *pixel = Color16 {
+                r: channels[0],
+                g: channels[1],
+                b: channels[2],
+            };
+        }
 
-        Ok(Image {
+        Ok(Image16 {
             data,
             width,
             height,
+            max_value,
         })
     }
 
     pub fn save_to_ppm(&self, path: &Path) -> Result<(), Box<dyn Error>> {
-        let mut output_str: String = String::default();
-        for pixel in self.data.iter() {
-            output_str.push_str(&format!("{} {} {}\n", pixel.r, pixel.g, pixel.b));
+        let output_file = File::create(path)?;
+        self.write_ppm(&mut BufWriter::new(output_file))
+    }
+
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let ppm_header = format!("P3\n{} {}\n{}\n", self.width, self.height, self.max_value);
+        writer.write_all(ppm_header.as_bytes())?;
+
+        let samples = self
+            .data
+            .iter()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b]);
+        writer.write_all(wrap_ppm_samples(samples).as_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+// the longest line a plain text PPM should have, as the format recommends
+const PPM_LINE_WIDTH: usize = 70;
+
+/*
+ * The samples of a plain text PPM separated by spaces and broken onto a new line wherever the next
+ * one would run past PPM_LINE_WIDTH. Pixels may be split across lines, which the format allows
+ * since only whitespace between the samples matters.
+ */
+fn wrap_ppm_samples(samples: impl Iterator<Item = u16>) -> String {
+    let mut output_str = String::default();
+    let mut line_len = 0;
+    for sample in samples {
+        let sample = sample.to_string();
+        if line_len > 0 && line_len + 1 + sample.len() > PPM_LINE_WIDTH {
+            output_str.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            output_str.push(' ');
+            line_len += 1;
         }
+        output_str.push_str(&sample);
+        line_len += sample.len();
+    }
+    if line_len > 0 {
+        output_str.push('\n');
+    }
+    output_str
+}
 
-        let mut output_file = File::create(path)?;
-        let ppm_header = format!("P3 {} {}\n255\n", self.width, self.height);
-        output_file.write_all(ppm_header.as_bytes())?;
+impl Mipmap {
+    pub fn new(texture: &dyn Texture) -> Mipmap {
+        let mut levels = vec![texture.to_image()];
 
-        let mut output_str: String = String::default();
-        for pixel in self.data.iter() {
-            output_str.push_str(&format!("{} {} {}\n", pixel.r, pixel.g, pixel.b));
+        while levels[levels.len() - 1].width > 1 || levels[levels.len() - 1].height > 1 {
+            let previous = &levels[levels.len() - 1];
+
+            // box filter every 2x2 block of the previous level into one texel, odd sized levels
+            // reuse their last row or column
+            let mut level = Image::new(
+                usize::max(previous.width / 2, 1),
+                usize::max(previous.height / 2, 1),
+            );
+            for y in 0..level.height {
+                for x in 0..level.width {
+                    let sum = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(dx, dy)| {
+                            let src_x = usize::min(x * 2 + dx, previous.width - 1);
+                            let src_y = usize::min(y * 2 + dy, previous.height - 1);
+                            previous.data[(src_y * previous.width) + src_x].to_vector3()
+                        })
+                        .fold(Vector3::default(), |acc, color| acc + color);
+                    level.data[(y * level.width) + x] = (sum * 0.25).to_color();
+                }
+            }
+            levels.push(level);
         }
 
-        // write to file and catch error
-        output_file.write_all(output_str.as_bytes())?;
+        Mipmap { levels }
+    }
 
-        Ok(())
+    // the mipmap of texture, built once and reused for as long as cache is handed the same texture
+    pub fn cached(texture: &Arc<dyn Texture>, cache: &MipmapCache) -> Arc<Mipmap> {
+        let mut cached = cache
+            .cached
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match &*cached {
+            Some((cached_texture, mipmap)) if Arc::ptr_eq(cached_texture, texture) => {
+                Arc::clone(mipmap)
+            }
+            _ => {
+                let mipmap = Arc::new(Mipmap::new(texture.as_ref()));
+                *cached = Some((Arc::clone(texture), Arc::clone(&mipmap)));
+                mipmap
+            }
+        }
     }
 
-    pub fn sample_bilinear(&self, u: f32, v: f32) -> Color {
-        let max_x = self.width - 1;
-        let max_y = self.height - 1;
-        let v = 1.0 - v;
+    /*
+     * Bilinearly samples the two levels surrounding lod and blends between them. An lod of 0.0
+     * samples the full resolution image, each whole step above that halves the resolution.
+     */
+    pub fn sample_trilinear(&self, u: f32, v: f32, lod: f32, wrap: WrapMode) -> Color {
+        let max_level = self.levels.len() - 1;
+        let lod = lod.clamp(0.0, max_level as f32);
+        let low_level = lod.floor() as usize;
+        let high_level = usize::min(low_level + 1, max_level);
+        let t = lod - low_level as f32;
 
-        let x_low_idx = ((u * max_x as f32).floor() as usize).clamp(0, max_x);
-        let x_high_idx = ((u * max_x as f32).ceil() as usize).clamp(0, max_x);
-        let y_low_idx = ((v * max_y as f32).floor() as usize).clamp(0, max_y);
-        let y_high_idx = ((v * max_y as f32).ceil() as usize).clamp(0, max_y);
-
-        // (note: amoussa) we need to add epsilon here to avoid a divide by zero in the case that
-        // one axis is not being interpolated
-        let x1 = (x_low_idx as f32 / max_x as f32) - f32::EPSILON;
-        let x2 = (x_high_idx as f32 / max_x as f32) + f32::EPSILON;
-        let y1 = (y_low_idx as f32 / max_y as f32) - f32::EPSILON;
-        let y2 = (y_high_idx as f32 / max_y as f32) + f32::EPSILON;
-
-        let q11 = self.data[(y_low_idx * self.width) + x_low_idx].to_vector3();
-        let q21 = self.data[(y_low_idx * self.width) + x_high_idx].to_vector3();
-        let q12 = self.data[(y_high_idx * self.width) + x_low_idx].to_vector3();
-        let q22 = self.data[(y_high_idx * self.width) + x_high_idx].to_vector3();
-
-        // (note: amoussa) these names are stupid
-        let range = (x2 - x1) * (y2 - y1);
-        let temp1 = (x2 - u) / range;
-        let temp2 = (u - x1) / range;
-        let temp3 = q11 * temp1 + q21 * temp2;
-        let temp4 = q12 * temp1 + q22 * temp2;
-
-        (temp3 * (y2 - v) + temp4 * (v - y1)).to_color()
-    }
-
-    #[allow(dead_code)]
-    pub fn sample_nearest_neighbor(&self, u: f32, v: f32) -> Color {
-        let max_x = self.width - 1;
-        let max_y = self.height - 1;
-        let v = 1.0 - v;
+        let low = self.levels[low_level]
+            .sample_bilinear(u, v, wrap)
+            .to_vector3();
+        let high = self.levels[high_level]
+            .sample_bilinear(u, v, wrap)
+            .to_vector3();
+        (low * (1.0 - t) + high * t).to_color()
+    }
+}
 
-        let nearest_x = ((u * max_x as f32).round() as usize).clamp(0, max_x);
-        let nearest_y = ((v * max_y as f32).round() as usize).clamp(0, max_y);
-        self.data[(nearest_y * self.width) + nearest_x]
+/*
+ * A raw dump of linear float RGB, for handing unclamped renders to external tone mapping. The
+ * layout is:
+ *   8 bytes  magic "RBHDRF32"
+ *   4 bytes  width as a little endian u32
+ *   4 bytes  height as a little endian u32
+ *   then width * height pixels, left to right and top to bottom, each as three little endian f32s
+ *   in r, g, b order
+ * Values are written bit for bit, so anything above 1.0, negative, or even NaN survives a round
+ * trip.
+ */
+const HDR_RAW_MAGIC: &[u8; 8] = b"RBHDRF32";
+
+pub fn save_hdr_raw(
+    path: &Path,
+    data: &[Vector3],
+    width: usize,
+    height: usize,
+) -> Result<(), Box<dyn Error>> {
+    let output_file = File::create(path)?;
+    write_hdr_raw(&mut BufWriter::new(output_file), data, width, height)
+}
+
+pub fn write_hdr_raw<W: Write>(
+    writer: &mut W,
+    data: &[Vector3],
+    width: usize,
+    height: usize,
+) -> Result<(), Box<dyn Error>> {
+    if data.len() != width * height {
+        return Err(Box::new(ImageError {
+            msg: format!(
+                "{} pixels do not make a {}x{} image",
+                data.len(),
+                width,
+                height
+            ),
+        }));
+    }
+    writer.write_all(HDR_RAW_MAGIC)?;
+    writer.write_all(&u32::try_from(width)?.to_le_bytes())?;
+    writer.write_all(&u32::try_from(height)?.to_le_bytes())?;
+    for pixel in data {
+        for channel in [pixel.x, pixel.y, pixel.z] {
+            writer.write_all(&channel.to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+// returns the pixels along with the width and height
+pub fn load_hdr_raw(path: &Path) -> Result<(Vec<Vector3>, usize, usize), Box<dyn Error>> {
+    let input_file = File::open(path)?;
+    read_hdr_raw(&mut BufReader::new(input_file))
+}
+
+pub fn read_hdr_raw<R: Read>(
+    reader: &mut R,
+) -> Result<(Vec<Vector3>, usize, usize), Box<dyn Error>> {
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header)?;
+    if &header[0..8] != HDR_RAW_MAGIC {
+        return Err(Box::new(ImageError {
+            msg: "not a raw HDR dump, the magic bytes are wrong".to_string(),
+        }));
     }
+    let width = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as usize;
+    let height = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if Some(bytes.len())
+        != width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(12))
+    {
+        return Err(Box::new(ImageError {
+            msg: format!(
+                "a {}x{} raw HDR dump needs 12 bytes for each pixel but has {} bytes of pixels",
+                width,
+                height,
+                bytes.len()
+            ),
+        }));
+    }
+
+    let channel = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let data = bytes
+        .chunks_exact(12)
+        .map(|pixel| Vector3 {
+            x: channel(&pixel[0..4]),
+            y: channel(&pixel[4..8]),
+            z: channel(&pixel[8..12]),
+        })
+        .collect();
+    Ok((data, width, height))
 }
@@ -1,6 +1,10 @@
+pub mod anim;
+#[cfg(feature = "gltf")]
+pub mod gltf;
 pub mod image;
 pub mod math;
 pub mod mesh;
+pub mod png;
 pub mod rasterizer;
 pub mod scene;
 
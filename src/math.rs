@@ -3,6 +3,8 @@
  * Matrices are stored in column-major order
  * The coordinate system is right handed with +Y as up
  */
+use core::fmt;
+use std::error::Error;
 use std::ops;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -22,6 +24,32 @@ pub struct Vector3 {
     pub z: f32,
 }
 
+// homogeneous coordinate, w is 1 for points and 0 for directions
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Vector4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+// axis aligned bounding box
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+/*
+ * The volume a view projection matrix sees, as the six planes from Mat4::frustum_planes. Built
+ * from projection * view * model the planes are in model space, so points and boxes can be tested
+ * without transforming them first.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4; 6],
+}
+
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct ScreenCoordinate {
     pub x: i32,
@@ -35,6 +63,18 @@ pub struct Color {
     pub b: u8,
 }
 
+#[derive(Debug)]
+pub struct LookAtError {
+    pub msg: String,
+}
+impl Error for LookAtError {}
+
+impl fmt::Display for LookAtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed building look at matrix with error {}", self.msg,)
+    }
+}
+
 #[allow(clippy::identity_op)]
 #[allow(clippy::erasing_op)]
 impl Mat4 {
@@ -94,7 +134,17 @@ impl Mat4 {
         ret
     }
 
+    /*
+     * Panics unless 0 < near_plane < far_plane, anything else would fill the matrix with NaNs or
+     * flip the depth range.
+     */
     pub fn perspective(aspect_ratio: f32, fov: f32, near_plane: f32, far_plane: f32) -> Mat4 {
+        assert!(
+            near_plane > 0.0 && far_plane > near_plane,
+            "perspective projection needs 0 < near ({}) < far ({})",
+            near_plane,
+            far_plane
+        );
         let mut ret = Mat4 { data: [0.0; 16] };
         let tangent = (fov / 2.0).tan();
 
@@ -106,6 +156,28 @@ impl Mat4 {
         ret
     }
 
+    /*
+     * The perspective matrix with its far plane pushed out to infinity, handy for skyboxes. Panics
+     * unless near_plane > 0.
+     */
+    pub fn perspective_infinite(aspect_ratio: f32, fov: f32, near_plane: f32) -> Mat4 {
+        assert!(
+            near_plane > 0.0,
+            "perspective projection needs 0 < near ({})",
+            near_plane
+        );
+        let mut ret = Mat4 { data: [0.0; 16] };
+        let tangent = (fov / 2.0).tan();
+
+        // the far plane terms of perspective() as far goes to infinity
+        *ret.mut_at(0, 0) = 1.0 / (aspect_ratio * tangent);
+        *ret.mut_at(1, 1) = 1.0 / tangent;
+        *ret.mut_at(2, 2) = -1.0;
+        *ret.mut_at(2, 3) = -1.0;
+        *ret.mut_at(3, 2) = -2.0 * near_plane;
+        ret
+    }
+
     #[allow(dead_code)]
     pub fn translation_part(self) -> Vector3 {
         Vector3 {
@@ -249,6 +321,74 @@ impl Mat4 {
         Some(ret)
     }
 
+    /*
+     * The six clip planes of a view projection matrix as (a, b, c, d) with ax + by + cz + d >= 0
+     * on the inside, in the order left, right, bottom, top, near, far. Each comes from adding or
+     * subtracting a row from the w row (Gribb and Hartmann). Normals are normalized, so plugging a
+     * point in gives its signed distance, except for the far plane of an infinite projection which
+     * is all zeros apart from a positive d and so never rejects anything.
+     */
+    pub fn frustum_planes(self) -> [Vector4; 6] {
+        let row = |i: usize| Vector4 {
+            x: *self.at(0, i),
+            y: *self.at(1, i),
+            z: *self.at(2, i),
+            w: *self.at(3, i),
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let combine = |a: Vector4, b: Vector4, sign: f32| {
+            let plane = Vector4 {
+                x: a.x + sign * b.x,
+                y: a.y + sign * b.y,
+                z: a.z + sign * b.z,
+                w: a.w + sign * b.w,
+            };
+            let length = plane.xyz().magnitude();
+            if length == 0.0 {
+                return plane;
+            }
+            Vector4 {
+                x: plane.x / length,
+                y: plane.y / length,
+                z: plane.z / length,
+                w: plane.w / length,
+            }
+        };
+        [
+            combine(w, x, 1.0),
+            combine(w, x, -1.0),
+            combine(w, y, 1.0),
+            combine(w, y, -1.0),
+            combine(w, z, 1.0),
+            combine(w, z, -1.0),
+        ]
+    }
+
+    /*
+     * Gram-Schmidt on the columns of the upper left 3x3, so a rotation that drifted from being
+     * multiplied over and over is a pure rotation again. The x column keeps its direction, y is
+     * straightened against it and z against both. Translation is kept, but any scale is lost since
+     * every column comes out unit length.
+     */
+    pub fn orthonormalize_rotation(&mut self) {
+        let column = |m: &Mat4, col: usize| Vector3 {
+            x: *m.at(col, 0),
+            y: *m.at(col, 1),
+            z: *m.at(col, 2),
+        };
+        let x = column(self, 0).normalized();
+        let y = column(self, 1);
+        let y = (y - x * Vector3::dot(x, y)).normalized();
+        let z = column(self, 2);
+        let z = (z - x * Vector3::dot(x, z) - y * Vector3::dot(y, z)).normalized();
+
+        for (col, basis) in [x, y, z].into_iter().enumerate() {
+            *self.mut_at(col, 0) = basis.x;
+            *self.mut_at(col, 1) = basis.y;
+            *self.mut_at(col, 2) = basis.z;
+        }
+    }
+
     pub fn transpose(self) -> Mat4 {
         let mut ret = Mat4::identity();
         for i in 0..4 {
@@ -259,11 +399,27 @@ impl Mat4 {
         ret
     }
 
-    pub fn look_at(eye: Vector3, center: Vector3, up: Vector3) -> Mat4 {
+    /*
+     * Errors instead of building a garbage matrix when there is no way to pick a basis, which is
+     * when eye and center are the same point (no forward direction) or up is zero or parallel to
+     * the forward direction (no sideways direction).
+     */
+    pub fn look_at(eye: Vector3, center: Vector3, up: Vector3) -> Result<Mat4, LookAtError> {
         let mut ret = Mat4::identity();
 
+        if center == eye {
+            return Err(LookAtError {
+                msg: format!("eye and center are both {:?}", eye),
+            });
+        }
         let f = (center - eye).normalized();
-        let s = Vector3::cross(f, up).normalized();
+        let side = Vector3::cross(f, up);
+        if side.magnitude() <= 1.0e-6 * up.magnitude() {
+            return Err(LookAtError {
+                msg: format!("up {:?} is parallel to the view direction {:?}", up, f),
+            });
+        }
+        let s = side.normalized();
         let u = Vector3::cross(s, f);
 
         *ret.mut_at(0, 0) = s.x;
@@ -282,7 +438,7 @@ impl Mat4 {
         *ret.mut_at(3, 1) = Vector3::dot(u, eye);
         *ret.mut_at(3, 2) = Vector3::dot(f, eye);
 
-        ret
+        Ok(ret)
     }
 }
 
@@ -292,6 +448,31 @@ impl Default for Mat4 {
     }
 }
 
+/*
+ * Prints the matrix as it reads on paper, one row per line with the columns right aligned. Values
+ * get 3 decimal places unless the formatter asks for a precision, e.g. {:.1}.
+ */
+impl fmt::Display for Mat4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        let cells: Vec<String> = (0..16)
+            .map(|idx| format!("{:.*}", precision, self.at(idx % 4, idx / 4)))
+            .collect();
+        let width = cells.iter().map(String::len).max().unwrap_or(0);
+        for (row, row_cells) in cells.chunks(4).enumerate() {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "[")?;
+            for cell in row_cells {
+                write!(f, " {:>width$}", cell)?;
+            }
+            write!(f, " ]")?;
+        }
+        Ok(())
+    }
+}
+
 impl Mat3 {
     pub fn at(&self, col: usize, row: usize) -> &f32 {
         &self.data[(row * 3) + col]
@@ -324,6 +505,29 @@ impl Vector3 {
         }
     }
 
+    /*
+     * Same as to_color, but before truncating each channel is offset by a threshold from a 4x4
+     * Bayer matrix picked by the pixel position (ordered dithering). Values between two levels
+     * come out as a pattern of both levels which averages to the original value.
+     */
+    pub fn to_color_dithered(self, x: i32, y: i32) -> Color {
+        const BAYER_4X4: [[f32; 4]; 4] = [
+            [0.0, 8.0, 2.0, 10.0],
+            [12.0, 4.0, 14.0, 6.0],
+            [3.0, 11.0, 1.0, 9.0],
+            [15.0, 7.0, 13.0, 5.0],
+        ];
+        let threshold =
+            (BAYER_4X4[y.rem_euclid(4) as usize][x.rem_euclid(4) as usize] + 0.5) / 16.0;
+        let quantize =
+            |channel: f32| (channel.clamp(0.0, 1.0) * 255.0 + threshold).min(255.0) as u8;
+        Color {
+            r: quantize(self.x),
+            g: quantize(self.y),
+            b: quantize(self.z),
+        }
+    }
+
     pub fn magnitude(self) -> f32 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
@@ -352,6 +556,188 @@ impl Vector3 {
     pub fn dot(a: Vector3, b: Vector3) -> f32 {
         a.x * b.x + a.y * b.y + a.z * b.z
     }
+
+    pub fn distance(a: Vector3, b: Vector3) -> f32 {
+        (a - b).magnitude()
+    }
+
+    // whether every component is within epsilon of other's, for comparing results of float math
+    pub fn approx_eq(self, other: Vector3, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+    }
+
+    // cheaper than distance since it skips the sqrt, useful when only comparing distances
+    pub fn distance_squared(a: Vector3, b: Vector3) -> f32 {
+        let diff = a - b;
+        Vector3::dot(diff, diff)
+    }
+
+    pub fn min(a: Vector3, b: Vector3) -> Vector3 {
+        Vector3 {
+            x: f32::min(a.x, b.x),
+            y: f32::min(a.y, b.y),
+            z: f32::min(a.z, b.z),
+        }
+    }
+
+    pub fn max(a: Vector3, b: Vector3) -> Vector3 {
+        Vector3 {
+            x: f32::max(a.x, b.x),
+            y: f32::max(a.y, b.y),
+            z: f32::max(a.z, b.z),
+        }
+    }
+
+    // clamps each component on its own, like f32::clamp this panics if any lo component is above hi
+    pub fn clamp(self, lo: Vector3, hi: Vector3) -> Vector3 {
+        Vector3 {
+            x: self.x.clamp(lo.x, hi.x),
+            y: self.y.clamp(lo.y, hi.y),
+            z: self.z.clamp(lo.z, hi.z),
+        }
+    }
+}
+
+impl Vector4 {
+    // translations apply to points
+    pub fn point(v: Vector3) -> Vector4 {
+        Vector4 {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            w: 1.0,
+        }
+    }
+
+    // translations are ignored for directions
+    pub fn direction(v: Vector3) -> Vector4 {
+        Vector4 {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            w: 0.0,
+        }
+    }
+
+    // drops w without dividing by it
+    pub fn xyz(self) -> Vector3 {
+        Vector3 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+
+    pub fn perspective_divide(self) -> Vector3 {
+        Vector3 {
+            x: self.x / self.w,
+            y: self.y / self.w,
+            z: self.z / self.w,
+        }
+    }
+}
+
+impl Aabb {
+    // returns None if there are no points to bound
+    pub fn from_points(points: impl IntoIterator<Item = Vector3>) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |acc, point| {
+                acc.union(Aabb {
+                    min: point,
+                    max: point,
+                })
+            },
+        ))
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::min(self.min, other.min),
+            max: Vector3::max(self.max, other.max),
+        }
+    }
+
+    pub fn center(self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // boxes that only touch along a face still overlap
+    pub fn overlaps(self, other: Aabb) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+            && self.min.z <= other.max.z
+            && other.min.z <= self.max.z
+    }
+
+    pub fn corners(self) -> [Vector3; 8] {
+        let mut ret = [Vector3::default(); 8];
+        for (i, corner) in ret.iter_mut().enumerate() {
+            corner.x = if i & 1 == 0 { self.min.x } else { self.max.x };
+            corner.y = if i & 2 == 0 { self.min.y } else { self.max.y };
+            corner.z = if i & 4 == 0 { self.min.z } else { self.max.z };
+        }
+        ret
+    }
+}
+
+impl Frustum {
+    pub fn from_matrix(view_projection: Mat4) -> Frustum {
+        Frustum {
+            planes: view_projection.frustum_planes(),
+        }
+    }
+
+    // points right on a plane are inside
+    pub fn contains_point(&self, point: Vector3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Vector3::dot(plane.xyz(), point) + plane.w >= 0.0)
+    }
+
+    /*
+     * False only when the box is entirely outside of one of the planes, found by testing the
+     * corner farthest along that plane's normal. Boxes straddling a corner outside of the frustum
+     * still count as intersecting, which is fine for culling.
+     */
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let farthest = Vector3 {
+                x: if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                y: if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                z: if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            };
+            Vector3::dot(plane.xyz(), farthest) + plane.w >= 0.0
+        })
+    }
+
+    // like intersects_aabb, false only when the sphere is entirely outside of one plane
+    pub fn intersects_sphere(&self, center: Vector3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Vector3::dot(plane.xyz(), center) + plane.w >= -radius)
+    }
 }
 
 impl ops::Mul for Mat4 {
@@ -378,14 +764,11 @@ impl ops::Mul for Vector3 {
     }
 }
 
-// (note: amoussa) this is a bit sketchy because we internally promote the vec3 into a vec4
-// since it makes sense in the context of geometric transformations.
-// Perhaps Mat4 and Vector3 should be Transformation and Point respectively
 #[allow(clippy::needless_range_loop)]
-impl ops::Mul<Vector3> for Mat4 {
-    type Output = Vector3;
-    fn mul(self, rhs: Vector3) -> Vector3 {
-        let vec4_rhs = [rhs.x, rhs.y, rhs.z, 1.0];
+impl ops::Mul<Vector4> for Mat4 {
+    type Output = Vector4;
+    fn mul(self, rhs: Vector4) -> Vector4 {
+        let vec4_rhs = [rhs.x, rhs.y, rhs.z, rhs.w];
         let mut vec4_out = [0.0; 4];
 
         // 4x4 * 4x1
@@ -393,14 +776,24 @@ impl ops::Mul<Vector3> for Mat4 {
             vec4_out[i] = (0..4).map(|k| *self.at(k, i) * vec4_rhs[k]).sum();
         }
 
-        Vector3 {
-            x: vec4_out[0] / vec4_out[3],
-            y: vec4_out[1] / vec4_out[3],
-            z: vec4_out[2] / vec4_out[3],
+        Vector4 {
+            x: vec4_out[0],
+            y: vec4_out[1],
+            z: vec4_out[2],
+            w: vec4_out[3],
         }
     }
 }
 
+// a Vector3 is treated as a point, so it is promoted with w = 1 and divided by w afterwards.
+// Directions should go through Vector4::direction instead
+impl ops::Mul<Vector3> for Mat4 {
+    type Output = Vector3;
+    fn mul(self, rhs: Vector3) -> Vector3 {
+        (self * Vector4::point(rhs)).perspective_divide()
+    }
+}
+
 #[allow(clippy::needless_range_loop)]
 impl From<Mat4> for Mat3 {
     fn from(item: Mat4) -> Mat3 {
@@ -469,6 +862,35 @@ impl ops::Mul<f32> for Vector3 {
 }
 
 impl Color {
+    // linearly interpolates from self (t = 0.0) to other (t = 1.0)
+    pub fn blend(self, other: Color, t: f32) -> Color {
+        (self.to_vector3() * (1.0 - t) + other.to_vector3() * t).to_color()
+    }
+
+    // parses #RRGGBB, the hex digits in either case
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let digits = hex.strip_prefix('#')?;
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |idx: usize| u8::from_str_radix(&digits[idx..idx + 2], 16).ok();
+        Some(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+
+    // #rrggbb in lowercase
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    // Rec. 709 luma on the same 0-255 scale as the channels
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r as f32 + 0.7152 * self.g as f32 + 0.0722 * self.b as f32
+    }
+
     pub fn to_vector3(self) -> Vector3 {
         Vector3 {
             x: self.r as f32 / 255.0,
@@ -477,3 +899,37 @@ impl Color {
         }
     }
 }
+
+impl ops::Add for Color {
+    type Output = Color;
+    fn add(self, rhs: Color) -> Color {
+        Color {
+            r: self.r.saturating_add(rhs.r),
+            g: self.g.saturating_add(rhs.g),
+            b: self.b.saturating_add(rhs.b),
+        }
+    }
+}
+
+impl ops::Mul<f32> for Color {
+    type Output = Color;
+    fn mul(self, rhs: f32) -> Color {
+        (self.to_vector3() * rhs).to_color()
+    }
+}
+
+/*
+ * The index-th number of the halton sequence in the given base, the radical inverse of index. The
+ * numbers are spread evenly over 0 to 1 however many are taken, which makes them good sub pixel
+ * sample positions. Index 0 gives 0.0, so sequences usually start at 1.
+ */
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut ret = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        ret += fraction * (index % base) as f32;
+        index /= base;
+    }
+    ret
+}
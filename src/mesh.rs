@@ -3,9 +3,11 @@ use crate::math::*;
 use core::fmt;
 use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
-use std::io::{prelude::*, BufReader};
+use std::fs::{self, File};
+use std::io::{prelude::*, BufReader, BufWriter};
 use std::path::Path;
+use std::sync::Arc;
+use std::thread;
 
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct Triangle {
@@ -20,13 +22,113 @@ pub struct Triangle {
     pub c_texture: usize,
 }
 
+// how a mesh's surface looks, mirroring the parts of an MTL material we understand
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    // albedo multiplied into the lighting when there is no diffuse texture (Kd)
+    pub diffuse_color: Color,
+    pub diffuse_texture: Option<Arc<dyn Texture>>, // map_Kd
+    // the mipmap of diffuse_texture for trilinear filtering, built the first time it's drawn
+    pub diffuse_mipmap: MipmapCache,
+    pub specular_color: Color, // Ks
+    pub shininess: f32,        // Ns
+    // light the surface gives off itself, added on top of the lit color (Ke)
+    pub emissive_color: Color,
+    // how the texture coordinates of the mesh map onto diffuse_texture and alpha_mask
+    pub uv_origin: UvOrigin,
+    // what diffuse_texture and alpha_mask give for texture coordinates outside of 0 to 1
+    pub texture_wrap: WrapMode,
+    // cutout transparency (map_d), fragments where the mask's luminance is below alpha_cutoff
+    // (0 is black and 1 is white) are discarded without touching the color or depth buffer
+    pub alpha_mask: Option<Arc<dyn Texture>>,
+    pub alpha_cutoff: f32,
+    // how much of what's behind the surface it hides (d, or 1 - Tr). Anything below 1.0 is blended
+    // over the color buffer and doesn't write depth, see RenderOptions::depth_sort_transparent
+    pub opacity: f32,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            diffuse_color: Color {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+            diffuse_texture: None,
+            diffuse_mipmap: MipmapCache::default(),
+            specular_color: Color::default(),
+            shininess: 0.0,
+            emissive_color: Color::default(),
+            uv_origin: UvOrigin::default(),
+            texture_wrap: WrapMode::default(),
+            alpha_mask: None,
+            alpha_cutoff: 0.5,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl Material {
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0
+    }
+
+    // the mipmap of diffuse_texture, None without one
+    pub fn diffuse_mipmap(&self) -> Option<Arc<Mipmap>> {
+        self.diffuse_texture
+            .as_ref()
+            .map(|texture| Mipmap::cached(texture, &self.diffuse_mipmap))
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Mesh {
     pub verticies: Vec<Vector3>,
     pub face_indicies: Vec<Triangle>,
     pub vertex_normals: Vec<Vector3>,
     pub vertex_texture_coords: Vec<Vector3>,
-    pub texture: Option<Image>,
+    // multiplied into the albedo, indexed like verticies or empty when the mesh has none (from
+    // OBJ v lines with an r g b after the position, or glTF COLOR_0)
+    pub vertex_colors: Vec<Color>,
+    pub material: Material,
+    // pairs of vertex indices from OBJ l elements, drawn as lines rather than filled
+    pub edges: Vec<(usize, usize)>,
+    // vertex indices from OBJ p elements, drawn as single pixels
+    pub points: Vec<usize>,
+    // lets drawing skip faces outside of the frustum, see build_bvh
+    pub bvh: Option<Bvh>,
+}
+
+// the most faces a leaf holds before it is split
+const BVH_LEAF_FACES: usize = 4;
+
+/*
+ * A bounding volume hierarchy over the bounding boxes of a mesh's faces. Rasterizing doesn't need
+ * it to find what a ray hits, it's only there to throw away whole groups of faces at once, such
+ * as the ones outside of the camera's frustum.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct Bvh {
+    // the root is the first node, an interior node's left child always comes right after it
+    nodes: Vec<BvhNode>,
+    // face indices, each leaf owns a contiguous run of them
+    faces: Vec<usize>,
+    // the bounding box of every face, indexed by face
+    face_bounds: Vec<Aabb>,
+}
+
+#[derive(Debug, Clone)]
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        right: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -39,101 +141,878 @@ impl fmt::Display for ParseObjError {
     }
 }
 
+// which axis a model was authored with pointing up, the renderer assumes Y
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z, // e.g. Blender's default export
+}
+
+#[derive(Debug)]
+pub struct MergeMeshError {}
+impl Error for MergeMeshError {}
+
+impl fmt::Display for MergeMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot merge two meshes that both have a texture")
+    }
+}
+
 impl Mesh {
-    pub fn from_obj_file(path: &Path) -> Result<Mesh, Box<dyn Error>> {
-        let obj_file = File::open(path)?;
-        let mut ret = Mesh::default();
+    /*
+     * Appends other's geometry to this mesh, re-basing its face indices past the existing
+     * verticies, normals and texture coordinates. A mesh only holds one texture so merging fails
+     * if both meshes are textured, otherwise self's material is kept.
+     */
+    pub fn merge(&mut self, other: &Mesh) -> Result<(), MergeMeshError> {
+        if self.material.diffuse_texture.is_some() && other.material.diffuse_texture.is_some() {
+            return Err(MergeMeshError {});
+        }
+        if self.material.diffuse_texture.is_none() {
+            self.material.diffuse_texture = other.material.diffuse_texture.clone();
+        }
+
+        let vertex_offset = self.verticies.len();
+        let normal_offset = self.vertex_normals.len();
+        let texture_offset = self.vertex_texture_coords.len();
+
+        // a mesh without vertex colors is as if every vertex were white
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        if self.vertex_colors.is_empty() && !other.vertex_colors.is_empty() {
+            self.vertex_colors = vec![white; vertex_offset];
+        }
+        if !self.vertex_colors.is_empty() {
+            if other.vertex_colors.is_empty() {
+                self.vertex_colors
+                    .extend(std::iter::repeat_n(white, other.verticies.len()));
+            } else {
+                self.vertex_colors.extend_from_slice(&other.vertex_colors);
+            }
+        }
+        self.verticies.extend_from_slice(&other.verticies);
+        self.vertex_normals.extend_from_slice(&other.vertex_normals);
+        self.vertex_texture_coords
+            .extend_from_slice(&other.vertex_texture_coords);
+        self.face_indicies
+            .extend(other.face_indicies.iter().map(|face| Triangle {
+                a: face.a + vertex_offset,
+                b: face.b + vertex_offset,
+                c: face.c + vertex_offset,
+                a_normal: face.a_normal + normal_offset,
+                b_normal: face.b_normal + normal_offset,
+                c_normal: face.c_normal + normal_offset,
+                a_texture: face.a_texture + texture_offset,
+                b_texture: face.b_texture + texture_offset,
+                c_texture: face.c_texture + texture_offset,
+            }));
+        self.edges.extend(
+            other
+                .edges
+                .iter()
+                .map(|&(a, b)| (a + vertex_offset, b + vertex_offset)),
+        );
+        self.points
+            .extend(other.points.iter().map(|&point| point + vertex_offset));
+        self.refresh_bvh();
+        Ok(())
+    }
+
+    // area of face i in model space, i.e. before the model's transform is applied
+    pub fn face_area(&self, i: usize) -> f32 {
+        let face = self.face_indicies[i];
+        let v0 = self.verticies[face.a];
+        let v1 = self.verticies[face.b];
+        let v2 = self.verticies[face.c];
+        Vector3::cross(v1 - v0, v2 - v0).magnitude() / 2.0
+    }
 
-        let mut triangle_to_faces: HashMap<usize, Vec<usize>> = HashMap::new();
-        let mut should_compute_normals = true;
+    // centroid of face i in model space
+    pub fn face_centroid(&self, i: usize) -> Vector3 {
+        let face = self.face_indicies[i];
+        (self.verticies[face.a] + self.verticies[face.b] + self.verticies[face.c]) * (1.0 / 3.0)
+    }
+
+    /*
+     * A sphere around every vertex in model space as (center, radius). The center is the middle of
+     * the bounding box rather than the smallest possible sphere, which is close enough for culling.
+     * An empty mesh gets a zero radius sphere at the origin.
+     */
+    pub fn bounding_sphere(&self) -> (Vector3, f32) {
+        let Some(aabb) = Aabb::from_points(self.verticies.iter().copied()) else {
+            return (Vector3::ORIGIN, 0.0);
+        };
+        let center = aabb.center();
+        let radius = self
+            .verticies
+            .iter()
+            .map(|&vertex| Vector3::distance(center, vertex))
+            .fold(0.0, f32::max);
+        (center, radius)
+    }
+
+    /*
+     * Rotates a mesh authored with the given up axis into our Y-up convention. Z-up is rotated -90
+     * degrees about X, mapping (x, y, z) to (x, z, -y), which keeps the winding of every face.
+     */
+    pub fn convert_up_axis(&mut self, from: UpAxis) {
+        let convert = |v: Vector3| match from {
+            UpAxis::Y => v,
+            UpAxis::Z => Vector3 {
+                x: v.x,
+                y: v.z,
+                z: -v.y,
+            },
+        };
+        for vertex in self.verticies.iter_mut() {
+            *vertex = convert(*vertex);
+        }
+        for normal in self.vertex_normals.iter_mut() {
+            *normal = convert(*normal);
+        }
+        self.refresh_bvh();
+    }
+
+    /*
+     * Merges verticies that sit within epsilon of each other and rewrites the faces to match.
+     * Candidates are found through a spatial hash of epsilon sized cells so only the neighboring
+     * cells need to be searched. If the normals were generated per vertex (rather than read from
+     * the file) they are regenerated afterwards so they smooth over the newly shared edges.
+     */
+    pub fn weld_vertices(&mut self, epsilon: f32) {
+        let normals_are_per_vertex = self.face_indicies.iter().all(|face| {
+            face.a == face.a_normal && face.b == face.b_normal && face.c == face.c_normal
+        });
 
-        // read line by line, insert all verts into ret
-        let obj_reader = BufReader::new(obj_file);
-        for maybe_line in obj_reader.lines() {
-            let line = maybe_line?;
+        let cell_size = epsilon.max(f32::EPSILON);
+        let cell_of = |v: Vector3| {
+            (
+                (v.x / cell_size).floor() as i64,
+                (v.y / cell_size).floor() as i64,
+                (v.z / cell_size).floor() as i64,
+            )
+        };
 
-            let split_line: Vec<&str> = line.split_whitespace().collect();
+        let mut cells: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        let mut welded_verticies: Vec<Vector3> = Vec::new();
+        let mut remap: Vec<usize> = Vec::with_capacity(self.verticies.len());
+        for vertex in self.verticies.iter() {
+            let (cx, cy, cz) = cell_of(*vertex);
 
-            match split_line[0] {
-                "v" => {
-                    let x = split_line[1].parse::<f32>()?;
-                    let y = split_line[2].parse::<f32>()?;
-                    let z = split_line[3].parse::<f32>()?;
-                    ret.verticies.push(Vector3 { x, y, z });
+            // search this cell and its neighbors for a vertex we have already kept
+            let mut existing = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let Some(candidates) = cells.get(&(cx + dx, cy + dy, cz + dz)) else {
+                            continue;
+                        };
+                        if let Some(idx) = candidates.iter().find(|idx| {
+                            Vector3::distance(welded_verticies[**idx], *vertex) <= epsilon
+                        }) {
+                            existing = Some(*idx);
+                            break 'search;
+                        }
+                    }
                 }
-                "vn" => {
-                    let x = split_line[1].parse::<f32>()?;
-                    let y = split_line[2].parse::<f32>()?;
-                    let z = split_line[3].parse::<f32>()?;
-                    ret.vertex_normals.push(Vector3 { x, y, z }.normalized());
+            }
+
+            let welded_idx = existing.unwrap_or_else(|| {
+                welded_verticies.push(*vertex);
+                let idx = welded_verticies.len() - 1;
+                cells.entry((cx, cy, cz)).or_default().push(idx);
+                idx
+            });
+            remap.push(welded_idx);
+        }
+
+        self.verticies = welded_verticies;
+        // welded verticies keep the color of the first vertex that went into them
+        if !self.vertex_colors.is_empty() {
+            let mut welded_colors = vec![Color::default(); self.verticies.len()];
+            for (old_idx, &new_idx) in remap.iter().enumerate().rev() {
+                welded_colors[new_idx] = self.vertex_colors[old_idx];
+            }
+            self.vertex_colors = welded_colors;
+        }
+        for face in self.face_indicies.iter_mut() {
+            face.a = remap[face.a];
+            face.b = remap[face.b];
+            face.c = remap[face.c];
+        }
+        for edge in self.edges.iter_mut() {
+            *edge = (remap[edge.0], remap[edge.1]);
+        }
+        for point in self.points.iter_mut() {
+            *point = remap[*point];
+        }
+
+        if normals_are_per_vertex {
+            self.compute_smooth_normals();
+        }
+        self.refresh_bvh();
+    }
+
+    /*
+     * Points every texture index that has no matching texture coordinate (faces without /vt
+     * entries, or indices past the last vt) at a (0, 0) coordinate so drawing never indexes out
+     * of bounds. Returns how many indices were replaced.
+     */
+    pub fn fill_missing_texture_coords(&mut self) -> usize {
+        let num_coords = self.vertex_texture_coords.len();
+        let mut num_missing = 0;
+        for face in self.face_indicies.iter_mut() {
+            for idx in [
+                &mut face.a_texture,
+                &mut face.b_texture,
+                &mut face.c_texture,
+            ] {
+                if *idx >= num_coords {
+                    *idx = num_coords;
+                    num_missing += 1;
                 }
-                "vt" => {
-                    let x = split_line[1].parse::<f32>()?;
-                    let y = split_line[2].parse::<f32>()?;
-                    // FIXME make vector2
-                    ret.vertex_texture_coords.push(Vector3 { x, y, z: 0.0 });
+            }
+        }
+        if num_missing > 0 {
+            self.vertex_texture_coords.push(Vector3::default());
+        }
+        num_missing
+    }
+
+    /*
+     * Flips every face whose winding disagrees with its vertex normals, i.e. whose face normal
+     * points away from the sum of the three vertex normals, so it is not culled as a back face.
+     * Returns how many faces were flipped.
+     */
+    pub fn normalize_winding(&mut self) -> usize {
+        let mut num_flipped = 0;
+        for face in self.face_indicies.iter_mut() {
+            let v0 = self.verticies[face.a];
+            let v1 = self.verticies[face.b];
+            let v2 = self.verticies[face.c];
+            let face_normal = Vector3::cross(v2 - v0, v1 - v0);
+            let vertex_normal = self.vertex_normals[face.a_normal]
+                + self.vertex_normals[face.b_normal]
+                + self.vertex_normals[face.c_normal];
+
+            if Vector3::dot(face_normal, vertex_normal) < 0.0 {
+                std::mem::swap(&mut face.b, &mut face.c);
+                std::mem::swap(&mut face.b_normal, &mut face.c_normal);
+                std::mem::swap(&mut face.b_texture, &mut face.c_texture);
+                num_flipped += 1;
+            }
+        }
+        num_flipped
+    }
+
+    /*
+     * Throws away vertex_normals and regenerates them from the faces the same way the OBJ loader
+     * does for files without normals, for after verticies were edited or moved. Faces get one
+     * normal per vertex, so any hard edges from the file or from smoothing groups are lost.
+     */
+    pub fn recompute_normals(&mut self) {
+        self.compute_smooth_normals();
+    }
+
+    // averages the normals of every face touching a vertex, indexing normals like the verticies
+    pub(crate) fn compute_smooth_normals(&mut self) {
+        self.vertex_normals = vec![Vector3::default(); self.verticies.len()];
+        for face in self.face_indicies.iter_mut() {
+            let v0 = self.verticies[face.a];
+            let v1 = self.verticies[face.b];
+            let v2 = self.verticies[face.c];
+            let face_normal = Vector3::cross(v2 - v0, v1 - v0).normalized();
+            for vertex_idx in [face.a, face.b, face.c] {
+                self.vertex_normals[vertex_idx] = self.vertex_normals[vertex_idx] + face_normal;
+            }
+            face.a_normal = face.a;
+            face.b_normal = face.b;
+            face.c_normal = face.c;
+        }
+        for normal in self.vertex_normals.iter_mut() {
+            *normal = normal.normalized();
+        }
+    }
+
+    /*
+     * Like compute_smooth_normals but only averages faces within the same smoothing group, faces
+     * without a group keep their flat face normal. A vertex can end up with one normal per group
+     * touching it, so normals are no longer indexed like the verticies.
+     */
+    fn compute_grouped_normals(&mut self, face_groups: &[Option<u32>]) {
+        let face_normal = |face: &Triangle| {
+            let v0 = self.verticies[face.a];
+            let v1 = self.verticies[face.b];
+            let v2 = self.verticies[face.c];
+            Vector3::cross(v2 - v0, v1 - v0).normalized()
+        };
+        let face_normals: Vec<Vector3> = self.face_indicies.iter().map(face_normal).collect();
+
+        // one normal per (vertex, group) pair, ungrouped faces get a normal of their own
+        let mut normals: Vec<Vector3> = Vec::new();
+        let mut group_normal_idx: HashMap<(usize, u32), usize> = HashMap::new();
+        for (face_idx, face) in self.face_indicies.iter_mut().enumerate() {
+            let normal = face_normals[face_idx];
+            let Some(group) = face_groups[face_idx] else {
+                normals.push(normal);
+                face.a_normal = normals.len() - 1;
+                face.b_normal = normals.len() - 1;
+                face.c_normal = normals.len() - 1;
+                continue;
+            };
+
+            for (vertex_idx, normal_idx) in [
+                (face.a, &mut face.a_normal),
+                (face.b, &mut face.b_normal),
+                (face.c, &mut face.c_normal),
+            ] {
+                *normal_idx = *group_normal_idx
+                    .entry((vertex_idx, group))
+                    .or_insert_with(|| {
+                        normals.push(Vector3::default());
+                        normals.len() - 1
+                    });
+                normals[*normal_idx] = normals[*normal_idx] + normal;
+            }
+        }
+
+        for idx in group_normal_idx.into_values() {
+            normals[idx] = normals[idx].normalized();
+        }
+        self.vertex_normals = normals;
+    }
+
+    /*
+     * Generates normals that only average faces meeting at less than smoothing_angle (in radians),
+     * so hard edges like a cube's corners stay sharp. A vertex on a crease gets one normal per
+     * side of it, so normals are no longer indexed like the verticies.
+     */
+    pub fn compute_angle_normals(&mut self, smoothing_angle: f32) {
+        let face_normals: Vec<Vector3> = self
+            .face_indicies
+            .iter()
+            .map(|face| {
+                let v0 = self.verticies[face.a];
+                let v1 = self.verticies[face.b];
+                let v2 = self.verticies[face.c];
+                Vector3::cross(v2 - v0, v1 - v0).normalized()
+            })
+            .collect();
+        let mut vertex_faces: Vec<Vec<usize>> = vec![Vec::new(); self.verticies.len()];
+        for (face_idx, face) in self.face_indicies.iter().enumerate() {
+            for vertex_idx in [face.a, face.b, face.c] {
+                vertex_faces[vertex_idx].push(face_idx);
+            }
+        }
+
+        // corners of a vertex that end up averaging the same faces share one normal
+        let min_cos = smoothing_angle.cos();
+        let mut normals: Vec<Vector3> = Vec::new();
+        let mut smoothed_normal_idx: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+        for (face_idx, face) in self.face_indicies.iter_mut().enumerate() {
+            let normal = face_normals[face_idx];
+            for (vertex_idx, normal_idx) in [
+                (face.a, &mut face.a_normal),
+                (face.b, &mut face.b_normal),
+                (face.c, &mut face.c_normal),
+            ] {
+                let smoothed: Vec<usize> = vertex_faces[vertex_idx]
+                    .iter()
+                    .copied()
+                    .filter(|&other| Vector3::dot(face_normals[other], normal) >= min_cos)
+                    .collect();
+                *normal_idx = *smoothed_normal_idx
+                    .entry((vertex_idx, smoothed))
+                    .or_insert_with_key(|(_, smoothed)| {
+                        let sum = smoothed
+                            .iter()
+                            .fold(Vector3::default(), |sum, &other| sum + face_normals[other]);
+                        normals.push(sum.normalized());
+                        normals.len() - 1
+                    });
+            }
+        }
+        self.vertex_normals = normals;
+    }
+
+    pub fn from_obj_file(path: &Path) -> Result<Mesh, Box<dyn Error>> {
+        Mesh::load_obj(path, None)
+    }
+
+    /*
+     * Like from_obj_file, but generated normals only smooth across edges sharper than
+     * smoothing_angle (in radians). Normals from the file and smoothing groups are still used
+     * as is since they already say which edges are hard.
+     */
+    pub fn from_obj_file_with_smoothing_angle(
+        path: &Path,
+        smoothing_angle: f32,
+    ) -> Result<Mesh, Box<dyn Error>> {
+        Mesh::load_obj(path, Some(smoothing_angle))
+    }
+
+    pub fn save_to_obj(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let output_file = File::create(path)?;
+        self.write_obj(&mut BufWriter::new(output_file))
+    }
+
+    /*
+     * Writes the geometry as an OBJ file that from_obj_file reads back into the same mesh:
+     * verticies (with their colors), normals, texture coordinates, faces and l elements. The
+     * material isn't written.
+     * Faces only reference texture coordinates when the mesh has any, and normals likewise.
+     */
+    pub fn write_obj<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        for (idx, v) in self.verticies.iter().enumerate() {
+            match self.vertex_colors.get(idx) {
+                Some(color) => {
+                    let color = color.to_vector3();
+                    writeln!(
+                        writer,
+                        "v {} {} {} {} {} {}",
+                        v.x, v.y, v.z, color.x, color.y, color.z
+                    )?;
                 }
-                "f" => {
-                    ret.face_indicies
-                        .push(parse_face(&line).ok_or(ParseObjError {})?);
-                    let face_index = ret.face_indicies.len() - 1;
-                    let face_ref: &Triangle = ret.face_indicies.last().unwrap();
-
-                    // (note: amoussa) this is not great, but we say that if every
-                    // single face has the same vertex index and normal index, then we should
-                    // generate normals (since that output is what happens if there were no normals
-                    // in the file). Ideally the parse_face function should just tell us if normals
-                    // were present in the file though.
-                    let normals_and_vert_idxs_are_the_same = face_ref.a == face_ref.a_normal
-                        && face_ref.b == face_ref.b_normal
-                        && face_ref.c == face_ref.c_normal;
-                    should_compute_normals &= normals_and_vert_idxs_are_the_same;
-
-                    if should_compute_normals {
-                        // store for normal generation
-                        for t in [face_ref.a, face_ref.b, face_ref.c] {
-                            let triangle_index = t;
-                            match triangle_to_faces.get_mut(&triangle_index) {
-                                Some(face_list) => face_list.push(face_index),
-                                _ => {
-                                    drop(triangle_to_faces.insert(triangle_index, vec![face_index]))
-                                }
-                            }
-                        }
+                None => writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?,
+            }
+        }
+        for vn in self.vertex_normals.iter() {
+            writeln!(writer, "vn {} {} {}", vn.x, vn.y, vn.z)?;
+        }
+        for vt in self.vertex_texture_coords.iter() {
+            writeln!(writer, "vt {} {}", vt.x, vt.y)?;
+        }
+
+        // OBJ indices start at 1
+        let has_normals = !self.vertex_normals.is_empty();
+        let has_texture_coords = !self.vertex_texture_coords.is_empty();
+        let corner = |vertex: usize, texture: usize, normal: usize| match (
+            has_texture_coords,
+            has_normals,
+        ) {
+            (true, true) => format!("{}/{}/{}", vertex + 1, texture + 1, normal + 1),
+            (false, true) => format!("{}//{}", vertex + 1, normal + 1),
+            (true, false) => format!("{}/{}", vertex + 1, texture + 1),
+            (false, false) => format!("{}", vertex + 1),
+        };
+        for face in self.face_indicies.iter() {
+            writeln!(
+                writer,
+                "f {} {} {}",
+                corner(face.a, face.a_texture, face.a_normal),
+                corner(face.b, face.b_texture, face.b_normal),
+                corner(face.c, face.c_texture, face.c_normal)
+            )?;
+        }
+        for (start, end) in self.edges.iter() {
+            writeln!(writer, "l {} {}", start + 1, end + 1)?;
+        }
+        for point in self.points.iter() {
+            writeln!(writer, "p {}", point + 1)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /*
+     * Builds the bvh draw_mesh culls with. Meshes loaded from files already have one and merge,
+     * weld_vertices and convert_up_axis keep it up to date, but a mesh whose verticies or faces
+     * are edited by hand has to build it again (or set it to None) before it is drawn.
+     */
+    pub fn build_bvh(&mut self) {
+        self.bvh = Some(Bvh::new(self));
+    }
+
+    fn refresh_bvh(&mut self) {
+        if self.bvh.is_some() {
+            self.build_bvh();
+        }
+    }
+
+    // the file is read whole so big files can be parsed on several threads, see parse_obj_lines
+    fn load_obj(path: &Path, smoothing_angle: Option<f32>) -> Result<Mesh, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let lines: Vec<&str> = content.lines().collect();
+        let num_chunks = thread::available_parallelism()
+            .map_or(1, |threads| threads.get())
+            .min(lines.len() / OBJ_MIN_CHUNK_LINES);
+        let contents = parse_obj_lines(&lines, num_chunks).map_err(|why| why as Box<dyn Error>)?;
+
+        let mut ret = Mesh {
+            verticies: contents.verticies,
+            face_indicies: contents.face_indicies,
+            vertex_normals: contents.vertex_normals,
+            vertex_texture_coords: contents.vertex_texture_coords,
+            vertex_colors: if contents.has_vertex_colors {
+                contents.vertex_colors
+            } else {
+                Vec::new()
+            },
+            edges: contents.edges,
+            points: contents.points,
+            ..Default::default()
+        };
+        let num_faces_with_normals = contents.face_has_normals.iter().filter(|&&has| has).count();
+        let has_smoothing_groups = contents.last_group.is_some();
+        // faces before any s directive share group 0 (which s 0 can't name since it means off)
+        // so they all smooth together
+        let face_groups: Vec<Option<u32>> = contents
+            .face_groups
+            .iter()
+            .map(|group| group.unwrap_or(Some(0)))
+            .collect();
+
+        if let Some(material_lib) = contents.material_lib {
+            let prefix = match path.parent() {
+                Some(pre) => pre,
+                None => Path::new(""),
+            };
+            ret.material = load_material_lib(&prefix.join(material_lib))?;
+        }
+
+        // compute normals if they are missing, otherwise trust them over the face winding. Files
+        // can mix faces with and without normals, only the ones without get generated normals.
+        if num_faces_with_normals == 0 {
+            ret.generate_normals(&face_groups, has_smoothing_groups, smoothing_angle);
+        } else {
+            if num_faces_with_normals < ret.face_indicies.len() {
+                ret.generate_missing_normals(
+                    &contents.face_has_normals,
+                    &face_groups,
+                    has_smoothing_groups,
+                    smoothing_angle,
+                );
+            }
+            ret.normalize_winding();
+        }
+
+        // textured faces need a uv for every corner, fall back to the texture's corner otherwise
+        if ret.material.diffuse_texture.is_some() || ret.material.alpha_mask.is_some() {
+            ret.fill_missing_texture_coords();
+        }
+        ret.build_bvh();
+        Ok(ret)
+    }
+
+    // normals for a mesh loaded without any, following its smoothing groups or smoothing angle
+    fn generate_normals(
+        &mut self,
+        face_groups: &[Option<u32>],
+        has_smoothing_groups: bool,
+        smoothing_angle: Option<f32>,
+    ) {
+        if has_smoothing_groups {
+            self.compute_grouped_normals(face_groups);
+        } else if let Some(smoothing_angle) = smoothing_angle {
+            self.compute_angle_normals(smoothing_angle);
+        } else {
+            self.compute_smooth_normals();
+        }
+    }
+
+    /*
+     * generate_normals for only the faces that have_normals says are missing them, as if those
+     * faces were a mesh of their own. The generated normals are appended after the existing ones.
+     */
+    fn generate_missing_normals(
+        &mut self,
+        have_normals: &[bool],
+        face_groups: &[Option<u32>],
+        has_smoothing_groups: bool,
+        smoothing_angle: Option<f32>,
+    ) {
+        let missing: Vec<usize> = (0..self.face_indicies.len())
+            .filter(|&face_idx| !have_normals[face_idx])
+            .collect();
+        let mut generated = Mesh {
+            verticies: self.verticies.clone(),
+            face_indicies: missing
+                .iter()
+                .map(|&face_idx| self.face_indicies[face_idx])
+                .collect(),
+            ..Default::default()
+        };
+        let missing_groups: Vec<Option<u32>> = missing
+            .iter()
+            .map(|&face_idx| face_groups[face_idx])
+            .collect();
+        generated.generate_normals(&missing_groups, has_smoothing_groups, smoothing_angle);
+
+        let normal_offset = self.vertex_normals.len();
+        self.vertex_normals.extend(generated.vertex_normals);
+        for (&face_idx, generated_face) in missing.iter().zip(generated.face_indicies.iter()) {
+            let face = &mut self.face_indicies[face_idx];
+            face.a_normal = generated_face.a_normal + normal_offset;
+            face.b_normal = generated_face.b_normal + normal_offset;
+            face.c_normal = generated_face.c_normal + normal_offset;
+        }
+    }
+}
+
+impl Bvh {
+    pub fn new(mesh: &Mesh) -> Bvh {
+        let mut ret = Bvh {
+            nodes: Vec::new(),
+            faces: (0..mesh.face_indicies.len()).collect(),
+            face_bounds: mesh
+                .face_indicies
+                .iter()
+                .map(|face| {
+                    let corners = [face.a, face.b, face.c].map(|idx| mesh.verticies[idx]);
+                    Aabb::from_points(corners).unwrap_or_default()
+                })
+                .collect(),
+        };
+        if !ret.faces.is_empty() {
+            ret.build_node(0, ret.faces.len());
+        }
+        ret
+    }
+
+    // splits faces[start..end] at the median centroid along the axis they are most spread out on
+    fn build_node(&mut self, start: usize, end: usize) -> usize {
+        let node_idx = self.nodes.len();
+        let face_bounds = &self.face_bounds;
+        let bounds = self.faces[start..end]
+            .iter()
+            .map(|&face| face_bounds[face])
+            .reduce(Aabb::union)
+            .unwrap_or_default();
+        if end - start <= BVH_LEAF_FACES {
+            self.nodes.push(BvhNode::Leaf { bounds, start, end });
+            return node_idx;
+        }
+
+        let centroids = Aabb::from_points(
+            self.faces[start..end]
+                .iter()
+                .map(|&face| face_bounds[face].center()),
+        )
+        .unwrap_or_default();
+        let extent = centroids.max - centroids.min;
+        let axis = |point: Vector3| {
+            if extent.x >= extent.y && extent.x >= extent.z {
+                point.x
+            } else if extent.y >= extent.z {
+                point.y
+            } else {
+                point.z
+            }
+        };
+        let mid = (start + end) / 2;
+        self.faces[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            axis(face_bounds[a].center()).total_cmp(&axis(face_bounds[b].center()))
+        });
+
+        self.nodes.push(BvhNode::Interior { bounds, right: 0 });
+        self.build_node(start, mid);
+        let right_idx = self.build_node(mid, end);
+        if let BvhNode::Interior { right, .. } = &mut self.nodes[node_idx] {
+            *right = right_idx;
+        }
+        node_idx
+    }
+
+    /*
+     * Every face whose bounding box passes overlaps, in ascending order so faces are still drawn
+     * in the order the mesh lists them. Subtrees whose bounds fail are skipped without looking at
+     * their faces, so overlaps has to fail for a box whenever it fails for everything inside it.
+     */
+    pub fn query(&self, mut overlaps: impl FnMut(Aabb) -> bool) -> Vec<usize> {
+        let mut ret = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node_idx) = stack.pop() {
+            match self.nodes[node_idx] {
+                BvhNode::Leaf { bounds, start, end } => {
+                    if overlaps(bounds) {
+                        ret.extend(
+                            self.faces[start..end]
+                                .iter()
+                                .filter(|&&face| overlaps(self.face_bounds[face])),
+                        );
                     }
                 }
-                "mtllib" => {
-                    let prefix = match path.parent() {
-                        Some(pre) => pre,
-                        None => Path::new(""),
-                    };
-                    let mat_lib = prefix.join(split_line[1]);
-                    ret.texture = Some(load_texture_from_material_lib(&mat_lib)?);
+                BvhNode::Interior { bounds, right } => {
+                    if overlaps(bounds) {
+                        stack.push(right);
+                        stack.push(node_idx + 1);
+                    }
                 }
-                _ => continue,
             }
         }
+        ret.sort_unstable();
+        ret
+    }
+
+    // the faces whose bounding boxes overlap region
+    pub fn query_aabb(&self, region: Aabb) -> Vec<usize> {
+        self.query(|bounds| bounds.overlaps(region))
+    }
+}
 
-        // compute normals if they are missing
-        if should_compute_normals {
-            ret.vertex_normals = vec![Vector3::default(); ret.verticies.len()];
-            for (triangle_idx, face_idx_list) in triangle_to_faces.into_iter() {
-                // compute, sum, and then normalize the normals of every face that this vertex
-                // contributes to
-                ret.vertex_normals[triangle_idx] = face_idx_list
-                    .into_iter()
-                    .map(|face_idx| {
-                        let v0 = ret.verticies[ret.face_indicies[face_idx].a];
-                        let v1 = ret.verticies[ret.face_indicies[face_idx].b];
-                        let v2 = ret.verticies[ret.face_indicies[face_idx].c];
-                        Vector3::cross(v2 - v0, v1 - v0).normalized()
-                    })
-                    .fold(Vector3::default(), |acc, norm| acc + norm)
-                    .normalized();
+// OBJ files are only split across threads when every thread gets at least this many lines
+const OBJ_MIN_CHUNK_LINES: usize = 1 << 16;
+
+// errors have to cross back from the parsing threads
+type ParseChunkError = Box<dyn Error + Send + Sync>;
+
+// everything one run of lines from an OBJ file holds, see Mesh::load_obj
+#[derive(Debug, Default, PartialEq)]
+struct ObjChunk {
+    verticies: Vec<Vector3>,
+    vertex_normals: Vec<Vector3>,
+    vertex_texture_coords: Vec<Vector3>,
+    // one per vertex, white for verticies without a color
+    vertex_colors: Vec<Color>,
+    has_vertex_colors: bool,
+    face_indicies: Vec<Triangle>,
+    edges: Vec<(usize, usize)>,
+    points: Vec<usize>,
+    // the smoothing group of every face, or None for faces before the chunk's first s line since
+    // those keep whatever group the chunks before it left off with
+    face_groups: Vec<Option<Option<u32>>>,
+    // the group the chunk's last s line set, None if it has no s lines
+    last_group: Option<Option<u32>>,
+    // whether every face listed normals for its corners
+    face_has_normals: Vec<bool>,
+    // the last mtllib line's path
+    material_lib: Option<String>,
+}
+
+/*
+ * Parses lines split into num_chunks runs, each on its own thread, and merges them in order into
+ * what parsing every line as one chunk gives. Face indices are absolute so chunks don't depend on
+ * each other, apart from the smoothing group in effect, which is carried from one chunk to the next
+ * while merging.
+ */
+fn parse_obj_lines(lines: &[&str], num_chunks: usize) -> Result<ObjChunk, ParseChunkError> {
+    if num_chunks <= 1 {
+        return parse_obj_chunk(lines);
+    }
+    let chunks: Vec<Result<ObjChunk, ParseChunkError>> = thread::scope(|scope| {
+        let workers: Vec<_> = lines
+            .chunks(lines.len().div_ceil(num_chunks).max(1))
+            .map(|chunk| scope.spawn(move || parse_obj_chunk(chunk)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    let mut ret = ObjChunk::default();
+    for chunk in chunks {
+        let chunk = chunk?;
+        ret.verticies.extend(chunk.verticies);
+        ret.vertex_colors.extend(chunk.vertex_colors);
+        ret.has_vertex_colors |= chunk.has_vertex_colors;
+        ret.vertex_normals.extend(chunk.vertex_normals);
+        ret.vertex_texture_coords
+            .extend(chunk.vertex_texture_coords);
+        ret.face_indicies.extend(chunk.face_indicies);
+        ret.edges.extend(chunk.edges);
+        ret.points.extend(chunk.points);
+        let current_group = ret.last_group;
+        ret.face_groups.extend(
+            chunk
+                .face_groups
+                .iter()
+                .map(|group| group.or(current_group)),
+        );
+        ret.last_group = chunk.last_group.or(current_group);
+        ret.face_has_normals.extend(chunk.face_has_normals);
+        ret.material_lib = chunk.material_lib.or(ret.material_lib);
+    }
+    Ok(ret)
+}
+
+fn parse_obj_chunk(lines: &[&str]) -> Result<ObjChunk, ParseChunkError> {
+    const WHITE: Color = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let mut ret = ObjChunk::default();
+
+    for line in lines {
+        // everything after a # is a comment, whether it fills the line or trails some data
+        let data = line.split('#').next().unwrap_or_default();
+        let split_line: Vec<&str> = data.split_whitespace().collect();
+        if split_line.is_empty() {
+            continue;
+        }
+
+        match split_line[0] {
+            "v" => {
+                let x = split_line[1].parse::<f32>()?;
+                let y = split_line[2].parse::<f32>()?;
+                let z = split_line[3].parse::<f32>()?;
+                // either an r g b color from 0 to 1 or an optional homogeneous weight, a w of 0
+                // would put the vertex at infinity
+                let (w, color) = match split_line.len() {
+                    7 => {
+                        let channel = |idx: usize| -> Result<u8, ParseChunkError> {
+                            let value = split_line[idx].parse::<f32>()?;
+                            Ok((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+                        };
+                        ret.has_vertex_colors = true;
+                        let (r, g, b) = (channel(4)?, channel(5)?, channel(6)?);
+                        (1.0, Color { r, g, b })
+                    }
+                    _ => match split_line.get(4) {
+                        Some(w) => (w.parse::<f32>()?, WHITE),
+                        None => (1.0, WHITE),
+                    },
+                };
+                if w == 0.0 {
+                    return Err(Box::new(ParseObjError {}));
+                }
+                ret.verticies.push(Vector3 { x, y, z } * (1.0 / w));
+                ret.vertex_colors.push(color);
             }
+            "vn" => {
+                let x = split_line[1].parse::<f32>()?;
+                let y = split_line[2].parse::<f32>()?;
+                let z = split_line[3].parse::<f32>()?;
+                ret.vertex_normals.push(Vector3 { x, y, z }.normalized());
+            }
+            "vt" => {
+                let x = split_line[1].parse::<f32>()?;
+                let y = split_line[2].parse::<f32>()?;
+                // FIXME make vector2
+                ret.vertex_texture_coords.push(Vector3 { x, y, z: 0.0 });
+            }
+            "f" => {
+                let (face_ref, has_normals) = parse_face(data).ok_or(ParseObjError {})?;
+                ret.face_indicies.push(face_ref);
+                ret.face_has_normals.push(has_normals);
+                ret.face_groups.push(ret.last_group);
+            }
+            "l" => ret
+                .edges
+                .extend(parse_line(&split_line).ok_or(ParseObjError {})?),
+            "p" => ret
+                .points
+                .extend(parse_points(&split_line).ok_or(ParseObjError {})?),
+            "s" => {
+                ret.last_group = Some(match *split_line.get(1).ok_or(ParseObjError {})? {
+                    "off" | "0" => None,
+                    group => Some(group.parse::<u32>()?),
+                });
+            }
+            "mtllib" => ret.material_lib = Some(split_line[1].to_string()),
+            _ => continue,
         }
-        Ok(ret)
     }
+    Ok(ret)
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -188,7 +1067,47 @@ fn push_number_into_face(
     Some(())
 }
 
-fn parse_face(face_str: &str) -> Option<Triangle> {
+/*
+ * An l element is a polyline through two or more verticies, each optionally followed by a /vt
+ * texture coordinate we don't need. Every consecutive pair becomes one zero-based edge.
+ */
+fn parse_line(split_line: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let verticies = split_line[1..]
+        .iter()
+        .map(
+            |vertex| match vertex.split('/').next()?.parse::<usize>().ok()? {
+                0 => None,
+                idx => Some(idx - 1),
+            },
+        )
+        .collect::<Option<Vec<usize>>>()?;
+    if verticies.len() < 2 {
+        return None;
+    }
+    Some(
+        verticies
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect(),
+    )
+}
+
+// the vertex indices of a p element, which can list any number of points
+fn parse_points(split_line: &[&str]) -> Option<Vec<usize>> {
+    if split_line.len() < 2 {
+        return None;
+    }
+    split_line[1..]
+        .iter()
+        .map(|vertex| match vertex.parse::<usize>().ok()? {
+            0 => None,
+            idx => Some(idx - 1),
+        })
+        .collect()
+}
+
+// the face, and whether its corners listed normals (if not they get the vertex indices instead)
+fn parse_face(face_str: &str) -> Option<(Triangle, bool)> {
     let mut state = FaceParseState::Ready;
     let mut num_type = CurrentNumberType::Vert;
     let mut vert_idx = 0;
@@ -267,24 +1186,71 @@ fn parse_face(face_str: &str) -> Option<Triangle> {
         ret.b_normal = ret.b;
         ret.c_normal = ret.c;
     }
-    Some(ret)
+    Some((ret, seen_normals))
 }
 
-fn load_texture_from_material_lib(mat_path: &Path) -> Result<Image, Box<dyn Error>> {
+// a mesh only has one material, so only the first one in the lib is read
+fn load_material_lib(mat_path: &Path) -> Result<Material, Box<dyn Error>> {
     // load file
     let file = File::open(mat_path)?;
     let reader = BufReader::new(file);
+    let mut ret = Material::default();
+    let mut seen_material = false;
+
+    let parse_color = |split_line: &[&str]| -> Result<Color, Box<dyn Error>> {
+        if split_line.len() != 4 {
+            return Err(Box::new(ParseObjError {}));
+        }
+        Ok(Vector3 {
+            x: split_line[1].parse::<f32>()?,
+            y: split_line[2].parse::<f32>()?,
+            z: split_line[3].parse::<f32>()?,
+        }
+        .to_color())
+    };
 
     for maybe_line in reader.lines() {
         let line = maybe_line?;
         let split_line: Vec<&str> = line.split_whitespace().collect();
-        if !split_line.is_empty() && split_line[0] == "map_Kd" {
-            let path = Path::new(split_line[1]);
-            return Image::load_ppm(path);
+        if split_line.is_empty() {
+            continue;
+        }
+
+        match split_line[0] {
+            "newmtl" if seen_material => break,
+            "newmtl" => seen_material = true,
+            "Kd" => ret.diffuse_color = parse_color(&split_line)?,
+            "Ks" => ret.specular_color = parse_color(&split_line)?,
+            "Ke" => ret.emissive_color = parse_color(&split_line)?,
+            "Ns" => ret.shininess = split_line.get(1).ok_or(ParseObjError {})?.parse::<f32>()?,
+            "d" | "Tr" => {
+                let value = split_line.get(1).ok_or(ParseObjError {})?.parse::<f32>()?;
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(Box::new(ParseObjError {}));
+                }
+                ret.opacity = if split_line[0] == "d" {
+                    value
+                } else {
+                    1.0 - value
+                };
+            }
+            "map_Kd" | "map_d" => {
+                // relative texture paths are relative to the material lib, absolute ones are kept
+                let prefix = mat_path.parent().unwrap_or(Path::new(""));
+                let texture_path = split_line.get(1).ok_or(ParseObjError {})?;
+                let texture: Arc<dyn Texture> =
+                    Arc::new(Image::load_from_file(&prefix.join(texture_path))?);
+                if split_line[0] == "map_Kd" {
+                    ret.diffuse_texture = Some(texture);
+                } else {
+                    ret.alpha_mask = Some(texture);
+                }
+            }
+            _ => continue,
         }
     }
 
-    Err(Box::new(ParseObjError {}))
+    Ok(ret)
 }
 
 #[cfg(test)]
@@ -297,7 +1263,8 @@ mod test {
         let maybe_tri = parse_face(face_str);
         assert!(maybe_tri.is_some());
 
-        let tri = maybe_tri.unwrap();
+        let (tri, has_normals) = maybe_tri.unwrap();
+        assert!(!has_normals);
         assert_eq!(tri.a, 0);
         assert_eq!(tri.b, 1);
         assert_eq!(tri.c, 2);
@@ -313,7 +1280,8 @@ mod test {
         let maybe_tri = parse_face(face_str);
         assert!(maybe_tri.is_some());
 
-        let tri = maybe_tri.unwrap();
+        let (tri, has_normals) = maybe_tri.unwrap();
+        assert!(has_normals);
         assert_eq!(tri.a, 0);
         assert_eq!(tri.b, 1);
         assert_eq!(tri.c, 2);
@@ -329,7 +1297,8 @@ mod test {
         let maybe_tri = parse_face(face_str);
         assert!(maybe_tri.is_some());
 
-        let tri = maybe_tri.unwrap();
+        let (tri, has_normals) = maybe_tri.unwrap();
+        assert!(!has_normals);
         assert_eq!(tri.a, 0);
         assert_eq!(tri.b, 1);
         assert_eq!(tri.c, 2);
@@ -349,7 +1318,8 @@ mod test {
         let maybe_tri = parse_face(face_str);
         assert!(maybe_tri.is_some());
 
-        let tri = maybe_tri.unwrap();
+        let (tri, has_normals) = maybe_tri.unwrap();
+        assert!(has_normals);
         assert_eq!(tri.a, 0);
         assert_eq!(tri.b, 1);
         assert_eq!(tri.c, 2);
@@ -369,4 +1339,40 @@ mod test {
         let maybe_tri = parse_face(face_str);
         assert!(maybe_tri.is_none());
     }
+
+    #[test]
+    fn test_parse_obj_in_chunks() {
+        // smoothing groups change partway and faces sit on both sides of every chunk boundary
+        let content = "mtllib first.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nvt 0 0\nvn 0 0 1\n\
+                       f 1 2 3\ns 2\nf 2 4 3\nf 1 2 4\n# a comment\ns off\nf 1 3 4\n\
+                       l 1 2 3\nmtllib second.mtl\nf 3 2 1\ns 1\nf 4 2 1\n";
+        let lines: Vec<&str> = content.lines().collect();
+        let whole = parse_obj_lines(&lines, 1).unwrap();
+        assert_eq!(whole.face_indicies.len(), 6);
+        assert_eq!(
+            whole.face_groups,
+            vec![
+                None,
+                Some(Some(2)),
+                Some(Some(2)),
+                Some(None),
+                Some(None),
+                Some(Some(1))
+            ]
+        );
+        assert_eq!(whole.last_group, Some(Some(1)));
+        assert_eq!(whole.material_lib, Some("second.mtl".to_string()));
+
+        for num_chunks in 2..=lines.len() + 1 {
+            let chunked = parse_obj_lines(&lines, num_chunks).unwrap();
+            assert_eq!(chunked, whole, "split into {num_chunks} chunks");
+        }
+
+        // an error in any chunk fails the whole parse
+        let mut broken = lines.clone();
+        broken[14] = "f 1 2 x";
+        for num_chunks in 1..=4 {
+            assert!(parse_obj_lines(&broken, num_chunks).is_err());
+        }
+    }
 }
@@ -0,0 +1,591 @@
+/*
+ * A small PNG decoder and encoder. Decoding supports every color type and bit depth in the spec
+ * but not interlaced images, encoding always writes 8 bit RGB with uncompressed deflate blocks.
+ */
+use crate::image::Image;
+use crate::math::Color;
+use core::fmt;
+use std::error::Error;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+#[derive(Debug)]
+pub struct PNGLoadError {
+    pub msg: String,
+}
+impl Error for PNGLoadError {}
+
+impl fmt::Display for PNGLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed Loading PNG Image With Error {}", self.msg,)
+    }
+}
+
+fn png_error(msg: &str) -> PNGLoadError {
+    PNGLoadError {
+        msg: msg.to_string(),
+    }
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<Image, PNGLoadError> {
+    if bytes.len() < PNG_SIGNATURE.len() || bytes[..PNG_SIGNATURE.len()] != PNG_SIGNATURE {
+        return Err(png_error("file does not start with the PNG signature"));
+    }
+
+    let mut header: Option<PNGHeader> = None;
+    let mut palette: Vec<Color> = vec![];
+    let mut compressed: Vec<u8> = vec![];
+    let mut seen_end = false;
+
+    // walk the chunks, we only care about IHDR, PLTE, IDAT, and IEND
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < bytes.len() && !seen_end {
+        if pos + 8 > bytes.len() {
+            return Err(png_error("chunk header is truncated"));
+        }
+        let length = read_u32_be(&bytes[pos..pos + 4]) as usize;
+        let chunk_type = &bytes[pos + 4..pos + 8];
+        let data_start = pos + 8;
+        let data_end = data_start + length;
+        if data_end + 4 > bytes.len() {
+            return Err(png_error("chunk data is truncated"));
+        }
+        let data = &bytes[data_start..data_end];
+        if crc32(&bytes[pos + 4..data_end]) != read_u32_be(&bytes[data_end..data_end + 4]) {
+            return Err(png_error("chunk CRC does not match its contents"));
+        }
+
+        match chunk_type {
+            b"IHDR" => header = Some(parse_header(data)?),
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| Color {
+                        r: rgb[0],
+                        g: rgb[1],
+                        b: rgb[2],
+                    })
+                    .collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => seen_end = true,
+            _ => {}
+        }
+        pos = data_end + 4;
+    }
+
+    let header = header.ok_or(png_error("file did not contain an IHDR chunk"))?;
+    if !seen_end {
+        return Err(png_error("file did not contain an IEND chunk"));
+    }
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(png_error("palette image did not contain a PLTE chunk"));
+    }
+
+    let raw = zlib_decompress(&compressed)?;
+    let scanlines = unfilter_scanlines(&header, &raw)?;
+    Ok(expand_pixels(&header, &scanlines, &palette))
+}
+
+pub fn encode_png(image: &Image) -> Vec<u8> {
+    let mut ret = PNG_SIGNATURE.to_vec();
+
+    let mut header = vec![];
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // 8 bit depth, RGB, deflate, adaptive filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut ret, b"IHDR", &header);
+
+    // every scanline uses filter type 0 (none)
+    let mut raw = Vec::with_capacity(image.height * (image.width * 3 + 1));
+    for row in image.data.chunks(image.width.max(1)) {
+        raw.push(0);
+        for pixel in row {
+            raw.extend_from_slice(&[pixel.r, pixel.g, pixel.b]);
+        }
+    }
+    write_chunk(&mut ret, b"IDAT", &zlib_store(&raw));
+    write_chunk(&mut ret, b"IEND", &[]);
+    ret
+}
+
+struct PNGHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl PNGHeader {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+}
+
+fn parse_header(data: &[u8]) -> Result<PNGHeader, PNGLoadError> {
+    if data.len() != 13 {
+        return Err(png_error("IHDR chunk has the wrong length"));
+    }
+
+    let header = PNGHeader {
+        width: read_u32_be(&data[0..4]) as usize,
+        height: read_u32_be(&data[4..8]) as usize,
+        bit_depth: data[8],
+        color_type: data[9],
+    };
+
+    let valid_depths: &[u8] = match header.color_type {
+        0 => &[1, 2, 4, 8, 16],
+        3 => &[1, 2, 4, 8],
+        2 | 4 | 6 => &[8, 16],
+        _ => return Err(png_error("IHDR chunk has an unknown color type")),
+    };
+    if !valid_depths.contains(&header.bit_depth) {
+        return Err(png_error(
+            "IHDR chunk has an invalid bit depth for its color type",
+        ));
+    }
+    if data[10] != 0 || data[11] != 0 {
+        return Err(png_error("unknown compression or filter method"));
+    }
+    if data[12] != 0 {
+        return Err(png_error("interlaced images are not supported"));
+    }
+
+    Ok(header)
+}
+
+// reverses the per scanline filters, returning the scanlines without their filter type bytes
+fn unfilter_scanlines(header: &PNGHeader, raw: &[u8]) -> Result<Vec<u8>, PNGLoadError> {
+    // the dimensions come straight from the file, so the sizes they give may not fit in a usize
+    let Some((stride, filtered_len)) =
+        header
+            .width
+            .checked_mul(header.bits_per_pixel())
+            .and_then(|bits| {
+                let stride = bits.div_ceil(8);
+                Some((stride, header.height.checked_mul(stride + 1)?))
+            })
+    else {
+        return Err(png_error("image dimensions are too large"));
+    };
+    let bpp = usize::max(header.bits_per_pixel() / 8, 1);
+    if raw.len() < filtered_len {
+        return Err(png_error("image data is smaller than the image dimensions"));
+    }
+
+    let mut ret = vec![0u8; header.height * stride];
+    for y in 0..header.height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[(y * (stride + 1) + 1)..((y + 1) * (stride + 1))];
+        for x in 0..stride {
+            let a = if x >= bpp {
+                ret[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 { ret[(y - 1) * stride + x] } else { 0 };
+            let c = if x >= bpp && y > 0 {
+                ret[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth_predictor(a, b, c),
+                _ => return Err(png_error("scanline has an unknown filter type")),
+            };
+            ret[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+    Ok(ret)
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// converts unfiltered scanlines into 8 bit RGB, alpha is dropped and 16 bit samples are truncated
+fn expand_pixels(header: &PNGHeader, scanlines: &[u8], palette: &[Color]) -> Image {
+    let stride = (header.width * header.bits_per_pixel()).div_ceil(8);
+    let depth = header.bit_depth as usize;
+    let mut ret = Image::new(header.width, header.height);
+
+    // reads the nth sample of a scanline scaled to 8 bits (palette indices are left unscaled)
+    let sample = |line: &[u8], n: usize| -> u8 {
+        match depth {
+            8 => line[n],
+            16 => line[n * 2],
+            _ => {
+                let bit = n * depth;
+                let shift = 8 - depth - (bit % 8);
+                let value = (line[bit / 8] >> shift) & ((1 << depth) - 1) as u8;
+                if header.color_type == 3 {
+                    value
+                } else {
+                    (value as u16 * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    };
+
+    for y in 0..header.height {
+        let line = &scanlines[(y * stride)..((y + 1) * stride)];
+        for x in 0..header.width {
+            let channels = header.channels();
+            ret.data[(y * header.width) + x] = match header.color_type {
+                2 | 6 => Color {
+                    r: sample(line, x * channels),
+                    g: sample(line, x * channels + 1),
+                    b: sample(line, x * channels + 2),
+                },
+                3 => palette
+                    .get(sample(line, x) as usize)
+                    .copied()
+                    .unwrap_or_default(),
+                _ => {
+                    let gray = sample(line, x * channels);
+                    Color {
+                        r: gray,
+                        g: gray,
+                        b: gray,
+                    }
+                }
+            };
+        }
+    }
+    ret
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = out.len();
+    out.extend_from_slice(chunk_type);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[crc_start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn read_u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// wraps the data in a zlib stream made of uncompressed deflate blocks
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut ret = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        ret.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        ret.push(is_final as u8);
+        ret.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        ret.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        ret.extend_from_slice(block);
+    }
+    ret.extend_from_slice(&adler32(data).to_be_bytes());
+    ret
+}
+
+fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, PNGLoadError> {
+    if data.len() < 6 {
+        return Err(png_error("compressed image data is truncated"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || ((cmf as u16) << 8 | flg as u16) % 31 != 0 || flg & 0x20 != 0 {
+        return Err(png_error(
+            "compressed image data has an invalid zlib header",
+        ));
+    }
+
+    let mut reader = BitReader {
+        data: &data[2..],
+        pos: 0,
+        bit: 0,
+    };
+    let ret = inflate(&mut reader)?;
+
+    let checksum_start = 2 + reader.pos + usize::from(reader.bit != 0);
+    let checksum = data
+        .get(checksum_start..checksum_start + 4)
+        .ok_or(png_error("compressed image data is missing its checksum"))?;
+    if read_u32_be(checksum) != adler32(&ret) {
+        return Err(png_error("compressed image data checksum does not match"));
+    }
+    Ok(ret)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl BitReader<'_> {
+    fn read_bits(&mut self, count: u32) -> Result<u32, PNGLoadError> {
+        let mut ret = 0;
+        for i in 0..count {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or(png_error("compressed image data ended early"))?;
+            ret |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(ret)
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// canonical huffman code stored as the number of codes of each length and the symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader<'_>) -> Result<u16, PNGLoadError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(png_error(
+            "compressed image data has an invalid huffman code",
+        ))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order code length code lengths are stored in for dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn inflate(reader: &mut BitReader<'_>) -> Result<Vec<u8>, PNGLoadError> {
+    let mut ret = vec![];
+    loop {
+        let is_final = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => inflate_stored_block(reader, &mut ret)?,
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5u8; 30]);
+                inflate_huffman_block(reader, &literals, &distances, &mut ret)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(reader)?;
+                inflate_huffman_block(reader, &literals, &distances, &mut ret)?;
+            }
+            _ => return Err(png_error("compressed image data has an invalid block type")),
+        }
+        if is_final {
+            return Ok(ret);
+        }
+    }
+}
+
+fn inflate_stored_block(reader: &mut BitReader<'_>, out: &mut Vec<u8>) -> Result<(), PNGLoadError> {
+    reader.align_to_byte();
+    let header = reader
+        .data
+        .get(reader.pos..reader.pos + 4)
+        .ok_or(png_error("compressed image data ended early"))?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    if length != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(png_error(
+            "stored block length does not match its complement",
+        ));
+    }
+    let start = reader.pos + 4;
+    let block = reader
+        .data
+        .get(start..start + length as usize)
+        .ok_or(png_error("compressed image data ended early"))?;
+    out.extend_from_slice(block);
+    reader.pos = start + length as usize;
+    Ok(())
+}
+
+fn read_dynamic_tables(reader: &mut BitReader<'_>) -> Result<(Huffman, Huffman), PNGLoadError> {
+    let num_literals = reader.read_bits(5)? as usize + 257;
+    let num_distances = reader.read_bits(5)? as usize + 1;
+    let num_code_lengths = reader.read_bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &idx in CODE_LENGTH_ORDER.iter().take(num_code_lengths) {
+        code_lengths[idx] = reader.read_bits(3)? as u8;
+    }
+    let code_length_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < num_literals + num_distances {
+        let symbol = code_length_huffman.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or(png_error("code length repeat has nothing to repeat"))?;
+                (previous, 3 + reader.read_bits(2)?)
+            }
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != num_literals + num_distances {
+        return Err(png_error("code lengths overflow the dynamic tables"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..num_literals]),
+        Huffman::new(&lengths[num_literals..]),
+    ))
+}
+
+fn inflate_huffman_block(
+    reader: &mut BitReader<'_>,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+) -> Result<(), PNGLoadError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let length_idx = symbol - 257;
+                if length_idx >= LENGTH_BASE.len() {
+                    return Err(png_error("compressed image data has an invalid length"));
+                }
+                let length = LENGTH_BASE[length_idx] as usize
+                    + reader.read_bits(LENGTH_EXTRA[length_idx] as u32)? as usize;
+
+                let distance_idx = distances.decode(reader)? as usize;
+                if distance_idx >= DISTANCE_BASE.len() {
+                    return Err(png_error("compressed image data has an invalid distance"));
+                }
+                let distance = DISTANCE_BASE[distance_idx] as usize
+                    + reader.read_bits(DISTANCE_EXTRA[distance_idx] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(png_error("compressed image data refers before its start"));
+                }
+
+                // copies may overlap what they are writing so go byte by byte
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
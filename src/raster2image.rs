@@ -1,54 +1,402 @@
+use rasterboy::anim::render_turntable;
 use rasterboy::image::*;
+use rasterboy::math::Color;
+use rasterboy::png::encode_png;
+use rasterboy::rasterizer::RenderOptions;
 use rasterboy::scene::*;
 use std::env;
+use std::io;
+use std::io::Write;
 use std::path::Path;
+use std::thread;
+
+const HELP: &str = "Invalid arguments. Usage is:\n\
+    raster2image [FILE...] [OPTION...]\n\
+    \n\
+    Pass - as the FILE to read the scene from stdin, .gltf and .glb files are imported when built with the gltf feature\n\
+    \n\
+    Application Options:\n\
+    -o [OUTPUT_FILE]\t writes output to a file at the given path, - writes a PPM to stdout. Defaults to output.ppm\n\
+    --format [FORMAT]\t writes the output as png, ppm (plain text P3), or ppm-binary (P6) instead of picking from the -o extension\n\
+    --turntable [N]\t renders N frames orbiting the scene as frame_0000.ppm, frame_0001.ppm, etc. into the directory given by -o (defaults to the current directory)\n\
+    --depth [DEPTH_FILE]\t also writes the depth buffer as a grayscale image, black on the camera's near plane and white on its far plane\n\
+    --clear-color [R G B]\t fills the background with the given color before rendering. Defaults to black\n\
+    --dither\t breaks up banding in smooth gradients with ordered dithering, off by default so output stays exact\n\
+    --fxaa\t smooths jagged edges with a cheap post pass over the finished image, also softening sharp texture detail\n\
+    --auto-camera\t ignores the scene's camera placement and looks at the whole scene from the front instead, keeping the camera's fov and canvas\n\
+    --verbose\t prints a summary of the loaded scene to stderr: models, verticies, triangles, textures, lights, and resolution\n\
+    --threads [N]\t renders using N threads. Defaults to the number of logical cores, 1 renders deterministically on a single thread";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum OutputFormat {
+    Png,
+    Ppm,
+    PpmBinary,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "png" => Some(OutputFormat::Png),
+            "ppm" => Some(OutputFormat::Ppm),
+            "ppm-binary" => Some(OutputFormat::PpmBinary),
+            _ => None,
+        }
+    }
+
+    // --format wins, otherwise the extension decides and stdout gets a plain text PPM
+    fn for_output(output_file: &str, format: Option<OutputFormat>) -> Result<OutputFormat, String> {
+        if let Some(format) = format {
+            return Ok(format);
+        }
+        if output_file == "-" {
+            return Ok(OutputFormat::Ppm);
+        }
+        let extension = Path::new(output_file)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => Ok(OutputFormat::Png),
+            Some("ppm") => Ok(OutputFormat::Ppm),
+            _ => Err(format!(
+                "Cannot tell the output format of {output_file}, use a .png or .ppm extension or pass --format"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct CliArgs {
+    input_file: String,
+    output_file: Option<String>,
+    output_format: Option<OutputFormat>,
+    turntable_frames: Option<usize>,
+    depth_file: Option<String>,
+    clear_color: Option<Color>,
+    dithering: bool,
+    fxaa: bool,
+    auto_camera: bool,
+    verbose: bool,
+    threads: usize,
+}
+
+// expects the program name to already be skipped
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<CliArgs> {
+    let mut cli_args = CliArgs {
+        input_file: String::default(),
+        output_file: None,
+        output_format: None,
+        turntable_frames: None,
+        depth_file: None,
+        clear_color: None,
+        dithering: false,
+        fxaa: false,
+        auto_camera: false,
+        verbose: false,
+        threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => cli_args.output_file = Some(args.next()?),
+            "--format" => cli_args.output_format = Some(OutputFormat::from_name(&args.next()?)?),
+            "--turntable" => cli_args.turntable_frames = Some(args.next()?.parse().ok()?),
+            "--depth" => cli_args.depth_file = Some(args.next()?),
+            "--clear-color" => {
+                let mut channel = || args.next()?.parse::<u8>().ok();
+                cli_args.clear_color = Some(Color {
+                    r: channel()?,
+                    g: channel()?,
+                    b: channel()?,
+                });
+            }
+            "--dither" => cli_args.dithering = true,
+            "--fxaa" => cli_args.fxaa = true,
+            "--auto-camera" => cli_args.auto_camera = true,
+            "--verbose" => cli_args.verbose = true,
+            "--threads" => {
+                cli_args.threads = args.next()?.parse().ok()?;
+                if cli_args.threads == 0 {
+                    return None;
+                }
+            }
+            _ => cli_args.input_file = arg,
+        }
+    }
+
+    if cli_args.input_file.is_empty() {
+        None
+    } else {
+        Some(cli_args)
+    }
+}
+
+#[cfg(feature = "gltf")]
+fn load_gltf_scene(path: &str) -> Result<Scene, Box<dyn std::error::Error>> {
+    Scene::load_from_gltf(path)
+}
+
+#[cfg(not(feature = "gltf"))]
+fn load_gltf_scene(_path: &str) -> Result<Scene, Box<dyn std::error::Error>> {
+    unreachable!("glTF scenes need the gltf feature")
+}
+
+// writes to stdout when output_file is -
+fn write_image(
+    image: &Image,
+    output_file: &str,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match (output_file, format) {
+        ("-", OutputFormat::Png) => io::stdout()
+            .lock()
+            .write_all(&encode_png(image))
+            .map_err(|why| why.into()),
+        ("-", OutputFormat::Ppm) => image.write_ppm(&mut io::stdout().lock()),
+        ("-", OutputFormat::PpmBinary) => image.write_ppm_binary(&mut io::stdout().lock()),
+        (path, OutputFormat::Png) => image.save_to_png(Path::new(path)),
+        (path, OutputFormat::Ppm) => image.save_to_ppm(Path::new(path)),
+        (path, OutputFormat::PpmBinary) => image.save_to_ppm_binary(Path::new(path)),
+    }
+}
 
 fn main() {
     // get path to scene and output file
-    let help = "Invalid arguments. Usage is:\nraster2image [FILE...] [OPTION...]\n\nApplication Options:\n-o [OUTPUT_FILE]\t writes output to a file at the given path. Defaults to output.ppm";
     let mut args = env::args();
-    if args.len() != 2 && args.len() != 4 {
-        println!("{help}");
+    args.next(); // skip program name
+    let Some(cli_args) = parse_args(args) else {
+        println!("{HELP}");
         return;
+    };
+
+    let options = RenderOptions {
+        threads: cli_args.threads,
+        clear_color: cli_args.clear_color,
+        dithering: cli_args.dithering,
+        fxaa: cli_args.fxaa,
+        ..Default::default()
+    };
+
+    // load scene from disk or stdin
+    let mut scene = if cli_args.input_file == "-" {
+        Scene::load_from_reader(io::stdin().lock())
+    } else if cfg!(feature = "gltf")
+        && (cli_args.input_file.ends_with(".gltf") || cli_args.input_file.ends_with(".glb"))
+    {
+        load_gltf_scene(&cli_args.input_file)
+    } else {
+        Scene::load_from_file(&cli_args.input_file)
+    }
+    .expect("could not load scene file");
+    if cli_args.verbose {
+        eprintln!("{}", scene.summary());
+    }
+    if cli_args.auto_camera {
+        let mut camera = scene.camera;
+        camera.frame_scene(&scene);
+        scene.camera = camera;
     }
 
-    let mut output_file: String = "output.ppm".to_string();
-    let mut input_file: String = String::default();
-    args.next().expect(help); // skip program name
-    loop {
-        match args.next() {
-            Some(path) => {
-                if path == "-o" {
-                    output_file = args.next().expect(help);
-                } else {
-                    input_file = path;
-                }
-            }
-            None => {
-                break;
-            }
+    if let Some(num_frames) = cli_args.turntable_frames {
+        let output_dir = cli_args.output_file.unwrap_or(".".to_string());
+        if output_dir == "-" {
+            panic!("Turntable frames cannot be written to stdout");
         }
+        if let Err(why) = render_turntable(&scene, num_frames, options, Path::new(&output_dir)) {
+            panic!("Could not write turntable frames to disk because of error: {why}");
+        }
+        return;
     }
-
-    // load scene from disk
-    let scene = Scene::load_from_file(&input_file).expect("could not load scene file");
+    let output_file = cli_args.output_file.unwrap_or("output.ppm".to_string());
+    let output_format = match OutputFormat::for_output(&output_file, cli_args.output_format) {
+        Ok(format) => format,
+        Err(why) => panic!("{why}"),
+    };
+    // the depth image always goes by its own extension, --format is for the color output
+    let depth_format = match &cli_args.depth_file {
+        Some(depth_file) if depth_file == "-" => {
+            panic!("The depth image cannot be written to stdout")
+        }
+        Some(depth_file) => match OutputFormat::for_output(depth_file, None) {
+            Ok(format) => Some(format),
+            Err(why) => panic!("{why}"),
+        },
+        None => None,
+    };
 
     // create color and depth buffers
     let image_width = scene.camera.canvas_width as usize;
     let image_height = scene.camera.canvas_height as usize;
     let num_pixels = image_width * image_height;
-    let output_path = Path::new(&output_file);
     let mut output_image = Image::new(image_width, image_height);
     let mut depth_buffer = vec![f32::MAX; num_pixels];
 
     // render
-    scene.render(&mut output_image.data, &mut depth_buffer);
+    scene.render(options, &mut output_image.data, &mut depth_buffer);
 
-    // write image to disk
-    if let Err(why) = output_image.save_to_ppm(output_path) {
+    // write image to disk or stdout
+    if let Err(why) = write_image(&output_image, &output_file, output_format) {
         panic!(
             "Could not write output image to disk because of error: {}",
             why
         );
     }
+    if let (Some(depth_file), Some(depth_format)) = (&cli_args.depth_file, depth_format) {
+        let depth_image = scene.camera.depth_image(&depth_buffer);
+        if let Err(why) = write_image(&depth_image, depth_file, depth_format) {
+            panic!(
+                "Could not write depth image to disk because of error: {}",
+                why
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_threads() {
+        let maybe_args = parse_args(args(&["scene.xml", "--threads", "3", "-o", "out.ppm"]));
+        assert_eq!(
+            maybe_args,
+            Some(CliArgs {
+                input_file: "scene.xml".to_string(),
+                output_file: Some("out.ppm".to_string()),
+                output_format: None,
+                turntable_frames: None,
+                depth_file: None,
+                clear_color: None,
+                dithering: false,
+                fxaa: false,
+                auto_camera: false,
+                verbose: false,
+                threads: 3,
+            })
+        );
+
+        // defaults to every logical core
+        let maybe_args = parse_args(args(&["scene.xml"]));
+        assert!(maybe_args.is_some());
+        assert_eq!(
+            maybe_args.unwrap().threads,
+            thread::available_parallelism().unwrap().get()
+        );
+    }
+
+    #[test]
+    fn test_parse_pipes() {
+        let maybe_args = parse_args(args(&["-", "-o", "-"]));
+        assert!(maybe_args.is_some());
+        let cli_args = maybe_args.unwrap();
+        assert_eq!(cli_args.input_file, "-");
+        assert_eq!(cli_args.output_file, Some("-".to_string()));
+    }
+
+    #[test]
+    fn test_output_format() {
+        let format_of = |output_file: &str, format: Option<OutputFormat>| {
+            OutputFormat::for_output(output_file, format)
+        };
+        assert_eq!(format_of("out.png", None), Ok(OutputFormat::Png));
+        assert_eq!(format_of("renders/OUT.PPM", None), Ok(OutputFormat::Ppm));
+        assert_eq!(format_of("-", None), Ok(OutputFormat::Ppm));
+        assert!(format_of("out.jpg", None).is_err());
+        assert!(format_of("out", None).is_err());
+
+        // --format overrides whatever the extension says
+        let cli_args = parse_args(args(&[
+            "scene.xml",
+            "-o",
+            "out.jpg",
+            "--format",
+            "ppm-binary",
+        ]));
+        let format = cli_args.unwrap().output_format;
+        assert_eq!(format, Some(OutputFormat::PpmBinary));
+        assert_eq!(format_of("out.jpg", format), Ok(OutputFormat::PpmBinary));
+        assert!(parse_args(args(&["scene.xml", "--format", "jpg"])).is_none());
+        assert!(parse_args(args(&["scene.xml", "--format"])).is_none());
+    }
+
+    #[test]
+    fn test_parse_clear_color() {
+        let maybe_args = parse_args(args(&["scene.xml", "--clear-color", "10", "20", "255"]));
+        assert_eq!(
+            maybe_args.unwrap().clear_color,
+            Some(Color {
+                r: 10,
+                g: 20,
+                b: 255
+            })
+        );
+        assert!(parse_args(args(&["scene.xml", "--clear-color", "10", "20"])).is_none());
+        assert!(parse_args(args(&["scene.xml", "--clear-color", "10", "20", "256"])).is_none());
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        let maybe_args = parse_args(args(&[
+            "scene.xml",
+            "--depth",
+            "depth.ppm",
+            "-o",
+            "out.png",
+        ]));
+        let cli_args = maybe_args.unwrap();
+        assert_eq!(cli_args.depth_file, Some("depth.ppm".to_string()));
+        assert_eq!(cli_args.output_file, Some("out.png".to_string()));
+        assert_eq!(parse_args(args(&["scene.xml"])).unwrap().depth_file, None);
+        assert!(parse_args(args(&["scene.xml", "--depth"])).is_none());
+    }
+
+    #[test]
+    fn test_parse_dither() {
+        let maybe_args = parse_args(args(&["scene.xml", "--dither", "-o", "out.ppm"]));
+        assert!(maybe_args.unwrap().dithering);
+        assert!(!parse_args(args(&["scene.xml"])).unwrap().dithering);
+    }
+
+    #[test]
+    fn test_parse_auto_camera() {
+        assert!(
+            parse_args(args(&["scene.xml", "--auto-camera"]))
+                .unwrap()
+                .auto_camera
+        );
+        assert!(!parse_args(args(&["scene.xml"])).unwrap().auto_camera);
+    }
+
+    #[test]
+    fn test_parse_verbose() {
+        assert!(
+            parse_args(args(&["--verbose", "scene.xml"]))
+                .unwrap()
+                .verbose
+        );
+        assert!(!parse_args(args(&["scene.xml"])).unwrap().verbose);
+    }
+
+    #[test]
+    fn test_parse_fxaa() {
+        assert!(parse_args(args(&["scene.xml", "--fxaa"])).unwrap().fxaa);
+        assert!(!parse_args(args(&["scene.xml"])).unwrap().fxaa);
+    }
+
+    #[test]
+    fn test_parse_invalid_threads() {
+        assert!(parse_args(args(&["scene.xml", "--threads"])).is_none());
+        assert!(parse_args(args(&["scene.xml", "--threads", "zero"])).is_none());
+        assert!(parse_args(args(&["scene.xml", "--threads", "0"])).is_none());
+        assert!(parse_args(args(&["--threads", "2"])).is_none());
+    }
 }
@@ -1,155 +1,1644 @@
+use crate::image::*;
 use crate::math::*;
 use crate::mesh::*;
 use crate::scene::*;
 
 use std::cmp::max;
 use std::cmp::min;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+/*
+ * Options that tweak how draw_mesh fills pixels. The default reproduces a plain full-frame render.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RenderOptions {
+    // (x0, y0, x1, y1) in pixels, x0/y0 inclusive and x1/y1 exclusive. Pixels outside of this
+    // rectangle are never written to.
+    pub scissor: Option<(i32, i32, i32, i32)>,
+    // sample textures from a mipmap chain with a per-triangle level of detail instead of always
+    // sampling the full resolution texture
+    pub trilinear_filtering: bool,
+    // how the full resolution texture is sampled when trilinear_filtering is off
+    pub texture_filter: Filter,
+    // number of threads Scene::render splits the frame across, 0 or 1 renders on the calling thread
+    pub threads: usize,
+    // interpolate texture coordinates linearly in screen space instead of perspective correctly,
+    // which gives textures the wobbly look of early 3D consoles
+    pub affine_texture_mapping: bool,
+    // blend pixels just outside of a triangle's edges by how close they are to the edge. This only
+    // smooths silhouettes, places where two triangles intersect each other stay aliased since the
+    // depth buffer still holds a single depth per pixel.
+    pub edge_antialiasing: bool,
+    // shade every pixel whose square overlaps a triangle rather than only the pixels whose sample
+    // point is inside of it, so thin and tiny triangles never vanish. Meant for coverage and
+    // silhouette passes since triangles sharing an edge will both shade the pixels along it.
+    pub conservative: bool,
+    // added to every fragment's depth before the depth test and write, so coplanar surfaces like
+    // decals can be nudged in front (negative) or behind (positive) without moving on screen. The
+    // depth buffer holds NDC depth, so this is in NDC units where -1 is the near plane and 1 is
+    // the far plane.
+    pub depth_bias: f32,
+    // offset each pixel by a 4x4 Bayer matrix before quantizing to 8 bits, breaking up banding in
+    // smooth gradients. Off by default since it changes exact output.
+    pub dithering: bool,
+    // flip normals that point away from the camera before lighting, so meshes with inconsistent
+    // winding or open surfaces seen from behind aren't shaded black
+    pub two_sided_lighting: bool,
+    // draw the triangles of transparent meshes (see Material::opacity) from farthest to nearest so
+    // they blend in back to front order, and Scene::render draws transparent models that way too.
+    // Opaque geometry doesn't need it since the depth buffer sorts it out.
+    pub depth_sort_transparent: bool,
+    // run Image::apply_fxaa over the finished frame (inside of the scissor), a cheap way to soften
+    // jagged edges that also softens sharp texture detail
+    pub fxaa: bool,
+    // fill the depth buffer with every opaque triangle first and only then shade, and only the
+    // fragments that end up visible, so overdraw costs a depth test rather than texturing and
+    // lighting. Worth it for scenes with lots of overlapping geometry, otherwise it's two passes
+    // over the triangles for nothing. Scene::render does the depth pass over every model at once.
+    pub depth_prepass: bool,
+    // linear light added once to every vertex on top of what the lights contribute, so surfaces
+    // no light reaches aren't pitch black. None means none at all when drawing a mesh, while
+    // Scene::render takes Scene::ambient instead.
+    pub ambient: Option<Vector3>,
+    // Scene::render fills the color and/or depth buffer with these before drawing anything. Leave
+    // one as None to keep that buffer from an earlier pass, e.g. keeping the opaque pass's depth
+    // while drawing a transparent pass. Like every other write, this stays inside the scissor.
+    pub clear_color: Option<Color>,
+    pub clear_depth: Option<f32>,
+    // fill every fragment (and line and point) with this color instead of texturing and lighting
+    // it, for silhouette and ID passes. Fragments are still depth and alpha tested but are always
+    // opaque. Scene::render takes this from Model::color_override for models that set one.
+    pub color_override: Option<Color>,
+}
+
+// counts of the work a draw did, to see what options like depth_prepass save
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RenderStats {
+    // fragments that passed the depth and alpha tests and were shaded, a pixel drawn over several
+    // times counts every time
+    pub pixels_shaded: usize,
+}
+
+impl AddAssign for RenderStats {
+    fn add_assign(&mut self, other: RenderStats) {
+        self.pixels_shaded += other.pixels_shaded;
+    }
+}
+
+// which part of drawing a fill does, see RenderOptions::depth_prepass
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Pass {
+    // depth test, shade and write everything in one go
+    Full,
+    // only write the depth of the fragments a full pass would write depth for
+    Depth,
+    // shade the fragments that won their pixel in a depth pass
+    Shade,
+}
+
+impl RenderOptions {
+    // these options with the scissor set to the width by height rectangle whose top left is (x, y)
+    pub fn with_scissor_rect(self, x: i32, y: i32, width: i32, height: i32) -> RenderOptions {
+        RenderOptions {
+            scissor: Some((x, y, x + width, y + height)),
+            ..self
+        }
+    }
+}
+
+pub fn clear_color(buffer: &mut [Color], color: Color) {
+    buffer.fill(color);
+}
+
+// f32::MAX is what a fresh depth buffer holds, anything drawn passes the depth test against it
+pub fn clear_depth(buffer: &mut [f32], value: f32) {
+    buffer.fill(value);
+}
+
+// applies options.clear_color and options.clear_depth, limited to the scissor if there is one
+pub(crate) fn clear_buffers(
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    let width = camera.canvas_width.max(0);
+    let height = camera.canvas_height.max(0);
+    let (x0, y0, x1, y1) = match options.scissor {
+        Some((x0, y0, x1, y1)) => (x0.max(0), y0.max(0), x1.min(width), y1.min(height)),
+        None => (0, 0, width, height),
+    };
+    if x0 >= x1 {
+        return;
+    }
+    for y in y0..y1 {
+        let row = (y * width + x0) as usize..(y * width + x1) as usize;
+        if let Some(color) = options.clear_color {
+            clear_color(&mut pixel_buffer[row.clone()], color);
+        }
+        if let Some(depth) = options.clear_depth {
+            clear_depth(&mut depth_buffer[row], depth);
+        }
+    }
+}
+
+/*
+ * Per-pixel surface attributes written alongside the color buffer, for lighting or SSAO done as a
+ * separate pass afterwards. Only fragments that also write depth are stored, so blended edges and
+ * transparent surfaces leave the surface behind them in place, as do lines and points. Pixels
+ * nothing was drawn to keep what they were created with.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct GBuffer {
+    // interpolated world space normals, unit length
+    pub normals: Vec<Vector3>,
+    // interpolated world space positions
+    pub positions: Vec<Vector3>,
+    // texture or diffuse color tinted by vertex colors, before lighting and emission
+    pub albedo: Vec<Color>,
+}
+
+impl GBuffer {
+    // a width by height G-buffer of zero normals and positions and black albedo
+    pub fn new(width: usize, height: usize) -> GBuffer {
+        GBuffer {
+            normals: vec![Vector3::default(); width * height],
+            positions: vec![Vector3::default(); width * height],
+            albedo: vec![Color::default(); width * height],
+        }
+    }
+}
+
+// tiles are square, this many pixels on a side
+pub const TILE_SIZE: i32 = 32;
 
 pub fn draw_mesh(
     mesh: &Mesh,
     transform: Mat4,
     lights: &[Light],
     camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) -> RenderStats {
+    draw_mesh_into(
+        mesh,
+        transform,
+        lights,
+        camera,
+        options,
+        pixel_buffer,
+        depth_buffer,
+        None,
+    )
+}
+
+// draw_mesh that also fills gbuffer, which has to be the same size as the other buffers
+#[allow(clippy::too_many_arguments)]
+pub fn draw_mesh_with_gbuffer(
+    mesh: &Mesh,
+    transform: Mat4,
+    lights: &[Light],
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+    gbuffer: &mut GBuffer,
+) -> RenderStats {
+    draw_mesh_into(
+        mesh,
+        transform,
+        lights,
+        camera,
+        options,
+        pixel_buffer,
+        depth_buffer,
+        Some(gbuffer),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_mesh_into(
+    mesh: &Mesh,
+    transform: Mat4,
+    lights: &[Light],
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+    mut gbuffer: Option<&mut GBuffer>,
+) -> RenderStats {
+    let mut unshaded = if options.depth_prepass {
+        vec![false; depth_buffer.len()]
+    } else {
+        Vec::new()
+    };
+    let mut draw = |pass: Pass| {
+        draw_mesh_pass(
+            mesh,
+            transform,
+            lights,
+            camera,
+            options,
+            pass,
+            pixel_buffer,
+            depth_buffer,
+            &mut unshaded,
+            gbuffer.as_deref_mut(),
+        )
+    };
+    if options.depth_prepass {
+        draw(Pass::Depth);
+        draw(Pass::Shade)
+    } else {
+        draw(Pass::Full)
+    }
+}
+
+/*
+ * draw_mesh doing only the given pass, lines and points are drawn with the shading. unshaded is
+ * shared by a depth pass and the shading pass after it, see fill_triangle, and can be empty for a
+ * full pass.
+ */
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_mesh_pass(
+    mesh: &Mesh,
+    transform: Mat4,
+    lights: &[Light],
+    camera: Camera,
+    options: RenderOptions,
+    pass: Pass,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+    unshaded: &mut [bool],
+    mut gbuffer: Option<&mut GBuffer>,
+) -> RenderStats {
+    let mipmap = if options.trilinear_filtering {
+        mesh.material.diffuse_mipmap()
+    } else {
+        None
+    };
+
+    let frame = Region {
+        x0: 0,
+        y0: 0,
+        x1: camera.canvas_width,
+        y1: camera.canvas_height,
+    };
+    let mut stats = RenderStats::default();
+    for triangle in setup_triangles(mesh, transform, lights, camera, options, mipmap.as_deref()) {
+        stats += fill_triangle(
+            &triangle,
+            mesh,
+            mipmap.as_deref(),
+            options,
+            pass,
+            frame,
+            pixel_buffer,
+            depth_buffer,
+            unshaded,
+            gbuffer.as_deref_mut(),
+        );
+    }
+    if pass == Pass::Depth {
+        return stats;
+    }
+
+    // lines and points are unlit, they take the material's diffuse color as is
+    let color = options
+        .color_override
+        .unwrap_or(mesh.material.diffuse_color);
+    if !mesh.edges.is_empty() {
+        draw_lines(
+            mesh,
+            transform,
+            camera,
+            options,
+            color,
+            pixel_buffer,
+            depth_buffer,
+        );
+    }
+    if !mesh.points.is_empty() {
+        draw_point_elements(
+            mesh,
+            transform,
+            camera,
+            options,
+            color,
+            pixel_buffer,
+            depth_buffer,
+        );
+    }
+    stats
+}
+
+/*
+ * Draws several meshes with the frame split into TILE_SIZE tiles that are filled in parallel on
+ * options.threads threads. Every triangle is first set up and binned into the tiles its bounding
+ * box touches, then each tile is filled into a buffer owned by one thread and copied back. Tiles
+ * fill their triangles in the same order draw_mesh would, so the output matches drawing the meshes
+ * one after another. progress is called with the fraction of the work done after every tile,
+ * always from the calling thread, and only reaches 1.0 once the tiles are copied back.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn draw_meshes_tiled(
+    meshes: &[(&Mesh, Mat4)],
+    lights: &[Light],
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+    progress: &mut dyn FnMut(f32),
+) -> RenderStats {
+    draw_meshes_tiled_with_overrides(
+        meshes,
+        &vec![options.color_override; meshes.len()],
+        lights,
+        camera,
+        options,
+        pixel_buffer,
+        depth_buffer,
+        progress,
+    )
+}
+
+// draw_meshes_tiled with RenderOptions::color_override given for every mesh separately
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_meshes_tiled_with_overrides(
+    meshes: &[(&Mesh, Mat4)],
+    color_overrides: &[Option<Color>],
+    lights: &[Light],
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+    progress: &mut dyn FnMut(f32),
+) -> RenderStats {
+    let mipmaps: Vec<Option<Arc<Mipmap>>> = meshes
+        .iter()
+        .map(|(mesh, _)| {
+            if options.trilinear_filtering {
+                mesh.material.diffuse_mipmap()
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // setup phase, every triangle tagged with the mesh it came from
+    let triangles: Vec<(usize, ScreenTriangle)> = meshes
+        .iter()
+        .enumerate()
+        .flat_map(|(mesh_idx, (mesh, transform))| {
+            setup_triangles(
+                mesh,
+                *transform,
+                lights,
+                camera,
+                options,
+                mipmaps[mesh_idx].as_deref(),
+            )
+            .into_iter()
+            .map(move |triangle| (mesh_idx, triangle))
+        })
+        .collect();
+
+    // binning phase
+    let tiles_x = (camera.canvas_width + TILE_SIZE - 1) / TILE_SIZE;
+    let tiles_y = (camera.canvas_height + TILE_SIZE - 1) / TILE_SIZE;
+    let mut bins: Vec<Vec<usize>> = vec![Vec::new(); (tiles_x * tiles_y).max(0) as usize];
+    for (triangle_idx, (_, triangle)) in triangles.iter().enumerate() {
+        let (x0, y0, x1, y1) = triangle.bounds(options);
+        if x1 <= 0 || y1 <= 0 || x0 >= camera.canvas_width || y0 >= camera.canvas_height {
+            continue;
+        }
+        for tile_y in (y0.max(0) / TILE_SIZE)..=((y1 - 1).min(camera.canvas_height - 1) / TILE_SIZE)
+        {
+            for tile_x in
+                (x0.max(0) / TILE_SIZE)..=((x1 - 1).min(camera.canvas_width - 1) / TILE_SIZE)
+            {
+                bins[(tile_y * tiles_x + tile_x) as usize].push(triangle_idx);
+            }
+        }
+    }
+
+    // shading phase, threads take the next unclaimed tile until they run out
+    let next_tile = AtomicUsize::new(0);
+    let canvas_width = camera.canvas_width as usize;
+    let shared_pixels: &[Color] = pixel_buffer;
+    let shared_depths: &[f32] = depth_buffer;
+    let passes: &[Pass] = if options.depth_prepass {
+        &[Pass::Depth, Pass::Shade]
+    } else {
+        &[Pass::Full]
+    };
+    let filled_tiles: Vec<(Region, Vec<Color>, Vec<f32>, RenderStats)> = thread::scope(|scope| {
+        // workers report finished tiles back here so only this thread calls progress
+        let (tile_done, tiles_done) = mpsc::channel::<()>();
+        let (next_tile, bins, triangles, mipmaps) = (&next_tile, &bins, &triangles, &mipmaps);
+        let workers: Vec<_> = (0..options.threads.max(1))
+            .map(|_| {
+                let tile_done = tile_done.clone();
+                scope.spawn(move || {
+                    let mut filled = Vec::new();
+                    loop {
+                        let tile_idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if tile_idx >= bins.len() {
+                            return filled;
+                        }
+                        if bins[tile_idx].is_empty() {
+                            let _ = tile_done.send(());
+                            continue;
+                        }
+
+                        let tile_x = tile_idx as i32 % tiles_x;
+                        let tile_y = tile_idx as i32 / tiles_x;
+                        let region = Region {
+                            x0: tile_x * TILE_SIZE,
+                            y0: tile_y * TILE_SIZE,
+                            x1: ((tile_x + 1) * TILE_SIZE).min(camera.canvas_width),
+                            y1: ((tile_y + 1) * TILE_SIZE).min(camera.canvas_height),
+                        };
+
+                        // start from what is already in the frame
+                        let mut pixels = Vec::with_capacity(region.len());
+                        let mut depths = Vec::with_capacity(region.len());
+                        for y in region.y0..region.y1 {
+                            let row = (y as usize * canvas_width) + region.x0 as usize;
+                            let row = row..(row + region.width() as usize);
+                            pixels.extend_from_slice(&shared_pixels[row.clone()]);
+                            depths.extend_from_slice(&shared_depths[row]);
+                        }
+
+                        // with a depth prepass, every triangle in the tile is through the depth
+                        // pass before any of them are shaded
+                        let mut unshaded = if options.depth_prepass {
+                            vec![false; region.len()]
+                        } else {
+                            Vec::new()
+                        };
+                        let mut stats = RenderStats::default();
+                        for &pass in passes {
+                            for &triangle_idx in bins[tile_idx].iter() {
+                                let (mesh_idx, triangle) = &triangles[triangle_idx];
+                                stats += fill_triangle(
+                                    triangle,
+                                    meshes[*mesh_idx].0,
+                                    mipmaps[*mesh_idx].as_deref(),
+                                    RenderOptions {
+                                        color_override: color_overrides[*mesh_idx],
+                                        ..options
+                                    },
+                                    pass,
+                                    region,
+                                    &mut pixels,
+                                    &mut depths,
+                                    &mut unshaded,
+                                    None,
+                                );
+                            }
+                        }
+                        filled.push((region, pixels, depths, stats));
+                        let _ = tile_done.send(());
+                    }
+                })
+            })
+            .collect();
+        drop(tile_done);
+        // copying the tiles back counts as one more step, so the last tile stays short of 1.0
+        for (done, ()) in tiles_done.iter().enumerate() {
+            progress((done + 1) as f32 / (bins.len() + 1) as f32);
+        }
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    let mut stats = RenderStats::default();
+    for (region, pixels, depths, tile_stats) in filled_tiles {
+        stats += tile_stats;
+        let width = region.width() as usize;
+        for (row_idx, y) in (region.y0..region.y1).enumerate() {
+            let row = (y as usize * canvas_width) + region.x0 as usize;
+            let tile_row = (row_idx * width)..((row_idx + 1) * width);
+            pixel_buffer[row..(row + width)].copy_from_slice(&pixels[tile_row.clone()]);
+            depth_buffer[row..(row + width)].copy_from_slice(&depths[tile_row]);
+        }
+    }
+    progress(1.0);
+    stats
+}
+
+/*
+ * Draws every one of the mesh's edges as a one pixel wide line in the given color, depth tested
+ * against (and written into) the depth buffer like the filled triangles. Without clipping, an edge
+ * is only drawn when both of its ends are on screen.
+ */
+pub fn draw_lines(
+    mesh: &Mesh,
+    transform: Mat4,
+    camera: Camera,
+    options: RenderOptions,
+    line_color: Color,
     pixel_buffer: &mut [Color],
     depth_buffer: &mut [f32],
 ) {
-    let inverse_transform = match transform.inverse() {
-        Some(inverse) => Mat3::from(inverse.transpose()),
-        None => Mat3::default(),
+    let (clip_x0, clip_y0, clip_x1, clip_y1) = screen_clip(camera, options);
+
+    let frustum = camera.frustum();
+    let to_ndc = |vertex: Vector3| camera.projection_mat * camera.view_mat * vertex;
+    for &(a, b) in mesh.edges.iter() {
+        let (world_a, world_b) = (transform * mesh.verticies[a], transform * mesh.verticies[b]);
+        if !(frustum.contains_point(world_a) && frustum.contains_point(world_b)) {
+            continue;
+        }
+        let (ndc_a, ndc_b) = (to_ndc(world_a), to_ndc(world_b));
+        let pixel_a = ndc_a.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
+        let pixel_b = ndc_b.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
+
+        // NDC depth is linear in screen space
+        for (x, y, t) in line_pixels(pixel_a, pixel_b) {
+            if x < clip_x0 || x >= clip_x1 || y < clip_y0 || y >= clip_y1 {
+                continue;
+            }
+
+            let buff_idx = ((y * camera.canvas_width) + x) as usize;
+            let depth = ndc_a.z + (ndc_b.z - ndc_a.z) * t + options.depth_bias;
+            if depth < depth_buffer[buff_idx] {
+                depth_buffer[buff_idx] = depth;
+                pixel_buffer[buff_idx] = line_color;
+            }
+        }
+    }
+}
+
+/*
+ * Draws the mesh's points (OBJ p elements, not every vertex like draw_points) as single pixels in
+ * the given color, depth tested and written like draw_lines.
+ */
+pub fn draw_point_elements(
+    mesh: &Mesh,
+    transform: Mat4,
+    camera: Camera,
+    options: RenderOptions,
+    point_color: Color,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    splat_verticies(
+        mesh,
+        mesh.points.iter().copied(),
+        transform,
+        camera,
+        options,
+        1,
+        PointColor::Flat(point_color),
+        pixel_buffer,
+        depth_buffer,
+    );
+}
+
+// every pixel on the line from a to b, one per step along the longer axis, with how far along it is
+fn line_pixels(a: ScreenCoordinate, b: ScreenCoordinate) -> impl Iterator<Item = (i32, i32, f32)> {
+    let steps = max((b.x - a.x).abs(), (b.y - a.y).abs());
+    (0..=steps).map(move |step| {
+        let t = if steps == 0 {
+            0.0
+        } else {
+            step as f32 / steps as f32
+        };
+        let x = a.x + ((b.x - a.x) as f32 * t).round() as i32;
+        let y = a.y + ((b.y - a.y) as f32 * t).round() as i32;
+        (x, y, t)
+    })
+}
+
+/*
+ * Fills a triangle given directly in pixels with a flat color, for overlays like gizmos or text
+ * drawn over a render. The camera's view and projection are skipped entirely, it only gives the
+ * canvas size. Either winding is filled. Given a depth, the triangle is depth tested at it and
+ * writes it like a mesh would, without one it covers everything and leaves the depth buffer alone.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn draw_screen_triangle(
+    v0: ScreenCoordinate,
+    v1: ScreenCoordinate,
+    v2: ScreenCoordinate,
+    color: Color,
+    depth: Option<f32>,
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    // the inside test expects triangles to wind clockwise, like front faces do
+    let (v1, v2) = match triangle_edge(v2, v0, v1) {
+        area if area > 0.0 => (v1, v2),
+        area if area < 0.0 => (v2, v1),
+        _ => return,
+    };
+    // edges in NDC orientation (y up) for the top left rule
+    let edge = |from: ScreenCoordinate, to: ScreenCoordinate| Vector3 {
+        x: (to.x - from.x) as f32,
+        y: (from.y - to.y) as f32,
+        z: 0.0,
     };
+    let (edge0, edge1, edge2) = (edge(v1, v2), edge(v2, v0), edge(v0, v1));
 
-    for t in &mesh.face_indicies {
-        let world_to_v0 = transform * mesh.verticies[t.a];
-        let world_to_v1 = transform * mesh.verticies[t.b];
-        let world_to_v2 = transform * mesh.verticies[t.c];
+    let (clip_x0, clip_y0, clip_x1, clip_y1) = screen_clip(camera, options);
+    let x_start = max(min(min(v0.x, v1.x), v2.x), clip_x0);
+    let y_start = max(min(min(v0.y, v1.y), v2.y), clip_y0);
+    let x_end = min(max(max(v0.x, v1.x), v2.x), clip_x1);
+    let y_end = min(max(max(v0.y, v1.y), v2.y), clip_y1);
+    for y in y_start..y_end {
+        for x in x_start..x_end {
+            let pixel = ScreenCoordinate { x, y };
+            if top_left_inside(triangle_edge(pixel, v1, v2), edge0)
+                && top_left_inside(triangle_edge(pixel, v2, v0), edge1)
+                && top_left_inside(triangle_edge(pixel, v0, v1), edge2)
+            {
+                let buff_idx = ((y * camera.canvas_width) + x) as usize;
+                write_screen_pixel(buff_idx, color, depth, options, pixel_buffer, depth_buffer);
+            }
+        }
+    }
+}
+
+/*
+ * A one pixel wide line between two pixels in a flat color, the screen space counterpart of
+ * draw_lines. Depth works as in draw_screen_triangle.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn draw_screen_line(
+    a: ScreenCoordinate,
+    b: ScreenCoordinate,
+    color: Color,
+    depth: Option<f32>,
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    let (clip_x0, clip_y0, clip_x1, clip_y1) = screen_clip(camera, options);
+    for (x, y, _) in line_pixels(a, b) {
+        if x < clip_x0 || x >= clip_x1 || y < clip_y0 || y >= clip_y1 {
+            continue;
+        }
+        let buff_idx = ((y * camera.canvas_width) + x) as usize;
+        write_screen_pixel(buff_idx, color, depth, options, pixel_buffer, depth_buffer);
+    }
+}
 
-        let v0_normal = (inverse_transform * mesh.vertex_normals[t.a_normal]).normalized();
-        let v1_normal = (inverse_transform * mesh.vertex_normals[t.b_normal]).normalized();
-        let v2_normal = (inverse_transform * mesh.vertex_normals[t.c_normal]).normalized();
+// the canvas, narrowed to the scissor if there is one
+fn screen_clip(camera: Camera, options: RenderOptions) -> (i32, i32, i32, i32) {
+    let (x0, y0, x1, y1) =
+        options
+            .scissor
+            .unwrap_or((0, 0, camera.canvas_width, camera.canvas_height));
+    (
+        max(x0, 0),
+        max(y0, 0),
+        min(x1, camera.canvas_width),
+        min(y1, camera.canvas_height),
+    )
+}
 
-        let mut ndc_v0 = camera.projection_mat * camera.view_mat * world_to_v0;
-        let mut ndc_v1 = camera.projection_mat * camera.view_mat * world_to_v1;
-        let mut ndc_v2 = camera.projection_mat * camera.view_mat * world_to_v2;
+fn write_screen_pixel(
+    buff_idx: usize,
+    color: Color,
+    depth: Option<f32>,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    match depth {
+        Some(depth) => {
+            let depth = depth + options.depth_bias;
+            if depth < depth_buffer[buff_idx] {
+                depth_buffer[buff_idx] = depth;
+                pixel_buffer[buff_idx] = color;
+            }
+        }
+        None => pixel_buffer[buff_idx] = color,
+    }
+}
 
-        // let face_normal = Vector3::cross(world_to_v2 - world_to_v0, world_to_v1 - world_to_v0).normalized();
+// what color draw_points gives each vertex
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PointColor {
+    // the world space normal mapped from -1..1 into 0..255, gray for verticies without a normal
+    Normal,
+    Flat(Color),
+}
 
-        // if any points are on screen
-        // FIXME: I removed backface culling because it requires the view position, which is not
-        // easily accesible yet
-        if is_on_screen(ndc_v0, camera.near_plane, camera.far_plane)
-            || is_on_screen(ndc_v1, camera.near_plane, camera.far_plane)
-            || is_on_screen(ndc_v2, camera.near_plane, camera.far_plane)
+/*
+ * Draws only the verticies of a mesh as point_size by point_size squares centered on each projected
+ * vertex, ignoring its faces. Meant for point clouds and for debugging meshes. Points are depth
+ * tested and write depth like triangles do, and respect the scissor and depth bias options.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn draw_points(
+    mesh: &Mesh,
+    transform: Mat4,
+    camera: Camera,
+    options: RenderOptions,
+    point_size: i32,
+    point_color: PointColor,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    splat_verticies(
+        mesh,
+        0..mesh.verticies.len(),
+        transform,
+        camera,
+        options,
+        point_size,
+        point_color,
+        pixel_buffer,
+        depth_buffer,
+    );
+}
+
+// draws the given verticies of the mesh as squares, shared by draw_points and draw_point_elements
+#[allow(clippy::too_many_arguments)]
+fn splat_verticies(
+    mesh: &Mesh,
+    vertex_indices: impl Iterator<Item = usize>,
+    transform: Mat4,
+    camera: Camera,
+    options: RenderOptions,
+    point_size: i32,
+    point_color: PointColor,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    // normals go through the inverse transpose, as directions so the translation drops out
+    let normal_transform = transform
+        .inverse()
+        .map(Mat4::transpose)
+        .unwrap_or(Mat4 { data: [0.0; 16] });
+    let (clip_x0, clip_y0, clip_x1, clip_y1) = screen_clip(camera, options);
+
+    let frustum = camera.frustum();
+    for vertex_idx in vertex_indices {
+        let world = transform * mesh.verticies[vertex_idx];
+        if !frustum.contains_point(world) {
+            continue;
+        }
+        let ndc = camera.projection_mat * camera.view_mat * world;
+
+        let color = match point_color {
+            PointColor::Flat(color) => color,
+            PointColor::Normal => match mesh.vertex_normals.get(vertex_idx) {
+                Some(&normal) => {
+                    let normal = (normal_transform * Vector4::direction(normal))
+                        .xyz()
+                        .normalized();
+                    ((normal
+                        + Vector3 {
+                            x: 1.0,
+                            y: 1.0,
+                            z: 1.0,
+                        })
+                        * 0.5)
+                        .to_color()
+                }
+                None => Color {
+                    r: 128,
+                    g: 128,
+                    b: 128,
+                },
+            },
+        };
+
+        let center = ndc.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
+        let x_start = max(center.x - (point_size - 1) / 2, clip_x0);
+        let y_start = max(center.y - (point_size - 1) / 2, clip_y0);
+        let x_end = min(center.x - (point_size - 1) / 2 + point_size, clip_x1);
+        let y_end = min(center.y - (point_size - 1) / 2 + point_size, clip_y1);
+        let depth = ndc.z + options.depth_bias;
+
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let buff_idx = ((y * camera.canvas_width) + x) as usize;
+                if depth < depth_buffer[buff_idx] {
+                    depth_buffer[buff_idx] = depth;
+                    pixel_buffer[buff_idx] = color;
+                }
+            }
+        }
+    }
+}
+
+// a rectangle of the frame, x0/y0 inclusive and x1/y1 exclusive
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Region {
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+}
+
+impl Region {
+    fn width(&self) -> i32 {
+        self.x1 - self.x0
+    }
+
+    fn len(&self) -> usize {
+        (self.width() * (self.y1 - self.y0)) as usize
+    }
+}
+
+/*
+ * A triangle that has been transformed, lit and projected, holding everything needed to fill its
+ * pixels.
+ */
+#[derive(Debug, Copy, Clone)]
+struct ScreenTriangle {
+    pixel_v0: ScreenCoordinate,
+    pixel_v1: ScreenCoordinate,
+    pixel_v2: ScreenCoordinate,
+    // the pixel positions before they're truncated to whole pixels, z is unused. Conservative
+    // rasterization tests against these so triangles smaller than a pixel still cover it.
+    exact_v0: Vector3,
+    exact_v1: Vector3,
+    exact_v2: Vector3,
+    // NDC positions with z replaced by its inverse
+    ndc_v0: Vector3,
+    ndc_v1: Vector3,
+    ndc_v2: Vector3,
+    // vertex lighting pre-multiplied by the inverse depth
+    c0: Vector3,
+    c1: Vector3,
+    c2: Vector3,
+    // texture coordinates, taken as is so affine mapping can skip the inverse depth
+    uv0: Vector3,
+    uv1: Vector3,
+    uv2: Vector3,
+    // vertex colors pre-multiplied by the inverse depth, white for meshes without any
+    vertex_color0: Vector3,
+    vertex_color1: Vector3,
+    vertex_color2: Vector3,
+    // world space positions and normals pre-multiplied by the inverse depth, for the G-buffer
+    world0: Vector3,
+    world1: Vector3,
+    world2: Vector3,
+    normal0: Vector3,
+    normal1: Vector3,
+    normal2: Vector3,
+    // twice the signed area in whole pixels, positive for front facing triangles
+    area: f32,
+    lod: f32,
+}
+
+impl ScreenTriangle {
+    // the pixels the triangle may touch, x0/y0 inclusive and x1/y1 exclusive
+    fn bounds(&self, options: RenderOptions) -> (i32, i32, i32, i32) {
+        // anti-aliased edges and conservative rasterization also touch the ring of pixels just
+        // outside of the triangle
+        let fringe = (options.edge_antialiasing || options.conservative) as i32;
+        let (v0, v1, v2) = (self.pixel_v0, self.pixel_v1, self.pixel_v2);
+        (
+            min(min(v0.x, v1.x), v2.x) - fringe,
+            min(min(v0.y, v1.y), v2.y) - fringe,
+            max(max(v0.x, v1.x), v2.x) + fringe,
+            max(max(v0.y, v1.y), v2.y) + fringe,
+        )
+    }
+
+    // the bounds, clipped to match the region and scissor, as x_start, x_end, y_start, y_end
+    fn clipped_bounds(&self, options: RenderOptions, region: Region) -> (i32, i32, i32, i32) {
+        let (clip_x0, clip_y0, clip_x1, clip_y1) = options
+            .scissor
+            .unwrap_or((region.x0, region.y0, region.x1, region.y1));
+        let (bounds_x0, bounds_y0, bounds_x1, bounds_y1) = self.bounds(options);
+        (
+            max(bounds_x0, max(clip_x0, region.x0)),
+            min(bounds_x1, min(clip_x1, region.x1)),
+            max(bounds_y0, max(clip_y0, region.y0)),
+            min(bounds_y1, min(clip_y1, region.y1)),
+        )
+    }
+
+    // how much of pixel the triangle covers and where, None when it doesn't cover any of it
+    fn fragment(&self, pixel: ScreenCoordinate, options: RenderOptions) -> Option<Fragment> {
+        let ScreenTriangle {
+            pixel_v0,
+            pixel_v1,
+            pixel_v2,
+            ndc_v0,
+            ndc_v1,
+            ndc_v2,
+            exact_v0,
+            exact_v1,
+            exact_v2,
+            area,
+            ..
+        } = *self;
+        // front facing or collapsed, with the same sign as area but before truncating to whole
+        // pixels
+        let exact_area = exact_edge(exact_v2, exact_v0, exact_v1);
+
+        let w0 = triangle_edge(pixel, pixel_v1, pixel_v2);
+        let w1 = triangle_edge(pixel, pixel_v2, pixel_v0);
+        let w2 = triangle_edge(pixel, pixel_v0, pixel_v1);
+
+        let edge0 = ndc_v2 - ndc_v1;
+        let edge1 = ndc_v0 - ndc_v2;
+        let edge2 = ndc_v1 - ndc_v0;
+
+        // are we inside of a triangle?
+        let inside =
+            top_left_inside(w0, edge0) && top_left_inside(w1, edge1) && top_left_inside(w2, edge2);
+
+        // pixels that pass the inside test are fully covered, with edge anti-aliasing the pixels
+        // just outside are partially covered based on how far the point the inside test samples
+        // is from the nearest edge
+        let coverage = if inside
+            || (options.conservative
+                && exact_area >= 0.0
+                && pixel_overlaps_triangle(pixel, exact_v0, exact_v1, exact_v2))
         {
-            // screen coords
-            let pixel_v0 = ndc_v0.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
-            let pixel_v1 = ndc_v1.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
-            let pixel_v2 = ndc_v2.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
-
-            // (note: amoussa) perhaps this could be passed as a function pointer to the draw call
-            let phong_lighting = |light: Light, vertex: Vector3, normal: Vector3| -> Vector3 {
-                let v_to_light = (light.position - vertex).normalized();
-                let color = light.color.to_vector3();
-                (color * f32::max(Vector3::dot(normal, v_to_light), 0.0))
-                    + (color * light.ambient_strength)
+            1.0
+        } else if options.edge_antialiasing && area > 0.0 {
+            let nearest_edge = f32::min(
+                edge_distance(pixel, pixel_v1, pixel_v2),
+                f32::min(
+                    edge_distance(pixel, pixel_v2, pixel_v0),
+                    edge_distance(pixel, pixel_v0, pixel_v1),
+                ),
+            );
+            (nearest_edge + 0.5).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        if coverage <= 0.0 {
+            return None;
+        }
+
+        // triangles with no area in whole pixels are only here for conservative rasterization,
+        // they're small enough to take the average of their verticies
+        let weights = if area == 0.0 {
+            (1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0)
+        } else {
+            barycentric(pixel, pixel_v0, pixel_v1, pixel_v2, area)
+        };
+        let (w0, w1, w2) = weights;
+
+        // (note: amoussa) this is a very unintuitive formula I recommend reading about
+        // it here: https://www.scratchapixel.com/lessons/3d-basic-rendering/rasterization-practical-implementation/visibility-problem-depth-buffer-depth-interpolation.html
+        let depth = 1.0 / (ndc_v0.z * w0 + ndc_v1.z * w1 + ndc_v2.z * w2);
+        Some(Fragment {
+            coverage,
+            weights,
+            depth,
+        })
+    }
+}
+
+// the part of a triangle covering one pixel
+#[derive(Debug, Copy, Clone)]
+struct Fragment {
+    // 1 for fully covered pixels, less for the partially covered pixels of anti-aliased edges
+    coverage: f32,
+    // barycentric weights of the pixel in the triangle
+    weights: (f32, f32, f32),
+    // depth before the depth bias is added
+    depth: f32,
+}
+
+/*
+ * The depth test of a fragment at biased_depth against what the depth buffer holds. The nearer
+ * fragment wins and of two at the same depth the one already there stays. The shading pass after a
+ * depth pass tests the fragments that write depth against the marks the depth pass left instead,
+ * see fill_triangle.
+ */
+fn passes_depth_test(biased_depth: f32, buffer_depth: f32) -> bool {
+    biased_depth < buffer_depth
+}
+
+// a Light with its color already converted for lighting math
+struct PreparedLight {
+    position: Vector3,
+    color: Vector3,
+    // color scaled by the light's ambient strength, added on top of RenderOptions::ambient
+    ambient: Vector3,
+}
+
+fn setup_triangles(
+    mesh: &Mesh,
+    transform: Mat4,
+    lights: &[Light],
+    camera: Camera,
+    options: RenderOptions,
+    mipmap: Option<&Mipmap>,
+) -> Vec<ScreenTriangle> {
+    // normals go through the inverse transpose, as directions so the translation drops out
+    let normal_transform = transform
+        .inverse()
+        .map(Mat4::transpose)
+        .unwrap_or(Mat4 { data: [0.0; 16] });
+    let camera_position = camera
+        .view_mat
+        .inverse()
+        .map(Mat4::translation_part)
+        .unwrap_or_default();
+
+    // everything about a light that doesn't depend on the vertex being lit, worked out once per
+    // mesh rather than for every vertex of every triangle
+    let prepared_lights: Vec<PreparedLight> = lights
+        .iter()
+        .map(|light| {
+            let color = light.color;
+            PreparedLight {
+                position: light.position,
+                color,
+                ambient: color * light.ambient_strength,
+            }
+        })
+        .collect();
+
+    // (note: amoussa) perhaps this could be passed as a function pointer to the draw call
+    let phong_lighting = |light: &PreparedLight, vertex: Vector3, normal: Vector3| -> Vector3 {
+        let normal =
+            if options.two_sided_lighting && Vector3::dot(normal, camera_position - vertex) < 0.0 {
+                normal * -1.0
+            } else {
+                normal
             };
+        let v_to_light = (light.position - vertex).normalized();
+        (light.color * f32::max(Vector3::dot(normal, v_to_light), 0.0)) + light.ambient
+    };
 
-            let c0 = lights
-                .iter()
-                .map(|&light| phong_lighting(light, world_to_v0, v0_normal))
-                .fold(Vector3::default(), |acc, color| acc + color);
-            let c1 = lights
-                .iter()
-                .map(|&light| phong_lighting(light, world_to_v1, v1_normal))
-                .fold(Vector3::default(), |acc, color| acc + color);
-            let c2 = lights
+    // the frustum is taken in object space so the bvh's boxes can be tested as is. A box is only
+    // thrown away when it is entirely behind one plane, and clipping below leaves nothing of a
+    // triangle like that, so culling doesn't change the image. Meshes without a bvh are still
+    // culled as a whole.
+    let frustum = Frustum::from_matrix(camera.projection_mat * camera.view_mat * transform);
+    let faces: Vec<&Triangle> = match &mesh.bvh {
+        Some(bvh) => bvh
+            .query(|bounds| frustum.intersects_aabb(&bounds))
+            .into_iter()
+            .map(|idx| &mesh.face_indicies[idx])
+            .collect(),
+        None => match Aabb::from_points(mesh.verticies.iter().copied()) {
+            Some(bounds) if frustum.intersects_aabb(&bounds) => mesh.face_indicies.iter().collect(),
+            _ => Vec::new(),
+        },
+    };
+
+    // the level of detail is worked out for each triangle that comes out of clipping, while the
+    // lighting comes from the verticies before clipping (see clip_vertex below)
+    let screen_triangle = |v0: &ClipVertex, v1: &ClipVertex, v2: &ClipVertex| {
+        let mut ndc_v0 = v0.clip.perspective_divide();
+        let mut ndc_v1 = v1.clip.perspective_divide();
+        let mut ndc_v2 = v2.clip.perspective_divide();
+
+        // screen coords
+        let pixel_v0 = ndc_v0.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
+        let pixel_v1 = ndc_v1.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
+        let pixel_v2 = ndc_v2.ndc_to_pixel(camera.canvas_width, camera.canvas_height);
+        let exact_pixel = |ndc: Vector3| Vector3 {
+            x: (ndc.x + 1.0) * 0.5 * camera.canvas_width as f32,
+            y: (1.0 - ndc.y) * 0.5 * camera.canvas_height as f32,
+            z: 0.0,
+        };
+        let (exact_v0, exact_v1, exact_v2) = (
+            exact_pixel(ndc_v0),
+            exact_pixel(ndc_v1),
+            exact_pixel(ndc_v2),
+        );
+
+        // triangles seen edge on, collapsed onto a line, or onto a single pixel have no samples
+        // inside and would divide the barycentric weights by a zero area. Conservative
+        // rasterization still covers the pixels they touch, so they're kept for it.
+        let area = triangle_edge(pixel_v2, pixel_v0, pixel_v1);
+        let is_finite = |ndc: Vector3| ndc.x.is_finite() && ndc.y.is_finite() && ndc.z.is_finite();
+        if (area == 0.0 && !options.conservative)
+            || !(is_finite(ndc_v0) && is_finite(ndc_v1) && is_finite(ndc_v2))
+        {
+            return None;
+        }
+
+        let (c0, c1, c2) = (v0.lighting, v1.lighting, v2.lighting);
+
+        // pre-compute inverse depth before loop
+        ndc_v0.z = 1.0 / ndc_v0.z;
+        ndc_v1.z = 1.0 / ndc_v1.z;
+        ndc_v2.z = 1.0 / ndc_v2.z;
+
+        // a constant lod per triangle, picked by comparing how many texels the triangle covers
+        // against how many pixels it covers on screen
+        let lod = match mipmap {
+            Some(mipmap) => {
+                let texture = &mipmap.levels[0];
+                let texel_area = Vector3::cross(v1.uv - v0.uv, v2.uv - v0.uv).magnitude()
+                    * 0.5
+                    * (texture.width * texture.height) as f32;
+                // triangles without any area on screen are sized like the smallest ones that
+                // have some
+                let pixel_area = (area.abs() * 0.5).max(0.5);
+                0.5 * (texel_area / pixel_area).log2()
+            }
+            None => 0.0,
+        };
+
+        Some(ScreenTriangle {
+            pixel_v0,
+            pixel_v1,
+            pixel_v2,
+            exact_v0,
+            exact_v1,
+            exact_v2,
+            c0: c0 * ndc_v0.z,
+            c1: c1 * ndc_v1.z,
+            c2: c2 * ndc_v2.z,
+            uv0: v0.uv,
+            uv1: v1.uv,
+            uv2: v2.uv,
+            vertex_color0: v0.color * ndc_v0.z,
+            vertex_color1: v1.color * ndc_v1.z,
+            vertex_color2: v2.color * ndc_v2.z,
+            world0: v0.world * ndc_v0.z,
+            world1: v1.world * ndc_v1.z,
+            world2: v2.world * ndc_v2.z,
+            normal0: v0.normal * ndc_v0.z,
+            normal1: v1.normal * ndc_v1.z,
+            normal2: v2.normal * ndc_v2.z,
+            ndc_v0,
+            ndc_v1,
+            ndc_v2,
+            area,
+            lod,
+        })
+    };
+
+    let white = Vector3 {
+        x: 1.0,
+        y: 1.0,
+        z: 1.0,
+    };
+    // verticies are lit before clipping, so the ones clipping adds take the lighting interpolated
+    // along the edge like any other pixel would rather than being lit again, and shading doesn't
+    // change with where the clip lands
+    let clip_vertex = |position: usize, normal: usize, texture: usize| {
+        let world = transform * mesh.verticies[position];
+        let normal = (normal_transform * Vector4::direction(mesh.vertex_normals[normal]))
+            .xyz()
+            .normalized();
+        ClipVertex {
+            clip: camera.projection_mat * camera.view_mat * Vector4::point(world),
+            world,
+            normal,
+            lighting: prepared_lights
                 .iter()
-                .map(|&light| phong_lighting(light, world_to_v2, v2_normal))
-                .fold(Vector3::default(), |acc, color| acc + color);
+                .map(|light| phong_lighting(light, world, normal))
+                .fold(options.ambient.unwrap_or_default(), |acc, color| {
+                    acc + color
+                }),
+            // only read when the material has a texture, or the mesh has vertex colors
+            uv: mesh
+                .vertex_texture_coords
+                .get(texture)
+                .copied()
+                .unwrap_or_default(),
+            color: mesh
+                .vertex_colors
+                .get(position)
+                .map_or(white, |color| color.to_vector3()),
+        }
+    };
 
-            // pre-compute inverse depth before loop
-            ndc_v0.z = 1.0 / ndc_v0.z;
-            ndc_v1.z = 1.0 / ndc_v1.z;
-            ndc_v2.z = 1.0 / ndc_v2.z;
+    let sort_by_depth = options.depth_sort_transparent && mesh.material.is_transparent();
+    let mut view_depths = Vec::new();
+    let mut triangles = Vec::new();
+    for t in faces {
+        let v0 = clip_vertex(t.a, t.a_normal, t.a_texture);
+        let v1 = clip_vertex(t.b, t.b_normal, t.b_texture);
+        let v2 = clip_vertex(t.c, t.c_normal, t.c_texture);
 
-            let c0 = c0 * ndc_v0.z;
-            let c1 = c1 * ndc_v1.z;
-            let c2 = c2 * ndc_v2.z;
+        // FIXME: I removed backface culling because it requires the view position, which is not
+        // easily accesible yet
+        let polygon = clip_triangle(v0, v1, v2);
+        for idx in 1..polygon.len().saturating_sub(1) {
+            triangles.extend(screen_triangle(
+                &polygon[0],
+                &polygon[idx],
+                &polygon[idx + 1],
+            ));
+        }
 
-            let area = triangle_edge(pixel_v2, pixel_v0, pixel_v1);
+        if sort_by_depth {
+            // every piece of a clipped face sorts at the whole face's depth
+            let centroid = (v0.world + v1.world + v2.world) * (1.0 / 3.0);
+            let view_depth = (camera.view_mat * centroid).z;
+            view_depths.resize(triangles.len(), view_depth);
+        }
+    }
 
-            // axis aligned bounding box of triangle (clipped to match screen)
-            let x_start = max(min(min(pixel_v0.x, pixel_v1.x), pixel_v2.x), 0);
-            let x_end = min(
-                max(max(pixel_v0.x, pixel_v1.x), pixel_v2.x),
-                camera.canvas_width,
-            );
-            let y_start = max(min(min(pixel_v0.y, pixel_v1.y), pixel_v2.y), 0);
-            let y_end = min(
-                max(max(pixel_v0.y, pixel_v1.y), pixel_v2.y),
-                camera.canvas_height,
-            );
+    // the camera looks down -z, so the farthest triangles have the lowest view space z
+    if sort_by_depth {
+        let mut order: Vec<usize> = (0..triangles.len()).collect();
+        order.sort_by(|&a, &b| view_depths[a].total_cmp(&view_depths[b]));
+        triangles = order.into_iter().map(|idx| triangles[idx]).collect();
+    }
+    triangles
+}
 
-            for x in x_start..x_end {
-                for y in y_start..y_end {
-                    let current_pixel = ScreenCoordinate { x, y };
-                    let mut w0 = triangle_edge(current_pixel, pixel_v1, pixel_v2);
-                    let mut w1 = triangle_edge(current_pixel, pixel_v2, pixel_v0);
-                    let mut w2 = triangle_edge(current_pixel, pixel_v0, pixel_v1);
-
-                    let edge0 = ndc_v2 - ndc_v1;
-                    let edge1 = ndc_v0 - ndc_v2;
-                    let edge2 = ndc_v1 - ndc_v0;
-
-                    // are we inside of a triangle? (also does a top left edge rule check)
-                    if ((w0 == 0.0 && ((edge0.y == 0.0 && edge0.x > 0.0) || edge0.y > 0.0))
-                        || w0 >= 0.0)
-                        && ((w1 == 0.0 && ((edge1.y == 0.0 && edge1.x > 0.0) || edge1.y > 0.0))
-                            || w1 >= 0.0)
-                        && ((w2 == 0.0 && ((edge2.y == 0.0 && edge2.x > 0.0) || edge2.y > 0.0))
-                            || w2 >= 0.0)
-                    {
-                        let buff_idx = ((y * camera.canvas_width) + x) as usize;
-                        w0 /= area;
-                        w1 /= area;
-                        w2 /= area;
-
-                        // (note: amoussa) this is a very unintuitive formula I recommend reading about
-                        // it here: https://www.scratchapixel.com/lessons/3d-basic-rendering/rasterization-practical-implementation/visibility-problem-depth-buffer-depth-interpolation.html
-                        let depth = 1.0 / (ndc_v0.z * w0 + ndc_v1.z * w1 + ndc_v2.z * w2);
-
-                        // depth test
-                        if depth < depth_buffer[buff_idx] {
-                            depth_buffer[buff_idx] = depth;
-                            let lighting_color = (c0 * w0 + c1 * w1 + c2 * w2) * depth;
-                            if mesh.texture.is_some() {
-                                let v0_texture_coordinate =
-                                    mesh.vertex_texture_coords[t.a_texture] * ndc_v0.z;
-                                let v1_texture_coordinate =
-                                    mesh.vertex_texture_coords[t.b_texture] * ndc_v1.z;
-                                let v2_texture_coordinate =
-                                    mesh.vertex_texture_coords[t.c_texture] * ndc_v2.z;
-
-                                let object_uv = (v0_texture_coordinate * w0
-                                    + v1_texture_coordinate * w1
-                                    + v2_texture_coordinate * w2)
-                                    * depth;
-                                let object_color = mesh
-                                    .texture
-                                    .as_ref()
-                                    .unwrap()
-                                    .sample_bilinear(object_uv.x, object_uv.y)
-                                    .to_vector3();
-
-                                pixel_buffer[buff_idx] = (object_color * lighting_color).to_color();
-                            } else {
-                                pixel_buffer[buff_idx] = lighting_color.to_color();
+// a vertex in clip space along with everything interpolated across a triangle
+#[derive(Debug, Copy, Clone)]
+struct ClipVertex {
+    clip: Vector4,
+    world: Vector3,
+    // world space, unit length
+    normal: Vector3,
+    // the vertex lighting, Gouraud shaded
+    lighting: Vector3,
+    uv: Vector3,
+    color: Vector3,
+}
+
+impl ClipVertex {
+    // the vertex t of the way to other. Everything is linear in clip space, so these match what
+    // perspective correct interpolation gives at the same point.
+    fn towards(self, other: ClipVertex, t: f32) -> ClipVertex {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        ClipVertex {
+            clip: Vector4 {
+                x: mix(self.clip.x, other.clip.x),
+                y: mix(self.clip.y, other.clip.y),
+                z: mix(self.clip.z, other.clip.z),
+                w: mix(self.clip.w, other.clip.w),
+            },
+            world: self.world + (other.world - self.world) * t,
+            normal: (self.normal + (other.normal - self.normal) * t).normalized(),
+            lighting: self.lighting + (other.lighting - self.lighting) * t,
+            uv: self.uv + (other.uv - self.uv) * t,
+            color: self.color + (other.color - self.color) * t,
+        }
+    }
+
+    /*
+     * Signed distances to the six planes of the clip volume, where x, y and z are each between
+     * -w and w (the -1 to 1 cube after the perspective divide). Positive is inside.
+     */
+    fn plane_distances(&self) -> [f32; 6] {
+        let Vector4 { x, y, z, w } = self.clip;
+        [w + x, w - x, w + y, w - y, w + z, w - z]
+    }
+}
+
+/*
+ * Sutherland-Hodgman clipping of a triangle against all six planes of the clip volume, giving the
+ * convex polygon (in the same winding) of the part inside, or nothing if none of it is. Triangles
+ * entirely inside come back as is. Clipping keeps verticies behind the camera from projecting to
+ * nonsense and triangles reaching far off screen from being filled over huge bounding boxes.
+ */
+fn clip_triangle(v0: ClipVertex, v1: ClipVertex, v2: ClipVertex) -> Vec<ClipVertex> {
+    let mut polygon = vec![v0, v1, v2];
+    if polygon
+        .iter()
+        .all(|vertex| vertex.plane_distances().iter().all(|&d| d >= 0.0))
+    {
+        return polygon;
+    }
+
+    for plane in 0..6 {
+        let distance = |vertex: &ClipVertex| vertex.plane_distances()[plane];
+        if polygon.iter().all(|vertex| distance(vertex) >= 0.0) {
+            continue;
+        }
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (idx, &current) in polygon.iter().enumerate() {
+            let next = polygon[(idx + 1) % polygon.len()];
+            let (current_distance, next_distance) = (distance(&current), distance(&next));
+            if current_distance >= 0.0 {
+                clipped.push(current);
+            }
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                clipped.push(current.towards(next, t));
+            }
+        }
+        polygon = clipped;
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+    }
+    polygon
+}
+
+/*
+ * Fills the pixels of one set up triangle that fall inside of region. The buffers only hold that
+ * region of the frame, stored row major.
+ *
+ * A depth pass marks the pixels it writes depth to as unshaded. Several fragments can end up at
+ * the stored depth, but only the first of them wins the pixel in a full pass, so the shading pass
+ * after it shades the first fragment at that depth and then clears the mark to turn away the rest.
+ * A full pass doesn't use unshaded.
+ */
+#[allow(clippy::too_many_arguments)]
+fn fill_triangle(
+    triangle: &ScreenTriangle,
+    mesh: &Mesh,
+    mipmap: Option<&Mipmap>,
+    options: RenderOptions,
+    pass: Pass,
+    region: Region,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+    unshaded: &mut [bool],
+    mut gbuffer: Option<&mut GBuffer>,
+) -> RenderStats {
+    let ScreenTriangle {
+        ndc_v0,
+        ndc_v1,
+        ndc_v2,
+        c0,
+        c1,
+        c2,
+        uv0,
+        uv1,
+        uv2,
+        vertex_color0,
+        vertex_color1,
+        vertex_color2,
+        world0,
+        world1,
+        world2,
+        normal0,
+        normal1,
+        normal2,
+        lod,
+        ..
+    } = *triangle;
+    let emissive = mesh.material.emissive_color.to_vector3();
+    // a color override is drawn opaque whatever the material says
+    let opacity = if options.color_override.is_some() {
+        1.0
+    } else {
+        mesh.material.opacity
+    };
+    let mut stats = RenderStats::default();
+    // transparent surfaces never write depth, so they're left for the shading pass
+    if pass == Pass::Depth && opacity < 1.0 {
+        return stats;
+    }
+
+    let (x_start, x_end, y_start, y_end) = triangle.clipped_bounds(options, region);
+    for x in x_start..x_end {
+        for y in y_start..y_end {
+            let current_pixel = ScreenCoordinate { x, y };
+            let Some(Fragment {
+                coverage,
+                weights,
+                depth,
+            }) = triangle.fragment(current_pixel, options)
+            else {
+                continue;
+            };
+
+            let buff_idx = (((y - region.y0) * region.width()) + (x - region.x0)) as usize;
+            let biased_depth = depth + options.depth_bias;
+            // partially covered pixels and transparent surfaces blend over whatever is already
+            // there and leave the depth buffer alone
+            let alpha = coverage * opacity;
+            let passes_depth_test = if pass == Pass::Shade && alpha >= 1.0 {
+                unshaded[buff_idx] && biased_depth <= depth_buffer[buff_idx]
+            } else {
+                passes_depth_test(biased_depth, depth_buffer[buff_idx])
+            };
+            if passes_depth_test {
+                let texture_uv = || {
+                    // affine mapping skips the inverse depth weighting
+                    let (z0, z1, z2, uv_depth) = if options.affine_texture_mapping {
+                        (1.0, 1.0, 1.0, 1.0)
+                    } else {
+                        (ndc_v0.z, ndc_v1.z, ndc_v2.z, depth)
+                    };
+                    let object_uv = interpolate(weights, uv0 * z0, uv1 * z1, uv2 * z2) * uv_depth;
+                    let v = mesh.material.uv_origin.to_bottom_left(object_uv.y);
+                    (object_uv.x, v)
+                };
+
+                // alpha testing happens before anything is written so the holes show what's behind
+                if let Some(mask) = &mesh.material.alpha_mask {
+                    let (u, v) = texture_uv();
+                    let alpha = mask
+                        .sample(u, v, options.texture_filter, mesh.material.texture_wrap)
+                        .luminance()
+                        / 255.0;
+                    if alpha < mesh.material.alpha_cutoff {
+                        continue;
+                    }
+                }
+
+                if pass == Pass::Depth {
+                    if alpha >= 1.0 {
+                        depth_buffer[buff_idx] = biased_depth;
+                        unshaded[buff_idx] = true;
+                    }
+                    continue;
+                }
+                stats.pixels_shaded += 1;
+
+                let (albedo, fragment_color) = if let Some(color) = options.color_override {
+                    (color.to_vector3(), color)
+                } else {
+                    let lighting_color = interpolate(weights, c0, c1, c2) * depth;
+                    let albedo = if let Some(texture) = &mesh.material.diffuse_texture {
+                        let (u, v) = texture_uv();
+                        match mipmap {
+                            Some(mipmap) => {
+                                mipmap.sample_trilinear(u, v, lod, mesh.material.texture_wrap)
                             }
+                            None => texture.sample(
+                                u,
+                                v,
+                                options.texture_filter,
+                                mesh.material.texture_wrap,
+                            ),
                         }
+                        .to_vector3()
+                    } else {
+                        mesh.material.diffuse_color.to_vector3()
+                    };
+                    // vertex colors tint the albedo, perspective correct like the lighting
+                    let albedo = if mesh.vertex_colors.is_empty() {
+                        albedo
+                    } else {
+                        albedo
+                            * interpolate(weights, vertex_color0, vertex_color1, vertex_color2)
+                            * depth
+                    };
+                    // emission ignores the lights entirely
+                    let shaded = albedo * lighting_color + emissive;
+                    let fragment_color = if options.dithering {
+                        shaded.to_color_dithered(x, y)
+                    } else {
+                        shaded.to_color()
+                    };
+                    (albedo, fragment_color)
+                };
+
+                if alpha < 1.0 {
+                    pixel_buffer[buff_idx] = pixel_buffer[buff_idx].blend(fragment_color, alpha);
+                } else {
+                    if pass == Pass::Shade {
+                        unshaded[buff_idx] = false;
+                    }
+                    depth_buffer[buff_idx] = biased_depth;
+                    pixel_buffer[buff_idx] = fragment_color;
+                    if let Some(gbuffer) = gbuffer.as_deref_mut() {
+                        gbuffer.normals[buff_idx] =
+                            (interpolate(weights, normal0, normal1, normal2) * depth).normalized();
+                        gbuffer.positions[buff_idx] =
+                            interpolate(weights, world0, world1, world2) * depth;
+                        gbuffer.albedo[buff_idx] = albedo.to_color();
                     }
                 }
             }
         }
     }
+    stats
+}
+
+/*
+ * Whether any pixel of mesh's triangles would pass the depth test against depth_buffer, found
+ * without writing to it or shading anything. Lines and points are ignored. This covers the same
+ * pixels with the same depth comparison and bias as a full fill drawn with options, just stopping
+ * at the first pixel that gets through. A depth buffer that isn't the size of the canvas has
+ * nothing to test against so nothing passes.
+ */
+pub fn mesh_passes_depth_test(
+    mesh: &Mesh,
+    transform: Mat4,
+    camera: Camera,
+    options: RenderOptions,
+    depth_buffer: &[f32],
+) -> bool {
+    if depth_buffer.len() != (camera.canvas_width * camera.canvas_height) as usize {
+        return false;
+    }
+    let region = Region {
+        x0: 0,
+        y0: 0,
+        x1: camera.canvas_width,
+        y1: camera.canvas_height,
+    };
+    setup_triangles(mesh, transform, &[], camera, options, None)
+        .iter()
+        .any(|triangle| {
+            let (x_start, x_end, y_start, y_end) = triangle.clipped_bounds(options, region);
+            (x_start..x_end).any(|x| {
+                (y_start..y_end).any(|y| {
+                    triangle
+                        .fragment(ScreenCoordinate { x, y }, options)
+                        .is_some_and(|fragment| {
+                            passes_depth_test(
+                                fragment.depth + options.depth_bias,
+                                depth_buffer[(y * camera.canvas_width + x) as usize],
+                            )
+                        })
+                })
+            })
+        })
+}
+
+/*
+ * The barycentric weights (w0, w1, w2) of point p in the screen space triangle v0, v1, v2, i.e. how
+ * much each vertex contributes at p. area is twice the triangle's signed area, as the edge function
+ * of v2 against v0 and v1 gives it, which the rasterizer already has for every triangle. The
+ * weights sum to 1 and are all between 0 and 1 when p is inside the triangle. A triangle with no
+ * area has no weights, every one comes back infinite or NaN.
+ */
+pub fn barycentric(
+    p: ScreenCoordinate,
+    v0: ScreenCoordinate,
+    v1: ScreenCoordinate,
+    v2: ScreenCoordinate,
+    area: f32,
+) -> (f32, f32, f32) {
+    (
+        triangle_edge(p, v1, v2) / area,
+        triangle_edge(p, v2, v0) / area,
+        triangle_edge(p, v0, v1) / area,
+    )
+}
+
+/*
+ * Blends a per-vertex attribute (a color, uv, normal, ...) by barycentric weights. This is linear
+ * in screen space, for perspective correct results weight each attribute by its vertex's inverse
+ * depth first and divide the result by the interpolated inverse depth, like draw_mesh does.
+ */
+pub fn interpolate(weights: (f32, f32, f32), a: Vector3, b: Vector3, c: Vector3) -> Vector3 {
+    a * weights.0 + b * weights.1 + c * weights.2
+}
+
+/*
+ * Signed distance in pixels from the given pixel to the line defined by v0 and v1, positive on the
+ * same side that triangle_edge reports as positive. The pixel is sampled at the same point as in
+ * triangle_edge, so the distance is only positive where the inside test passes for the edge.
+ */
+fn edge_distance(pixel: ScreenCoordinate, v0: ScreenCoordinate, v1: ScreenCoordinate) -> f32 {
+    let (dx, dy) = ((v0.x - v1.x) as f32, (v0.y - v1.y) as f32);
+    triangle_edge(pixel, v0, v1) / (dx * dx + dy * dy).sqrt()
+}
+
+/*
+ * Whether the square covered by the given pixel overlaps the (clockwise on screen) triangle, whose
+ * verticies are exact pixel positions. The square is outside if it misses the triangle's bounding
+ * box, or sits entirely behind any one edge, which happens when the middle of the pixel is further
+ * from the edge than the square's half extent along the edge normal. Squares only touching the
+ * triangle along a line do not count. Triangles collapsed onto a line or a point still overlap the
+ * squares they pass through.
+ */
+fn pixel_overlaps_triangle(pixel: ScreenCoordinate, v0: Vector3, v1: Vector3, v2: Vector3) -> bool {
+    let (x, y) = (pixel.x as f32, pixel.y as f32);
+    let misses_bounds = x + 1.0 <= v0.x.min(v1.x).min(v2.x)
+        || x >= v0.x.max(v1.x).max(v2.x)
+        || y + 1.0 <= v0.y.min(v1.y).min(v2.y)
+        || y >= v0.y.max(v1.y).max(v2.y);
+    if misses_bounds {
+        return false;
+    }
+
+    let middle = Vector3 {
+        x: x + 0.5,
+        y: y + 0.5,
+        z: 0.0,
+    };
+    [(v1, v2), (v2, v0), (v0, v1)].into_iter().all(|(a, b)| {
+        let (dx, dy) = (a.x - b.x, a.y - b.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        // an edge between two equal verticies has no normal to separate along
+        if length == 0.0 {
+            return true;
+        }
+        let half_extent = 0.5 * (dx.abs() + dy.abs()) / length;
+        exact_edge(middle, a, b) / length > -half_extent
+    })
+}
+
+// triangle_edge for exact (not whole pixel) positions
+fn exact_edge(point: Vector3, v0: Vector3, v1: Vector3) -> f32 {
+    (point.x - v0.x) * (v0.y - v1.y) - (point.y - v0.y) * (v0.x - v1.x)
+}
+
+/*
+ * The inside test for one edge of a triangle, given the edge function w and the edge's direction
+ * in NDC. Pixels right on the edge only count for top and left edges, so a pixel on an edge shared
+ * by two triangles belongs to exactly one of them, the one walking the edge in the other direction.
+ */
+fn top_left_inside(w: f32, edge: Vector3) -> bool {
+    w > 0.0 || (w == 0.0 && ((edge.y == 0.0 && edge.x > 0.0) || edge.y > 0.0))
 }
 
 /*
@@ -160,14 +1649,65 @@ fn triangle_edge(point: ScreenCoordinate, v0: ScreenCoordinate, v1: ScreenCoordi
     ((point.x - v0.x) * (v0.y - v1.y) - (point.y - v0.y) * (v0.x - v1.x)) as f32
 }
 
-/*
- * Expects an NDC vertex
- */
-fn is_on_screen(point: Vector3, near: f32, far: f32) -> bool {
-    point.z > near
-        && point.z < far
-        && point.x >= -1.0
-        && point.x <= 1.0
-        && point.y >= -1.0
-        && point.y <= 1.0
+#[cfg(test)]
+mod test {
+    use crate::rasterizer::*;
+
+    #[test]
+    fn test_clip_triangle_interpolates_attributes() {
+        let vertex = |x: f32, y: f32, u: f32, normal: Vector3| ClipVertex {
+            clip: Vector4 {
+                x,
+                y,
+                z: 0.0,
+                w: 1.0,
+            },
+            world: Vector3 { x, y, z: 0.0 },
+            normal,
+            lighting: Vector3 { x: u, y: u, z: u },
+            uv: Vector3 {
+                x: u,
+                y: 0.0,
+                z: 0.0,
+            },
+            color: Vector3::default(),
+        };
+        let (right, up) = (
+            Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        );
+        let v0 = vertex(-3.0, 0.0, 0.0, right);
+        let v1 = vertex(1.0, 1.0, 1.0, up);
+        let v2 = vertex(1.0, -1.0, 1.0, up);
+
+        // entirely inside comes back untouched
+        let inside = clip_triangle(v1, v2, vertex(0.0, 0.0, 0.0, right));
+        assert_eq!(inside.len(), 3);
+        assert_eq!(inside[0].clip, v1.clip);
+
+        // cutting off the corner past x = -w turns it into a quad, with the new verticies halfway
+        // along the edges to v0
+        let clipped = clip_triangle(v0, v1, v2);
+        assert_eq!(clipped.len(), 4);
+        assert!(clipped.iter().all(|vertex| vertex.clip.x >= -1.0));
+        let cut: Vec<&ClipVertex> = clipped.iter().filter(|v| v.clip.x == -1.0).collect();
+        assert_eq!(cut.len(), 2);
+        for vertex in cut {
+            assert!((vertex.uv.x - 0.5).abs() < 1.0e-6);
+            assert!((vertex.lighting.y - 0.5).abs() < 1.0e-6);
+            assert!(vertex.normal.approx_eq((right + up).normalized(), 1.0e-6));
+        }
+
+        // nothing is left of a triangle entirely behind one plane
+        let behind = |x: f32| vertex(x, 0.0, 0.0, right);
+        assert!(clip_triangle(behind(-3.0), vertex(-2.0, 1.0, 0.0, up), behind(-4.0)).is_empty());
+    }
 }
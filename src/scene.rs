@@ -1,9 +1,14 @@
+use crate::image::{fxaa, Image};
 use crate::math::*;
 use crate::mesh::*;
-use crate::rasterizer::draw_mesh;
+use crate::rasterizer::{
+    clear_buffers, draw_lines, draw_mesh_pass, draw_meshes_tiled_with_overrides,
+    draw_point_elements, mesh_passes_depth_test, Pass, RenderOptions, RenderStats,
+};
 use core::fmt;
 use std::error::Error;
 use std::fs;
+use std::io::Read;
 use std::path::Path;
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -19,7 +24,10 @@ pub struct Camera {
 #[derive(Debug, Default, Copy, Clone)]
 pub struct Light {
     pub position: Vector3,
-    pub color: Color,
+    // linear intensity per channel, 1.0 matches a color of 255 and brighter than that is fine
+    pub color: Vector3,
+    // older scenes give every light its own ambient term, which adds up as lights are added.
+    // Scene::ambient is applied once no matter how many lights there are, prefer it.
     pub ambient_strength: f32,
 }
 
@@ -27,6 +35,18 @@ pub struct Light {
 pub struct Model {
     pub mesh: Mesh,
     pub transform: Mat4,
+    // draw the model in this flat color instead of its material, see RenderOptions::color_override
+    pub color_override: Option<Color>,
+}
+
+impl Model {
+    // options with this model's color override in place of the one passed in, if it has one
+    fn options(&self, options: RenderOptions) -> RenderOptions {
+        RenderOptions {
+            color_override: self.color_override.or(options.color_override),
+            ..options
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -34,6 +54,53 @@ pub struct Scene {
     pub camera: Camera,
     pub models: Vec<Model>,
     pub lights: Vec<Light>,
+    // linear ambient light applied once to every surface, 1.0 matches a color of 255
+    pub ambient: Vector3,
+}
+
+/*
+ * A running average of jittered renders of the same frame, see Scene::accumulate. Every sample
+ * looks through a slightly different sub pixel offset, so the average converges on a supersampled
+ * frame while a usable image can be resolved after any number of samples.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct Accumulator {
+    pub width: usize,
+    pub height: usize,
+    // average color of every pixel so far, row major
+    pub average: Vec<Vector3>,
+    pub sample_count: u32,
+}
+
+// counts describing a scene at a glance, see Scene::summary
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SceneSummary {
+    pub models: usize,
+    // models whose material has a diffuse texture
+    pub textured_models: usize,
+    pub verticies: usize,
+    pub triangles: usize,
+    pub lights: usize,
+    pub canvas_width: i32,
+    pub canvas_height: i32,
+}
+
+impl fmt::Display for SceneSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "models: {} ({} textured)",
+            self.models, self.textured_models
+        )?;
+        writeln!(f, "verticies: {}", self.verticies)?;
+        writeln!(f, "triangles: {}", self.triangles)?;
+        writeln!(f, "lights: {}", self.lights)?;
+        write!(
+            f,
+            "resolution: {}x{}",
+            self.canvas_width, self.canvas_height
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -55,7 +122,29 @@ impl Scene {
             msg: "Invalid scene path".to_string(),
         })?;
 
-        let file_content = fs::read_to_string(path)?.replace('\n', "");
+        Scene::load_from_scene_str(&fs::read_to_string(path)?, parent_dir)
+    }
+
+    // see gltf::load_gltf for what is imported
+    #[cfg(feature = "gltf")]
+    pub fn load_from_gltf(path_str: &str) -> Result<Scene, Box<dyn Error>> {
+        crate::gltf::load_gltf(Path::new(path_str))
+    }
+
+    // mesh paths in a scene read this way are resolved relative to the working directory
+    pub fn load_from_reader<R: Read>(mut reader: R) -> Result<Scene, Box<dyn Error>> {
+        let mut file_content = String::default();
+        reader.read_to_string(&mut file_content)?;
+        Scene::load_from_str(&file_content)
+    }
+
+    // mesh paths in a scene read this way are resolved relative to the working directory
+    pub fn load_from_str(content: &str) -> Result<Scene, Box<dyn Error>> {
+        Scene::load_from_scene_str(content, Path::new(""))
+    }
+
+    fn load_from_scene_str(content: &str, parent_dir: &Path) -> Result<Scene, Box<dyn Error>> {
+        let file_content = content.replace('\n', "");
         let xml_node = parse_scene_file(&file_content)?;
         let mut scene = Scene::default();
 
@@ -70,6 +159,7 @@ impl Scene {
             }));
         }
         let scene_node = &xml_node.children[0];
+        let mut has_ambient = false;
 
         // look over scene node children for camera, lights, models
         for child_node in scene_node.children.iter() {
@@ -79,6 +169,20 @@ impl Scene {
                     .push(model_from_xml_node(child_node, parent_dir)?),
                 "light" => scene.lights.push(light_from_xml_node(child_node)?),
                 "camera" => scene.camera = camera_from_xml_node(child_node)?,
+                "ambient" => {
+                    if has_ambient {
+                        return Err(Box::new(SceneLoadError {
+                            msg: "scene tag has multiple ambient values".to_string(),
+                        }));
+                    }
+                    has_ambient = true;
+                    // like a light's color tag, anything past 255 is overbright
+                    scene.ambient = if child_node.children.len() == 1 {
+                        color_from_xml_node(child_node)?.to_vector3()
+                    } else {
+                        numbers_from_xml_node(child_node, "ambient")? * (1.0 / 255.0)
+                    };
+                }
                 name => {
                     return Err(Box::new(SceneLoadError {
                         msg: format!("Unknown tag {} found", name),
@@ -89,18 +193,479 @@ impl Scene {
         Ok(scene)
     }
 
-    pub fn render(self, pixel_buffer: &mut [Color], depth_buffer: &mut [f32]) {
-        for model in self.models.iter() {
-            draw_mesh(
-                &model.mesh,
-                model.transform,
+    // what's in the scene, e.g. to check an unfamiliar scene loaded the way it was expected to
+    pub fn summary(&self) -> SceneSummary {
+        SceneSummary {
+            models: self.models.len(),
+            textured_models: self
+                .models
+                .iter()
+                .filter(|model| model.mesh.material.diffuse_texture.is_some())
+                .count(),
+            verticies: self
+                .models
+                .iter()
+                .map(|model| model.mesh.verticies.len())
+                .sum(),
+            triangles: self
+                .models
+                .iter()
+                .map(|model| model.mesh.face_indicies.len())
+                .sum(),
+            lights: self.lights.len(),
+            canvas_width: self.camera.canvas_width,
+            canvas_height: self.camera.canvas_height,
+        }
+    }
+
+    // the union of every model's bounds after it is transformed, or a zero sized box at the
+    // origin if the scene has no geometry
+    pub fn bounding_box(&self) -> Aabb {
+        self.models
+            .iter()
+            .filter_map(|model| {
+                Aabb::from_points(
+                    model
+                        .mesh
+                        .verticies
+                        .iter()
+                        .map(|&vertex| model.transform * vertex),
+                )
+            })
+            .reduce(Aabb::union)
+            .unwrap_or_default()
+    }
+
+    /*
+     * Whether nothing of model could show up in front of what depth_buffer already holds, so
+     * drawing it can be skipped. Rather than the model itself this tests the box bounding it
+     * after its transform, through self.camera, which makes the answer conservative: the model
+     * may still be hidden when this says it isn't, but never the other way around. The box is
+     * tested as it would be drawn with options, depth bias and scissor included. Models out of
+     * view or without any verticies count as occluded, while a camera inside of the box or a
+     * depth buffer that isn't the size of the canvas can't rule anything out.
+     */
+    pub fn is_occluded(&self, model: &Model, options: RenderOptions, depth_buffer: &[f32]) -> bool {
+        let Some(bounds) = Aabb::from_points(
+            model
+                .mesh
+                .verticies
+                .iter()
+                .map(|&vertex| model.transform * vertex),
+        ) else {
+            return true;
+        };
+        let camera_position = self
+            .camera
+            .view_mat
+            .inverse()
+            .map(Mat4::translation_part)
+            .unwrap_or_default();
+        let camera_point = Aabb {
+            min: camera_position,
+            max: camera_position,
+        };
+        if bounds.overlaps(camera_point)
+            || depth_buffer.len() != (self.camera.canvas_width * self.camera.canvas_height) as usize
+        {
+            return false;
+        }
+        !mesh_passes_depth_test(
+            &box_mesh(bounds),
+            Mat4::identity(),
+            self.camera,
+            options,
+            depth_buffer,
+        )
+    }
+
+    /*
+     * The models whose bounding sphere, after their transform, is at least partly inside of the
+     * camera's frustum. Everything else can't put a single pixel on screen.
+     */
+    pub fn visible_models(&self) -> impl Iterator<Item = &Model> {
+        self.models_seen_by(self.camera).map(|(_, model)| model)
+    }
+
+    // the models camera sees along with their index in self.models
+    fn models_seen_by(&self, camera: Camera) -> impl Iterator<Item = (usize, &Model)> {
+        self.models.iter().enumerate().filter(move |(_, model)| {
+            let (center, radius) = model.mesh.bounding_sphere();
+            // non-uniform scales stretch the sphere by up to the longest scaled axis
+            let axis_scale = |x: f32, y: f32, z: f32| {
+                (model.transform * Vector4::direction(Vector3 { x, y, z }))
+                    .xyz()
+                    .magnitude()
+            };
+            let scale = axis_scale(1.0, 0.0, 0.0)
+                .max(axis_scale(0.0, 1.0, 0.0))
+                .max(axis_scale(0.0, 0.0, 1.0));
+            camera.sees_sphere(model.transform * center, radius * scale)
+        })
+    }
+
+    /*
+     * Draws every model into the buffers, which are only cleared first if options.clear_color or
+     * options.clear_depth say so. Otherwise whatever an earlier render left behind is kept, so
+     * several renders can be layered into the same buffers. Returns counts of the work it took.
+     */
+    pub fn render(
+        &self,
+        options: RenderOptions,
+        pixel_buffer: &mut [Color],
+        depth_buffer: &mut [f32],
+    ) -> RenderStats {
+        self.render_with_progress(options, pixel_buffer, depth_buffer, |_| {})
+    }
+
+    /*
+     * Renders one more sample of the frame, looking through the sub pixel offset
+     * accumulator.next_jitter() gives, and folds it into accumulator's running average. Every
+     * sample starts from fresh buffers, cleared with options.clear_color if there is one. Panics if
+     * accumulator isn't the size of the camera's canvas, see Accumulator::for_camera.
+     */
+    pub fn accumulate(&self, options: RenderOptions, accumulator: &mut Accumulator) {
+        assert!(
+            accumulator.width as i64 == self.camera.canvas_width as i64
+                && accumulator.height as i64 == self.camera.canvas_height as i64,
+            "a {}x{} accumulator can't hold samples of a {}x{} canvas",
+            accumulator.width,
+            accumulator.height,
+            self.camera.canvas_width,
+            self.camera.canvas_height
+        );
+        let (jitter_x, jitter_y) = accumulator.next_jitter();
+        let num_pixels = accumulator.width * accumulator.height;
+        let mut pixel_buffer = vec![Color::default(); num_pixels];
+        let mut depth_buffer = vec![f32::MAX; num_pixels];
+        self.render_through(
+            self.camera.jittered(jitter_x, jitter_y),
+            None,
+            options,
+            &mut pixel_buffer,
+            &mut depth_buffer,
+            |_| {},
+        );
+        accumulator.add_sample(&pixel_buffer);
+    }
+
+    /*
+     * Like render, but calls progress with the fraction of the frame done so far, after every
+     * model on a single thread or every tile otherwise. Calls always come from the calling thread,
+     * never decrease, and only the last one, once the frame is finished, is 1.0.
+     */
+    pub fn render_with_progress(
+        &self,
+        options: RenderOptions,
+        pixel_buffer: &mut [Color],
+        depth_buffer: &mut [f32],
+        progress: impl FnMut(f32),
+    ) -> RenderStats {
+        self.render_through(
+            self.camera,
+            None,
+            options,
+            pixel_buffer,
+            depth_buffer,
+            progress,
+        )
+    }
+
+    /*
+     * Like render, but only draws self.models[index], which makes it easy to pick out a single
+     * model of a busy scene while debugging. The buffers are cleared as usual. Panics if index is
+     * out of range.
+     */
+    pub fn render_model(
+        &self,
+        index: usize,
+        options: RenderOptions,
+        pixel_buffer: &mut [Color],
+        depth_buffer: &mut [f32],
+    ) -> RenderStats {
+        assert!(index < self.models.len(), "no model at index {index}");
+        self.render_through(
+            self.camera,
+            Some(index),
+            options,
+            pixel_buffer,
+            depth_buffer,
+            |_| {},
+        )
+    }
+
+    /*
+     * render_with_progress looking through camera rather than the scene's own, and only drawing
+     * self.models[index] if only is Some(index)
+     */
+    fn render_through(
+        &self,
+        camera: Camera,
+        only: Option<usize>,
+        options: RenderOptions,
+        pixel_buffer: &mut [Color],
+        depth_buffer: &mut [f32],
+        mut progress: impl FnMut(f32),
+    ) -> RenderStats {
+        // an ambient the caller set wins over the scene's own
+        let options = RenderOptions {
+            ambient: Some(options.ambient.unwrap_or(self.ambient)),
+            ..options
+        };
+        clear_buffers(camera, options, pixel_buffer, depth_buffer);
+        let models = self.draw_order(camera, only, options);
+        let mut stats = RenderStats::default();
+        if options.threads <= 1 {
+            // every model goes through the depth pass before any are shaded, so overdraw between
+            // models is saved too and not just within one
+            let mut unshaded = if options.depth_prepass {
+                vec![false; depth_buffer.len()]
+            } else {
+                Vec::new()
+            };
+            let shading_pass = if options.depth_prepass {
+                for model in models.iter() {
+                    draw_mesh_pass(
+                        &model.mesh,
+                        model.transform,
+                        &self.lights,
+                        camera,
+                        model.options(options),
+                        Pass::Depth,
+                        pixel_buffer,
+                        depth_buffer,
+                        &mut unshaded,
+                        None,
+                    );
+                }
+                Pass::Shade
+            } else {
+                Pass::Full
+            };
+            for (model_idx, model) in models.iter().enumerate() {
+                stats += draw_mesh_pass(
+                    &model.mesh,
+                    model.transform,
+                    &self.lights,
+                    camera,
+                    model.options(options),
+                    shading_pass,
+                    pixel_buffer,
+                    depth_buffer,
+                    &mut unshaded,
+                    None,
+                );
+                // finishing the frame counts as one more step, see below
+                progress((model_idx + 1) as f32 / (models.len() + 1) as f32);
+            }
+        } else {
+            let meshes: Vec<(&Mesh, Mat4)> = models
+                .iter()
+                .map(|model| (&model.mesh, model.transform))
+                .collect();
+            let color_overrides: Vec<Option<Color>> = models
+                .iter()
+                .map(|model| model.options(options).color_override)
+                .collect();
+            stats = draw_meshes_tiled_with_overrides(
+                &meshes,
+                &color_overrides,
                 &self.lights,
-                self.camera,
+                camera,
+                options,
                 pixel_buffer,
                 depth_buffer,
+                // the frame isn't done once the tiles are, see below
+                &mut |fraction| {
+                    if fraction < 1.0 {
+                        progress(fraction);
+                    }
+                },
+            );
+            // lines and points are cheap next to the triangles, so they're drawn on this thread
+            // once the tiles are in
+            for &model in &models {
+                let options = model.options(options);
+                draw_model_primitives(model, camera, options, pixel_buffer, depth_buffer);
+            }
+        }
+
+        if options.fxaa {
+            let (width, height) = (camera.canvas_width.max(0), camera.canvas_height.max(0));
+            let (x0, y0, x1, y1) = options.scissor.unwrap_or((0, 0, width, height));
+            let region = (
+                x0.clamp(0, width) as usize,
+                y0.clamp(0, height) as usize,
+                x1.clamp(0, width) as usize,
+                y1.clamp(0, height) as usize,
             );
+            fxaa(pixel_buffer, width as usize, height as usize, region);
+        }
+        // the only report of 1.0, once nothing is left to draw
+        progress(1.0);
+        stats
+    }
+
+    /*
+     * The models camera sees (limited to self.models[index] if only is Some(index)) with every
+     * transparent one moved after the opaque ones, since they don't write depth and would
+     * otherwise be painted over by opaque models behind them. With options.depth_sort_transparent
+     * the transparent models are also drawn farthest first, by the view space depth of their
+     * bounding sphere's center.
+     */
+    fn draw_order(
+        &self,
+        camera: Camera,
+        only: Option<usize>,
+        options: RenderOptions,
+    ) -> Vec<&Model> {
+        let (mut models, mut transparent): (Vec<&Model>, Vec<&Model>) = self
+            .models_seen_by(camera)
+            .filter(|&(model_idx, _)| only.is_none_or(|index| index == model_idx))
+            .map(|(_, model)| model)
+            .partition(|model| !model.mesh.material.is_transparent());
+        if options.depth_sort_transparent {
+            let view_depth = |model: &Model| {
+                let (center, _) = model.mesh.bounding_sphere();
+                (camera.view_mat * model.transform * center).z
+            };
+            transparent.sort_by(|a, b| view_depth(a).total_cmp(&view_depth(b)));
+        }
+        models.append(&mut transparent);
+        models
+    }
+}
+
+// the six faces of bounds as a mesh, with every face wound both ways so it's seen from any side
+pub(crate) fn box_mesh(bounds: Aabb) -> Mesh {
+    // corners are numbered as in Aabb::corners, bit 0 picks x, bit 1 y and bit 2 z
+    let quads = [
+        [0, 2, 6, 4],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 3, 7, 6],
+        [0, 1, 3, 2],
+        [4, 5, 7, 6],
+    ];
+    let triangle = |a: usize, b: usize, c: usize| Triangle {
+        a,
+        b,
+        c,
+        ..Default::default()
+    };
+    Mesh {
+        verticies: bounds.corners().to_vec(),
+        face_indicies: quads
+            .iter()
+            .flat_map(|&[a, b, c, d]| {
+                [
+                    triangle(a, b, c),
+                    triangle(a, c, d),
+                    triangle(a, c, b),
+                    triangle(a, d, c),
+                ]
+            })
+            .collect(),
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        ..Default::default()
+    }
+}
+
+// the lines and points draw_mesh would draw, for models whose triangles were drawn in tiles
+fn draw_model_primitives(
+    model: &Model,
+    camera: Camera,
+    options: RenderOptions,
+    pixel_buffer: &mut [Color],
+    depth_buffer: &mut [f32],
+) {
+    let color = options
+        .color_override
+        .unwrap_or(model.mesh.material.diffuse_color);
+    if !model.mesh.edges.is_empty() {
+        draw_lines(
+            &model.mesh,
+            model.transform,
+            camera,
+            options,
+            color,
+            pixel_buffer,
+            depth_buffer,
+        );
+    }
+    if !model.mesh.points.is_empty() {
+        draw_point_elements(
+            &model.mesh,
+            model.transform,
+            camera,
+            options,
+            color,
+            pixel_buffer,
+            depth_buffer,
+        );
+    }
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            average: vec![Vector3::default(); width * height],
+            sample_count: 0,
         }
     }
+
+    // an accumulator the size of camera's canvas
+    pub fn for_camera(camera: Camera) -> Accumulator {
+        Accumulator::new(
+            camera.canvas_width.max(0) as usize,
+            camera.canvas_height.max(0) as usize,
+        )
+    }
+
+    // starts over from no samples, e.g. after the camera or scene changed
+    pub fn reset(&mut self) {
+        self.average.fill(Vector3::default());
+        self.sample_count = 0;
+    }
+
+    /*
+     * The sub pixel offset the next sample is taken at, in pixels between -0.5 and 0.5 on each
+     * axis. Samples walk the (2, 3) halton sequence so any number of them covers the pixel evenly.
+     */
+    pub fn next_jitter(&self) -> (f32, f32) {
+        let index = self.sample_count + 1;
+        (halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+    }
+
+    // folds one more frame, the size of the accumulator, into the running average
+    pub fn add_sample(&mut self, pixel_buffer: &[Color]) {
+        assert_eq!(
+            pixel_buffer.len(),
+            self.average.len(),
+            "sample is {} pixels but the accumulator holds {}",
+            pixel_buffer.len(),
+            self.average.len()
+        );
+        self.sample_count += 1;
+        let weight = 1.0 / self.sample_count as f32;
+        for (average, pixel) in self.average.iter_mut().zip(pixel_buffer) {
+            *average = *average + (pixel.to_vector3() - *average) * weight;
+        }
+    }
+
+    pub fn resolve(&self) -> Image {
+        let mut ret = Image::new(self.width, self.height);
+        for (pixel, average) in ret.data.iter_mut().zip(&self.average) {
+            *pixel = average.to_color();
+        }
+        ret
+    }
 }
 
 fn model_from_xml_node(model_node: &XMLNode, parent_path: &Path) -> Result<Model, Box<dyn Error>> {
@@ -110,6 +675,9 @@ fn model_from_xml_node(model_node: &XMLNode, parent_path: &Path) -> Result<Model
     let mut has_position = false;
     let mut has_scale = false;
     let mut has_rotation = false;
+    // applied once the mesh is loaded since the tags can come in any order
+    let mut base_color = None;
+    let mut emissive_color = None;
 
     for model_property in model_node.children.iter() {
         match model_property.name.as_str() {
@@ -125,6 +693,14 @@ fn model_from_xml_node(model_node: &XMLNode, parent_path: &Path) -> Result<Model
                         msg: "mesh tag did not specify a path".to_string(),
                     }));
                 }
+                // numbers come out of the parser as nameless nodes with data, only a quoted token
+                // gives us a usable path
+                let mesh_path_node = &model_property.children[0];
+                if mesh_path_node.data.is_some() || mesh_path_node.name.is_empty() {
+                    return Err(Box::new(SceneLoadError {
+                        msg: "mesh tag path was not a quoted file name".to_string(),
+                    }));
+                }
                 let mesh_file_name = Path::new(&model_property.children[0].name);
                 model.mesh = Mesh::from_obj_file(&parent_path.join(mesh_file_name))?;
             }
@@ -186,6 +762,30 @@ fn model_from_xml_node(model_node: &XMLNode, parent_path: &Path) -> Result<Model
                     }))?;
                 model.transform = model.transform * Mat4::translation(x, y, z);
             }
+            "color" => {
+                if base_color.is_some() {
+                    return Err(Box::new(SceneLoadError {
+                        msg: "model tag has multiple color values".to_string(),
+                    }));
+                }
+                base_color = Some(color_from_xml_node(model_property)?);
+            }
+            "emissive" => {
+                if emissive_color.is_some() {
+                    return Err(Box::new(SceneLoadError {
+                        msg: "model tag has multiple emissive values".to_string(),
+                    }));
+                }
+                emissive_color = Some(color_from_xml_node(model_property)?);
+            }
+            "override_color" => {
+                if model.color_override.is_some() {
+                    return Err(Box::new(SceneLoadError {
+                        msg: "model tag has multiple override_color values".to_string(),
+                    }));
+                }
+                model.color_override = Some(color_from_xml_node(model_property)?);
+            }
             "scale" => {
                 if has_scale {
                     return Err(Box::new(SceneLoadError {
@@ -237,10 +837,94 @@ fn model_from_xml_node(model_node: &XMLNode, parent_path: &Path) -> Result<Model
         }));
     }
 
+    if let Some(color) = base_color {
+        model.mesh.material.diffuse_color = color;
+    }
+    if let Some(color) = emissive_color {
+        model.mesh.material.emissive_color = color;
+    }
+
     Ok(model)
 }
 
+// <color> r g b </color> with each channel between 0 and 255
+#[allow(clippy::manual_range_contains)]
+fn color_from_xml_node(color_node: &XMLNode) -> Result<Color, Box<dyn Error>> {
+    // a single #RRGGBB can stand in for the three numbers
+    if let [hex] = color_node.children.as_slice() {
+        return Color::from_hex(&hex.name).ok_or(Box::new(SceneLoadError {
+            msg: format!(
+                "color tag contained {:?} which is not a #RRGGBB color",
+                hex.name
+            ),
+        }));
+    }
+    if color_node.children.len() != 3 {
+        return Err(Box::new(SceneLoadError {
+            msg: "color tag did not specify three numbers (RGB)".to_string(),
+        }));
+    }
+    let r = color_node.children[0].data.ok_or(Box::new(SceneLoadError {
+        msg: "color tag contained something other than a number".to_string(),
+    }))?;
+    let g = color_node.children[1].data.ok_or(Box::new(SceneLoadError {
+        msg: "color tag contained something other than a number".to_string(),
+    }))?;
+    let b = color_node.children[2].data.ok_or(Box::new(SceneLoadError {
+        msg: "color tag contained something other than a number".to_string(),
+    }))?;
+
+    if r > 255.0 || r < 0.0 {
+        return Err(Box::new(SceneLoadError {
+            msg: "red value in color tag was not between 0 and 255".to_string(),
+        }));
+    }
+
+    if g > 255.0 || g < 0.0 {
+        return Err(Box::new(SceneLoadError {
+            msg: "green value in color tag was not between 0 and 255".to_string(),
+        }));
+    }
+
+    if b > 255.0 || b < 0.0 {
+        return Err(Box::new(SceneLoadError {
+            msg: "blue value in color tag was not between 0 and 255".to_string(),
+        }));
+    }
+
+    Ok(Color {
+        r: f32::floor(r) as u8,
+        g: f32::floor(g) as u8,
+        b: f32::floor(b) as u8,
+    })
+}
+
 #[allow(clippy::manual_range_contains)]
+// three non-negative numbers without an upper limit, tag_name is only used in errors
+fn numbers_from_xml_node(node: &XMLNode, tag_name: &str) -> Result<Vector3, Box<dyn Error>> {
+    if node.children.len() != 3 {
+        return Err(Box::new(SceneLoadError {
+            msg: format!("{} tag did not specify three numbers (RGB)", tag_name),
+        }));
+    }
+    let mut numbers = [0.0; 3];
+    for (number, child) in numbers.iter_mut().zip(node.children.iter()) {
+        *number = child.data.ok_or(Box::new(SceneLoadError {
+            msg: format!("{} tag contained something other than a number", tag_name),
+        }))?;
+        if *number < 0.0 {
+            return Err(Box::new(SceneLoadError {
+                msg: format!("{} tag contained a negative number", tag_name),
+            }));
+        }
+    }
+    Ok(Vector3 {
+        x: numbers[0],
+        y: numbers[1],
+        z: numbers[2],
+    })
+}
+
 fn light_from_xml_node(light_node: &XMLNode) -> Result<Light, Box<dyn Error>> {
     let mut light: Light = Default::default();
 
@@ -276,48 +960,21 @@ fn light_from_xml_node(light_node: &XMLNode) -> Result<Light, Box<dyn Error>> {
                     }));
                 }
                 has_color = true;
-                if light_property.children.len() != 3 {
-                    return Err(Box::new(SceneLoadError {
-                        msg: "color tag did not specify three numbers (RGB)".to_string(),
-                    }));
-                }
-                let r = light_property.children[0]
-                    .data
-                    .ok_or(Box::new(SceneLoadError {
-                        msg: "color tag contained something other than a number".to_string(),
-                    }))?;
-                let g = light_property.children[1]
-                    .data
-                    .ok_or(Box::new(SceneLoadError {
-                        msg: "color tag contained something other than a number".to_string(),
-                    }))?;
-                let b = light_property.children[2]
-                    .data
-                    .ok_or(Box::new(SceneLoadError {
-                        msg: "color tag contained something other than a number".to_string(),
-                    }))?;
-
-                if r > 255.0 || r < 0.0 {
-                    return Err(Box::new(SceneLoadError {
-                        msg: "red value in color tag was not between 0 and 255".to_string(),
-                    }));
-                }
-
-                if g > 255.0 || g < 0.0 {
-                    return Err(Box::new(SceneLoadError {
-                        msg: "green value in color tag was not between 0 and 255".to_string(),
-                    }));
-                }
-
-                if b > 255.0 || b < 0.0 {
+                // 0 to 255 like every other color tag, but anything past 255 is an overbright light
+                light.color = if light_property.children.len() == 1 {
+                    color_from_xml_node(light_property)?.to_vector3()
+                } else {
+                    numbers_from_xml_node(light_property, "color")? * (1.0 / 255.0)
+                };
+            }
+            "intensity" => {
+                if has_color {
                     return Err(Box::new(SceneLoadError {
-                        msg: "blue value in color tag was not between 0 and 255".to_string(),
+                        msg: "light tag has multiple color values".to_string(),
                     }));
                 }
-
-                light.color.r = f32::floor(r) as u8;
-                light.color.g = f32::floor(g) as u8;
-                light.color.b = f32::floor(b) as u8;
+                has_color = true;
+                light.color = numbers_from_xml_node(light_property, "intensity")?;
             }
             "position" => {
                 if has_position {
@@ -542,26 +1199,226 @@ fn camera_from_xml_node(camera_node: &XMLNode) -> Result<Camera, Box<dyn Error>>
         }));
     }
 
+    if near <= 0.0 || far <= near {
+        return Err(Box::new(SceneLoadError {
+            msg: format!(
+                "projection tag needs 0 < near plane < far plane but got near {} and far {}",
+                near, far
+            ),
+        }));
+    }
+
     let mut camera = Camera::new(canvas_width, canvas_height, fov, near, far);
-    camera.view_mat = Mat4::look_at(position, look_at, up);
+    camera.view_mat = Mat4::look_at(position, look_at, up)?;
     Ok(camera)
 }
 
 impl Camera {
+    // a far plane of f32::INFINITY uses an infinite perspective projection
     pub fn new(canvas_width: i32, canvas_height: i32, fov: f32, near: f32, far: f32) -> Camera {
-        Camera {
+        let mut ret = Camera {
             near_plane: near,
             far_plane: far,
             canvas_width,
             canvas_height,
             view_mat: Mat4::identity(),
-            projection_mat: Mat4::perspective(
-                canvas_width as f32 / canvas_height as f32,
-                fov,
-                near,
-                far,
-            ),
+            projection_mat: Mat4::identity(),
+        };
+        ret.rebuild_projection(fov);
+        ret
+    }
+
+    // the world space volume the camera sees
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.projection_mat * self.view_mat)
+    }
+
+    /*
+     * False only when the sphere is entirely outside of one of the frustum's planes. Spheres
+     * straddling a corner outside of the frustum still count as visible, which is fine for culling.
+     */
+    pub fn sees_sphere(&self, center: Vector3, radius: f32) -> bool {
+        self.frustum().intersects_sphere(center, radius)
+    }
+
+    /*
+     * The world position seen at the middle of the given pixel at the given depth, the inverse of
+     * projecting a world point and calling ndc_to_pixel. depth is NDC depth as found in the depth
+     * buffer, so -1 lands on the near plane and 1 on the far plane.
+     */
+    pub fn unproject(&self, screen: ScreenCoordinate, depth: f32) -> Vector3 {
+        let ndc = Vector3 {
+            x: (screen.x as f32 + 0.5) / self.canvas_width as f32 * 2.0 - 1.0,
+            y: 1.0 - (screen.y as f32 + 0.5) / self.canvas_height as f32 * 2.0,
+            z: depth,
+        };
+        // a perspective projection and a rigid view matrix always invert, so this only fails for
+        // hand built degenerate matrices
+        let inverse = (self.projection_mat * self.view_mat)
+            .inverse()
+            .expect("view projection matrix is not invertible");
+        inverse * ndc
+    }
+
+    /*
+     * Bilinearly filters the depth buffer at a sub pixel position, where (x + 0.5, y + 0.5) is the
+     * middle of pixel (x, y). Filtering across a silhouette would make up a depth somewhere
+     * between the surface and the background, so if any of the four neighbors is empty (past the
+     * far plane) the nearest pixel's depth is returned instead.
+     */
+    pub fn depth_at(&self, depth_buffer: &[f32], x: f32, y: f32) -> f32 {
+        let width = self.canvas_width.max(1) as usize;
+        let height = self.canvas_height.max(1) as usize;
+        let clamp_x = |idx: f32| (idx.max(0.0) as usize).min(width - 1);
+        let clamp_y = |idx: f32| (idx.max(0.0) as usize).min(height - 1);
+        let depth = |x: usize, y: usize| depth_buffer[y * width + x];
+
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, x1) = (clamp_x(x.floor()), clamp_x(x.floor() + 1.0));
+        let (y0, y1) = (clamp_y(y.floor()), clamp_y(y.floor() + 1.0));
+        let neighbors = [depth(x0, y0), depth(x1, y0), depth(x0, y1), depth(x1, y1)];
+        if neighbors.iter().any(|&depth| depth > 1.0) {
+            return depth(clamp_x(x.round()), clamp_y(y.round()));
         }
+
+        let (x_weight, y_weight) = (x - x.floor(), y - y.floor());
+        let top = neighbors[0] * (1.0 - x_weight) + neighbors[1] * x_weight;
+        let bottom = neighbors[2] * (1.0 - x_weight) + neighbors[3] * x_weight;
+        top * (1.0 - y_weight) + bottom * y_weight
+    }
+
+    /*
+     * A grayscale picture of the depth buffer for debugging, black on the near plane and white on
+     * the far plane (and where nothing was drawn). Depths are turned back into distances from the
+     * camera first so the gray scales linearly. With an infinite far plane the farthest drawn
+     * depth is used as white instead.
+     */
+    pub fn depth_image(&self, depth_buffer: &[f32]) -> Image {
+        // both projections give ndc depth = -m22 + m32 / distance, so invert that
+        let (m22, m32) = (*self.projection_mat.at(2, 2), *self.projection_mat.at(3, 2));
+        let distances: Vec<Option<f32>> = depth_buffer
+            .iter()
+            .map(|&depth| (depth <= 1.0).then(|| m32 / (depth + m22)))
+            .collect();
+        let far = if self.far_plane == f32::INFINITY {
+            distances
+                .iter()
+                .flatten()
+                .fold(self.near_plane, |far, &d| far.max(d))
+        } else {
+            self.far_plane
+        };
+
+        let mut ret = Image::new(self.canvas_width as usize, self.canvas_height as usize);
+        for (pixel, distance) in ret.data.iter_mut().zip(distances) {
+            let gray = match distance {
+                Some(distance) if far > self.near_plane => {
+                    ((distance - self.near_plane) / (far - self.near_plane)).clamp(0.0, 1.0)
+                }
+                Some(_) => 0.0,
+                None => 1.0,
+            };
+            let gray = (gray * 255.0).round() as u8;
+            *pixel = Color {
+                r: gray,
+                g: gray,
+                b: gray,
+            };
+        }
+        ret
+    }
+
+    // the vertical fov in radians, recovered from the 1 / tan(fov / 2) the projection stores
+    pub fn fov(&self) -> f32 {
+        2.0 * (1.0 / self.projection_mat.at(1, 1)).atan()
+    }
+
+    // only rebuilds the projection, the view matrix and so where the camera looks is kept
+    pub fn set_fov(&mut self, fov: f32) {
+        self.rebuild_projection(fov);
+    }
+
+    // resizes the canvas and rebuilds the projection for its aspect ratio, keeping the fov and view
+    pub fn set_aspect(&mut self, canvas_width: i32, canvas_height: i32) {
+        let fov = self.fov();
+        self.canvas_width = canvas_width;
+        self.canvas_height = canvas_height;
+        self.rebuild_projection(fov);
+    }
+
+    /*
+     * This camera with everything it sees shifted by a fraction of a pixel, +x right and +y down
+     * like pixel coordinates. The shift is applied after the projection so it stays the same
+     * number of pixels at every depth. Rebuilding the projection (set_fov, set_aspect) drops it.
+     */
+    pub fn jittered(self, x: f32, y: f32) -> Camera {
+        let shift = Mat4::translation(
+            2.0 * x / self.canvas_width as f32,
+            -2.0 * y / self.canvas_height as f32,
+            0.0,
+        );
+        Camera {
+            projection_mat: shift * self.projection_mat,
+            ..self
+        }
+    }
+
+    fn rebuild_projection(&mut self, fov: f32) {
+        let aspect_ratio = self.canvas_width as f32 / self.canvas_height as f32;
+        self.projection_mat = if self.far_plane == f32::INFINITY {
+            Mat4::perspective_infinite(aspect_ratio, fov, self.near_plane)
+        } else {
+            Mat4::perspective(aspect_ratio, fov, self.near_plane, self.far_plane)
+        };
+    }
+
+    /*
+     * Keeps the camera's orientation but moves it back along its forward axis until the sphere
+     * around aabb fits inside of the narrower of the horizontal and vertical fov. The projection
+     * is left alone, so a box too deep for the far plane still gets clipped.
+     */
+    pub fn frame(&mut self, aabb: &Aabb) {
+        // the projection stores 1 / tan(fov / 2) on its diagonal
+        let half_fov = f32::min(
+            (1.0 / self.projection_mat.at(0, 0)).atan(),
+            (1.0 / self.projection_mat.at(1, 1)).atan(),
+        );
+        let center = aabb.center();
+        let radius = Vector3::distance(aabb.min, aabb.max) / 2.0;
+        let distance = f32::max(radius / half_fov.sin(), radius + self.near_plane);
+
+        let mut rotation = self.view_mat;
+        *rotation.mut_at(3, 0) = 0.0;
+        *rotation.mut_at(3, 1) = 0.0;
+        *rotation.mut_at(3, 2) = 0.0;
+
+        // move the center to the origin, rotate into camera space, then push it down -z
+        self.view_mat = Mat4::translation(0.0, 0.0, -distance)
+            * rotation
+            * Mat4::translation(-center.x, -center.y, -center.z);
+    }
+
+    /*
+     * Throws away the camera's view for one that looks at the center of scene's bounding box from
+     * the front, a little above and to the right, then backs off with Camera::frame until the
+     * whole box is in view. Handy when a scene's authored camera points away from the geometry.
+     */
+    pub fn frame_scene(&mut self, scene: &Scene) {
+        let aabb = scene.bounding_box();
+        let center = aabb.center();
+        let direction = Vector3 {
+            x: 0.5,
+            y: 0.5,
+            z: 1.0,
+        };
+        let up = Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        };
+        self.view_mat = Mat4::look_at(center + direction, center, up)
+            .expect("the framing direction is never parallel to up");
+        self.frame(&aabb);
     }
 }
 
@@ -597,6 +1454,7 @@ enum XMLToken {
     Number(f32),
     Name(String),
     Quote(String),
+    Hex(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -607,6 +1465,7 @@ enum RegexStates {
     InNumber,
     InName,
     InQuote,
+    InHex,
 }
 
 struct TokenizedFile {
@@ -781,7 +1640,8 @@ fn parse_tag_start_and_end(
     Ok(())
 }
 
-// <tag-content> = <number> <tag-content> | <quote> <tag-content> | <tag> <tag-content> | ""
+// <tag-content> = <number> <tag-content> | <quote> <tag-content> | <hex> <tag-content>
+//                 | <tag> <tag-content> | ""
 fn parse_tag_content(tokens: &mut TokenizedFile, node: &mut XMLNode) -> Result<(), XMLParseError> {
     if let Some(XMLToken::Number(num)) = tokens.peek() {
         node.children.push(XMLNode {
@@ -805,6 +1665,18 @@ fn parse_tag_content(tokens: &mut TokenizedFile, node: &mut XMLNode) -> Result<(
         return parse_tag_content(tokens, node);
     }
 
+    // hex colors are kept as written, like quotes
+    if let Some(XMLToken::Hex(hex)) = tokens.peek() {
+        node.children.push(XMLNode {
+            name: hex,
+            data: None,
+            children: Vec::default(),
+            attributes: Vec::default(),
+        });
+        tokens.consume();
+        return parse_tag_content(tokens, node);
+    }
+
     if let Some(XMLToken::OpenBracket) = tokens.peek() {
         parse_xml_node(tokens, node)?;
         return parse_tag_content(tokens, node);
@@ -858,6 +1730,7 @@ fn parse_tag_end(tokens: &mut TokenizedFile, node: &mut XMLNode) -> Result<(), X
 // Numbers accumulate until they run out of digits
 // Names accumulate until they run out of alphanumerics
 // Quotes accumulate until they hit another "
+// Hex colors start with # and accumulate until they run out of hex digits
 fn lex_scene_file(raw_text: &str) -> Option<TokenizedFile> {
     lex_scene_file_recursively(
         raw_text,
@@ -900,6 +1773,10 @@ fn lex_scene_file_recursively(
                 } else if c == '"' {
                     remaining_text = &text[1..];
                     state = RegexStates::InQuote;
+                } else if c == '#' {
+                    accumulator.push(c);
+                    remaining_text = &text[1..];
+                    state = RegexStates::InHex;
                 } else if c.is_ascii_digit() || c == '-' {
                     accumulator.push(c);
                     remaining_text = &text[1..];
@@ -938,7 +1815,8 @@ fn lex_scene_file_recursively(
                 }
             }
             RegexStates::InName => {
-                if c.is_ascii_alphanumeric() {
+                // underscores are allowed after the first character, e.g. override_color
+                if c.is_ascii_alphanumeric() || c == '_' {
                     accumulator.push(c);
                     remaining_text = &text[1..];
                 } else {
@@ -961,6 +1839,17 @@ fn lex_scene_file_recursively(
                     state = RegexStates::Ready;
                 }
             }
+            RegexStates::InHex => {
+                if c.is_ascii_hexdigit() {
+                    accumulator.push(c);
+                    remaining_text = &text[1..];
+                } else {
+                    tokens.push(XMLToken::Hex(accumulator.iter().collect()));
+                    accumulator.clear();
+                    // we do not consume the character here
+                    state = RegexStates::Ready;
+                }
+            }
             RegexStates::InQuote => {
                 if c == '"' {
                     tokens.push(XMLToken::Quote(accumulator.iter().collect()));
@@ -1005,6 +1894,23 @@ mod test {
         assert_eq!(tokens.unwrap().tokens, actual_tokens);
     }
 
+    #[test]
+    fn test_xml_lex_hex() {
+        let tokens = lex_scene_file("<color>#Ff8800</color>");
+        assert_eq!(
+            tokens.unwrap().tokens,
+            vec![
+                XMLToken::OpenBracket,
+                XMLToken::Name("color".to_string()),
+                XMLToken::CloseBracket,
+                XMLToken::Hex("#Ff8800".to_string()),
+                XMLToken::OpenSlashBracket,
+                XMLToken::Name("color".to_string()),
+                XMLToken::CloseBracket,
+            ]
+        );
+    }
+
     #[test]
     fn test_xml_lex_nested() {
         let example_tag =
@@ -1085,7 +1991,7 @@ mod test {
         assert_eq!(node.children.len(), 1);
 
         // pog node
-        test_for_childless_tag(node.children.get(0), "pog");
+        test_for_childless_tag(node.children.first(), "pog");
     }
 
     #[test]
@@ -1112,21 +2018,21 @@ mod test {
         assert!(node.data.is_none());
         assert_eq!(node.children.len(), 1);
 
-        let maybe_scene = node.children.get(0);
+        let maybe_scene = node.children.first();
         test_for_parent_tag(maybe_scene, "scene", 4);
 
-        test_for_childless_tag(maybe_scene.unwrap().children.get(0), "mesh");
+        test_for_childless_tag(maybe_scene.unwrap().children.first(), "mesh");
 
         let maybe_light = maybe_scene.unwrap().children.get(1);
         test_for_parent_tag(maybe_light, "light", 3);
 
-        test_for_num(maybe_light.unwrap().children.get(0), 1.0);
+        test_for_num(maybe_light.unwrap().children.first(), 1.0);
         test_for_num(maybe_light.unwrap().children.get(1), 2.0);
         test_for_num(maybe_light.unwrap().children.get(2), 3.0);
 
         let maybe_placeholder = maybe_scene.unwrap().children.get(2);
         test_for_parent_tag(maybe_placeholder, "placeholder", 1);
-        test_for_name(maybe_placeholder.unwrap().children.get(0), "some_names");
+        test_for_name(maybe_placeholder.unwrap().children.first(), "some_names");
 
         test_for_num(maybe_scene.unwrap().children.get(3), 8.0);
     }
@@ -1137,8 +2043,7 @@ mod test {
         let maybe_node = parse_scene_file(example_tag);
 
         let Err(parse_error) = maybe_node else {
-            assert!(false);
-            return;
+            panic!("expected the parse to fail");
         };
         assert!(!parse_error.msg.is_empty());
     }
@@ -1149,8 +2054,7 @@ mod test {
         let maybe_node = parse_scene_file(example_tag);
 
         let Err(parse_error) = maybe_node else {
-            assert!(false);
-            return;
+            panic!("expected the parse to fail");
         };
         assert!(!parse_error.msg.is_empty());
     }
@@ -1161,8 +2065,7 @@ mod test {
         let maybe_node = parse_scene_file(example_tag);
 
         let Err(parse_error) = maybe_node else {
-            assert!(false);
-            return;
+            panic!("expected the parse to fail");
         };
         assert!(!parse_error.msg.is_empty());
     }
@@ -1190,6 +2093,19 @@ mod test {
         assert!(!error.msg.is_empty());
     }
 
+    #[test]
+    fn test_camera_invalid_clip_planes() {
+        for projection in ["10 10 1.0 1.0 1.0", "10 10 1.0 0 100", "10 10 1.0 5 2"] {
+            let camera_xml = format!(
+                "<camera><projection>{}</projection><position>0 0 0</position><lookat>0 0 -1</lookat><up>0 1 0</up></camera>",
+                projection
+            );
+            let node = parse_scene_file(&camera_xml).unwrap();
+            let maybe_camera = camera_from_xml_node(&node.children[0]);
+            assert!(maybe_camera.is_err());
+        }
+    }
+
     // TODO: test the full scene loading including edge cases like multi tags or not enough tags
     // (will need to break out the file reading bit so you can pass in strings instead of files)
 }
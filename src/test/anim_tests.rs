@@ -0,0 +1,61 @@
+use super::TempDir;
+use crate::anim::*;
+use crate::image::Image;
+use crate::math::*;
+use crate::mesh::*;
+use crate::rasterizer::RenderOptions;
+use crate::scene::*;
+use std::fs;
+
+#[test]
+fn test_render_turntable() {
+    let mut scene = Scene {
+        camera: Camera::new(8, 6, 90_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    scene.models.push(Model {
+        mesh: Mesh {
+            verticies: vec![
+                Vector3 {
+                    x: -1.0,
+                    y: -1.0,
+                    z: -3.0,
+                },
+                Vector3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: -3.0,
+                },
+                Vector3 {
+                    x: 1.0,
+                    y: -1.0,
+                    z: -3.0,
+                },
+            ],
+            face_indicies: vec![Triangle {
+                a: 0,
+                b: 1,
+                c: 2,
+                ..Default::default()
+            }],
+            vertex_normals: vec![Vector3::default()],
+            ..Default::default()
+        },
+        transform: Mat4::identity(),
+        ..Default::default()
+    });
+
+    let output_dir = TempDir::new("render_turntable");
+
+    let result = render_turntable(&scene, 3, RenderOptions::default(), &output_dir);
+    assert!(result.is_ok());
+
+    for frame in 0..3 {
+        let frame_path = output_dir.join(format!("frame_{:04}.ppm", frame));
+        let contents = fs::read_to_string(&frame_path).unwrap();
+        assert!(contents.starts_with("P3\n8 6\n255\n"));
+        let frame_image = Image::load_ppm(&frame_path).unwrap();
+        assert_eq!((frame_image.width, frame_image.height), (8, 6));
+    }
+    assert!(!output_dir.join("frame_0003.ppm").exists());
+}
@@ -0,0 +1,256 @@
+use super::TempDir;
+use crate::image::*;
+use crate::math::*;
+use crate::png::encode_png;
+use crate::scene::*;
+use std::fs;
+
+// a triangle's positions followed by its u16 indices, padded to a multiple of four bytes
+fn triangle_buffer() -> Vec<u8> {
+    let mut bytes = vec![];
+    for value in [0.0_f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for index in [0_u16, 1, 2, 0] {
+        bytes.extend_from_slice(&index.to_le_bytes());
+    }
+    bytes
+}
+
+const TRIANGLE_ACCESSORS: &str = r#"
+    "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+        { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+    ],
+    "accessors": [
+        { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+        { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+    ]"#;
+
+#[test]
+fn test_load_gltf_with_external_buffer() {
+    let dir = TempDir::new("load_gltf_with_external_buffer");
+    fs::write(dir.join("triangle data.bin"), triangle_buffer()).unwrap();
+    fs::write(
+        dir.join("triangle.gltf"),
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "translation": [1, 2, 3], "children": [1] }},
+                    {{ "scale": [2, 2, 2], "mesh": 0 }}
+                ],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}],
+                "materials": [{{ "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0.5, 0, 1] }}, "emissiveFactor": [0, 0, 1] }}],
+                "buffers": [{{ "uri": "triangle%20data.bin", "byteLength": 44 }}],
+                {}
+            }}"#,
+            TRIANGLE_ACCESSORS
+        ),
+    )
+    .unwrap();
+
+    let scene = Scene::load_from_gltf(dir.join("triangle.gltf").to_str().unwrap()).unwrap();
+    assert_eq!(scene.models.len(), 1);
+    let model = &scene.models[0];
+    assert_eq!(model.mesh.verticies.len(), 3);
+    assert_eq!(
+        model.transform
+            * Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0
+            },
+        Vector3 {
+            x: 3.0,
+            y: 2.0,
+            z: 3.0
+        }
+    );
+
+    // the counter-clockwise glTF face becomes a clockwise one with a +z normal
+    let face = model.mesh.face_indicies[0];
+    assert_eq!((face.a, face.b, face.c), (0, 2, 1));
+    for normal in model.mesh.vertex_normals.iter() {
+        assert_eq!(
+            *normal,
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0
+            }
+        );
+    }
+    assert_eq!(
+        model.mesh.material.diffuse_color,
+        Color {
+            r: 255,
+            g: 128,
+            b: 0
+        }
+    );
+    assert_eq!(
+        model.mesh.material.emissive_color,
+        Color { r: 0, g: 0, b: 255 }
+    );
+    assert_eq!(model.mesh.material.uv_origin, UvOrigin::TopLeft);
+
+    // without a camera the default one looks at the geometry, lit from where it stands
+    assert_eq!(scene.camera.canvas_width, 640);
+    assert_eq!(scene.camera.canvas_height, 480);
+    assert_eq!(scene.lights.len(), 1);
+    let mut pixels = vec![Color::default(); 640 * 480];
+    let mut depths = vec![f32::MAX; 640 * 480];
+    scene.render(Default::default(), &mut pixels, &mut depths);
+    assert!(pixels.iter().any(|&pixel| pixel != Color::default()));
+}
+
+#[test]
+fn test_load_glb() {
+    let mut texture = Image::new(2, 2);
+    texture.data[0] = Color { r: 255, g: 0, b: 0 };
+    let png = encode_png(&texture);
+
+    // the triangle, then normals, then texture coordinates, then the png
+    let mut bin = triangle_buffer();
+    for _ in 0..3 {
+        for value in [0.0_f32, 0.0, 1.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for value in [0.0_f32, 0.0, 1.0, 0.0, 0.0, 1.0] {
+        bin.extend_from_slice(&value.to_le_bytes());
+    }
+    let png_offset = bin.len();
+    bin.extend_from_slice(&png);
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    let mut json = format!(
+        r#"{{
+            "asset": {{ "version": "2.0" }},
+            "nodes": [
+                {{ "mesh": 0 }},
+                {{ "camera": 0, "translation": [0, 0, 5] }}
+            ],
+            "cameras": [{{ "type": "perspective", "perspective": {{ "yfov": 1.0, "znear": 0.5, "aspectRatio": 2.0 }} }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 2, "TEXCOORD_0": 3 }}, "indices": 1, "material": 0 }}] }}],
+            "materials": [{{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }} }}],
+            "textures": [{{ "source": 0 }}],
+            "images": [{{ "bufferView": 4, "mimeType": "image/png" }}],
+            "buffers": [{{ "byteLength": {} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }},
+                {{ "buffer": 0, "byteOffset": 44, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 80, "byteLength": 24 }},
+                {{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }},
+                {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC2" }}
+            ]
+        }}"#,
+        bin.len(),
+        png_offset,
+        png.len()
+    );
+    while json.len() % 4 != 0 {
+        json.push(' ');
+    }
+
+    let mut glb = vec![];
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2_u32.to_le_bytes());
+    glb.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(json.as_bytes());
+    glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&bin);
+
+    let dir = TempDir::new("load_glb");
+    fs::write(dir.join("triangle.glb"), glb).unwrap();
+
+    let scene = Scene::load_from_gltf(dir.join("triangle.glb").to_str().unwrap()).unwrap();
+    let mesh = &scene.models[0].mesh;
+    assert_eq!(
+        mesh.material
+            .diffuse_texture
+            .as_ref()
+            .map(|texture| texture.to_image()),
+        Some(texture)
+    );
+    assert_eq!(
+        mesh.vertex_texture_coords[1],
+        Vector3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0
+        }
+    );
+    assert_eq!(mesh.vertex_normals.len(), 3);
+
+    // the camera node places the camera and its aspect ratio picks the canvas width
+    assert_eq!(scene.camera.canvas_width, 960);
+    assert_eq!(scene.camera.near_plane, 0.5);
+    assert_eq!(scene.camera.far_plane, f32::INFINITY);
+    assert_eq!(
+        scene.camera.view_mat * Vector3::ORIGIN,
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -5.0
+        }
+    );
+}
+
+#[test]
+fn test_load_gltf_errors() {
+    let dir = TempDir::new("load_gltf_errors");
+    let load_error = |name: &str, content: &str| -> String {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        Scene::load_from_gltf(path.to_str().unwrap())
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        load_error("old.gltf", r#"{ "asset": { "version": "1.0" } }"#),
+        "Failed Loading glTF With Error glTF version 1.0 is not supported"
+    );
+    assert!(load_error("broken.gltf", r#"{ "asset": "#).contains("invalid JSON"));
+    assert_eq!(
+        load_error(
+            "short.gltf",
+            r#"{ "asset": { "version": "2.0" }, "buffers": [{ "uri": "data:application/octet-stream;base64,AAAA", "byteLength": 44 }] }"#
+        ),
+        "Failed Loading glTF With Error buffer 0 holds 3 bytes but claims 44"
+    );
+
+    // line primitives are not triangles
+    fs::write(dir.join("triangle.bin"), triangle_buffer()).unwrap();
+    assert_eq!(
+        load_error(
+            "lines.gltf",
+            &format!(
+                r#"{{
+                    "asset": {{ "version": "2.0" }},
+                    "nodes": [{{ "mesh": 0 }}],
+                    "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "mode": 1 }}] }}],
+                    "buffers": [{{ "uri": "triangle.bin", "byteLength": 44 }}],
+                    {}
+                }}"#,
+                TRIANGLE_ACCESSORS
+            )
+        ),
+        "Failed Loading glTF With Error only triangle primitives are supported"
+    );
+}
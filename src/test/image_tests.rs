@@ -1,5 +1,11 @@
-use crate::image::Image;
-use crate::math::Color;
+use super::TempDir;
+use crate::image::{
+    load_hdr_raw, read_hdr_raw, save_hdr_raw, Checker, Color16, Filter, Image, Image16, Mipmap,
+    MipmapCache, PalettedImage, PpmError, Texture, UvOrigin, WrapMode,
+};
+use crate::math::{Color, Vector3};
+use crate::png::*;
+use std::sync::Arc;
 
 #[test]
 fn test_nearest_neighbor_sample() {
@@ -46,7 +52,7 @@ fn test_nearest_neighbor_sample() {
     };
 
     assert_eq!(
-        texture.sample_nearest_neighbor(0.0, 0.0),
+        texture.sample_nearest_neighbor(0.0, 0.0, WrapMode::Clamp),
         Color {
             r: 12,
             g: 12,
@@ -54,7 +60,7 @@ fn test_nearest_neighbor_sample() {
         }
     );
     assert_eq!(
-        texture.sample_nearest_neighbor(0.01, 0.01),
+        texture.sample_nearest_neighbor(0.01, 0.01, WrapMode::Clamp),
         Color {
             r: 12,
             g: 12,
@@ -62,7 +68,7 @@ fn test_nearest_neighbor_sample() {
         }
     );
     assert_eq!(
-        texture.sample_nearest_neighbor(0.5, 0.5),
+        texture.sample_nearest_neighbor(0.5, 0.5, WrapMode::Clamp),
         Color {
             r: 10,
             g: 10,
@@ -70,11 +76,11 @@ fn test_nearest_neighbor_sample() {
         }
     );
     assert_eq!(
-        texture.sample_nearest_neighbor(0.8, 0.8),
+        texture.sample_nearest_neighbor(0.8, 0.8, WrapMode::Clamp),
         Color { r: 6, g: 6, b: 6 }
     );
     assert_eq!(
-        texture.sample_nearest_neighbor(1.0, 0.0),
+        texture.sample_nearest_neighbor(1.0, 0.0, WrapMode::Clamp),
         Color {
             r: 15,
             g: 15,
@@ -82,7 +88,7 @@ fn test_nearest_neighbor_sample() {
         }
     );
     assert_eq!(
-        texture.sample_nearest_neighbor(0.0, 1.0),
+        texture.sample_nearest_neighbor(0.0, 1.0, WrapMode::Clamp),
         Color { r: 0, g: 0, b: 0 }
     );
 }
@@ -97,33 +103,995 @@ fn test_bilinear_sample() {
 
     // no interpolation
     assert_eq!(
-        texture.sample_bilinear(0.0, 0.0),
+        texture.sample_bilinear(0.0, 0.0, WrapMode::Clamp),
         Color { r: 0, g: 255, b: 0 }
     );
     assert_eq!(
-        texture.sample_bilinear(0.0, 1.0),
+        texture.sample_bilinear(0.0, 1.0, WrapMode::Clamp),
         Color { r: 0, g: 0, b: 0 }
     );
     assert_eq!(
-        texture.sample_bilinear(1.0, 1.0),
+        texture.sample_bilinear(1.0, 1.0, WrapMode::Clamp),
         Color { r: 255, g: 0, b: 0 }
     );
     assert_eq!(
-        texture.sample_bilinear(1.0, 0.0),
+        texture.sample_bilinear(1.0, 0.0, WrapMode::Clamp),
         Color { r: 0, g: 0, b: 255 }
     );
 
     // interpolation
     assert_eq!(
-        texture.sample_bilinear(0.0, 0.3),
-        Color { r: 0, g: 178, b: 0 }
+        texture.sample_bilinear(0.0, 0.3, WrapMode::Clamp),
+        Color { r: 0, g: 229, b: 0 }
     );
     assert_eq!(
-        texture.sample_bilinear(0.3, 0.3),
+        texture.sample_bilinear(0.3, 0.3, WrapMode::Clamp),
         Color {
-            r: 22,
-            g: 124,
-            b: 53
+            r: 2,
+            g: 206,
+            b: 22
         }
     );
 }
+
+#[test]
+fn test_bilinear_sample_single_column() {
+    let mut texture = Image::new(1, 4);
+    for (i, pixel) in texture.data.iter_mut().enumerate() {
+        let value = (i * 60) as u8;
+        *pixel = Color {
+            r: value,
+            g: value,
+            b: value,
+        };
+    }
+
+    // u does not matter when there is only one column
+    for u in [0.0, 0.5, 1.0] {
+        // texel centers are exact, beyond them the edges are clamped
+        assert_eq!(texture.sample_bilinear(u, 0.875, WrapMode::Clamp).r, 0);
+        assert_eq!(texture.sample_bilinear(u, 0.625, WrapMode::Clamp).r, 60);
+        assert_eq!(texture.sample_bilinear(u, 0.125, WrapMode::Clamp).r, 180);
+        assert_eq!(texture.sample_bilinear(u, 1.0, WrapMode::Clamp).r, 0);
+        assert_eq!(texture.sample_bilinear(u, 0.0, WrapMode::Clamp).r, 180);
+
+        // halfway between two texel centers
+        assert_eq!(texture.sample_bilinear(u, 0.5, WrapMode::Clamp).r, 90);
+    }
+}
+
+#[test]
+fn test_nearest_neighbor_sample_wrap() {
+    let mut texture = Image::new(4, 4);
+    for (idx, texel) in texture.data.iter_mut().enumerate() {
+        let value = idx as u8;
+        *texel = Color {
+            r: value,
+            g: value,
+            b: value,
+        };
+    }
+
+    // repeat tiles the texture, so u = 1.5 lands on the same texel as u = 0.5
+    assert_eq!(
+        texture.sample_nearest_neighbor(1.5, 0.5, WrapMode::Repeat),
+        texture.sample_nearest_neighbor(0.5, 0.5, WrapMode::Repeat)
+    );
+    assert_eq!(
+        texture.sample_nearest_neighbor(-0.5, 0.5, WrapMode::Repeat),
+        texture.sample_nearest_neighbor(0.5, 0.5, WrapMode::Repeat)
+    );
+    assert_eq!(
+        texture.sample_nearest_neighbor(1.5, 0.5, WrapMode::Repeat),
+        Color {
+            r: 10,
+            g: 10,
+            b: 10
+        }
+    );
+
+    // mirror flips every other copy, so u = 1.2 lands on the same texel as u = 0.8
+    assert_eq!(
+        texture.sample_nearest_neighbor(1.2, 0.8, WrapMode::Mirror),
+        texture.sample_nearest_neighbor(0.8, 0.8, WrapMode::Mirror)
+    );
+
+    // clamp keeps the edge texel
+    assert_eq!(
+        texture.sample_nearest_neighbor(1.5, 0.5, WrapMode::Clamp),
+        texture.sample_nearest_neighbor(1.0, 0.5, WrapMode::Clamp)
+    );
+}
+
+#[test]
+fn test_bilinear_sample_wrap() {
+    let mut texture = Image::new(2, 2);
+    texture.data[0] = Color { r: 0, g: 0, b: 0 };
+    texture.data[1] = Color { r: 255, g: 0, b: 0 };
+    texture.data[2] = Color { r: 0, g: 255, b: 0 };
+    texture.data[3] = Color { r: 0, g: 0, b: 255 };
+
+    // at the left edge the neighbor past the seam is the right column when repeating
+    assert_eq!(
+        texture.sample_bilinear(0.0, 0.75, WrapMode::Clamp),
+        Color { r: 0, g: 0, b: 0 }
+    );
+    assert_eq!(
+        texture.sample_bilinear(0.0, 0.75, WrapMode::Repeat),
+        Color { r: 127, g: 0, b: 0 }
+    );
+    assert_eq!(
+        texture.sample_bilinear(0.0, 0.75, WrapMode::Mirror),
+        Color { r: 0, g: 0, b: 0 }
+    );
+    assert_eq!(
+        texture.sample(1.25, 0.75, Filter::Bilinear, WrapMode::Repeat),
+        texture.sample(0.25, 0.75, Filter::Bilinear, WrapMode::Repeat)
+    );
+}
+
+#[test]
+fn test_mipmap_levels() {
+    let mut texture = Image::new(8, 4);
+    for (i, pixel) in texture.data.iter_mut().enumerate() {
+        let value = if i % 2 == 0 { 0 } else { 200 };
+        *pixel = Color {
+            r: value,
+            g: value,
+            b: value,
+        };
+    }
+
+    let mipmap = Mipmap::new(&texture);
+    let level_sizes: Vec<(usize, usize)> = mipmap
+        .levels
+        .iter()
+        .map(|level| (level.width, level.height))
+        .collect();
+    assert_eq!(level_sizes, vec![(8, 4), (4, 2), (2, 1), (1, 1)]);
+    assert_eq!(mipmap.levels[0], texture);
+
+    // every 2x2 block averages to the same value
+    assert!(mipmap.levels[1].data.iter().all(|&pixel| pixel
+        == Color {
+            r: 100,
+            g: 100,
+            b: 100
+        }));
+}
+
+#[test]
+fn test_mipmap_cache() {
+    let black = Color::default();
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let texture: Arc<dyn Texture> = Arc::new(Image::checkerboard(8, 8, 2, black, white));
+    let cache = MipmapCache::default();
+
+    // the same texture gets the same mipmap back, also through a clone of the cache
+    let mipmap = Mipmap::cached(&texture, &cache);
+    assert_eq!(*mipmap, Mipmap::new(texture.as_ref()));
+    assert!(Arc::ptr_eq(&mipmap, &Mipmap::cached(&texture, &cache)));
+    assert!(Arc::ptr_eq(
+        &mipmap,
+        &Mipmap::cached(&texture, &cache.clone())
+    ));
+
+    // a different texture is built fresh, even one with the same texels
+    let other: Arc<dyn Texture> = Arc::new(Image::checkerboard(8, 8, 2, black, white));
+    assert!(!Arc::ptr_eq(&mipmap, &Mipmap::cached(&other, &cache)));
+}
+
+#[test]
+fn test_paletted_image() {
+    let black = Color::default();
+    let red = Color { r: 255, g: 0, b: 0 };
+    let blue = Color { r: 0, g: 0, b: 255 };
+    let mut image = Image::checkerboard(6, 5, 2, black, red);
+    image.data[7] = blue;
+    let paletted = PalettedImage::from_image(&image).unwrap();
+    assert_eq!(paletted.palette.len(), 3);
+    assert_eq!(paletted.indices.len(), 30);
+    assert_eq!(paletted.to_image(), image);
+
+    for (u, v) in [
+        (0.0, 0.0),
+        (0.1, 0.9),
+        (0.33, 0.5),
+        (0.71, 0.24),
+        (1.0, 1.0),
+    ] {
+        for filter in [Filter::Nearest, Filter::Bilinear] {
+            assert_eq!(
+                paletted.sample(u, v, filter, WrapMode::Clamp),
+                image.sample(u, v, filter, WrapMode::Clamp)
+            );
+        }
+    }
+    assert_eq!(
+        Mipmap::new(&paletted).sample_trilinear(0.4, 0.6, 1.3, WrapMode::Clamp),
+        Mipmap::new(&image).sample_trilinear(0.4, 0.6, 1.3, WrapMode::Clamp)
+    );
+
+    // a u8 can't index more than 256 colors
+    let mut gradient = Image::new(257, 1);
+    for (x, _, color) in gradient.pixels_mut() {
+        *color = Color {
+            r: (x % 256) as u8,
+            g: (x / 256) as u8,
+            b: 0,
+        };
+    }
+    assert!(PalettedImage::from_image(&gradient).is_err());
+    assert!(PalettedImage::from_image(&gradient.crop(0, 0, 256, 1).unwrap()).is_ok());
+}
+
+#[test]
+fn test_checker_texture() {
+    let black = Color::default();
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let checker = Checker {
+        tiles: 4,
+        color_a: white,
+        color_b: black,
+    };
+    let texture: &dyn Texture = &checker;
+    assert_eq!(
+        texture.to_image(),
+        Image::checkerboard(4, 4, 1, white, black)
+    );
+
+    // v counts up from the bottom, so the top left tile is at (0, 1)
+    for filter in [Filter::Nearest, Filter::Bilinear] {
+        assert_eq!(texture.sample(0.01, 0.99, filter, WrapMode::Clamp), white);
+        assert_eq!(texture.sample(0.26, 0.99, filter, WrapMode::Clamp), black);
+        assert_eq!(texture.sample(0.26, 0.74, filter, WrapMode::Clamp), white);
+        assert_eq!(texture.sample(0.49, 0.01, filter, WrapMode::Clamp), white);
+        assert_eq!(texture.sample(0.51, 0.01, filter, WrapMode::Clamp), black);
+        assert_eq!(texture.sample(1.0, 0.0, filter, WrapMode::Clamp), white);
+        assert_eq!(texture.sample(-3.0, 7.0, filter, WrapMode::Clamp), white);
+    }
+}
+
+#[test]
+fn test_trilinear_sample() {
+    let mut texture = Image::new(4, 4);
+    texture.data[0] = Color {
+        r: 200,
+        g: 200,
+        b: 200,
+    };
+    let mipmap = Mipmap::new(&texture);
+    assert_eq!(mipmap.levels.len(), 3);
+
+    // lod 0 matches the original texture exactly
+    assert_eq!(
+        mipmap.sample_trilinear(0.0, 1.0, 0.0, WrapMode::Clamp),
+        texture.sample_bilinear(0.0, 1.0, WrapMode::Clamp)
+    );
+    // the top left texel is averaged with three black texels on level 1
+    assert_eq!(
+        mipmap.sample_trilinear(0.0, 1.0, 1.0, WrapMode::Clamp),
+        Color {
+            r: 50,
+            g: 50,
+            b: 50
+        }
+    );
+    // halfway between the two levels blends them, lods past the last level clamp to it
+    assert_eq!(
+        mipmap.sample_trilinear(0.0, 1.0, 0.5, WrapMode::Clamp),
+        Color {
+            r: 125,
+            g: 125,
+            b: 125
+        }
+    );
+    assert_eq!(
+        mipmap.sample_trilinear(0.0, 1.0, 8.0, WrapMode::Clamp),
+        mipmap.sample_trilinear(0.0, 1.0, 2.0, WrapMode::Clamp)
+    );
+}
+
+#[test]
+fn test_crop() {
+    let mut image = Image::new(4, 3);
+    for (i, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = Color {
+            r: i as u8,
+            g: i as u8,
+            b: i as u8,
+        };
+    }
+
+    let cropped = image.crop(1, 1, 2, 2);
+    assert!(cropped.is_ok());
+    let cropped = cropped.unwrap();
+    assert_eq!(cropped.width, 2);
+    assert_eq!(cropped.height, 2);
+    let values: Vec<u8> = cropped.data.iter().map(|pixel| pixel.r).collect();
+    assert_eq!(values, vec![5, 6, 9, 10]);
+
+    // the full image is a valid crop
+    assert_eq!(image.crop(0, 0, 4, 3).unwrap(), image);
+}
+
+#[test]
+fn test_crop_out_of_bounds() {
+    let image = Image::new(4, 3);
+    assert!(image.crop(3, 0, 2, 1).is_err());
+    assert!(image.crop(0, 2, 1, 2).is_err());
+    assert!(image.crop(0, 0, 5, 3).is_err());
+    // regions whose far edge doesn't fit in a usize
+    assert!(image.crop(usize::MAX, 0, 2, 1).is_err());
+    assert!(image.crop(0, 1, 1, usize::MAX).is_err());
+    assert!(image.crop(5, 0, 0, 0).is_err());
+}
+
+// a 6x5 RGB image with pixel (x, y) = (x * 40, y * 50, x * y * 13), every filter type used across
+// its rows, compressed with dynamic huffman codes by zlib
+const FILTERED_PNG: [u8; 136] = [
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 6, 0, 0, 0, 5, 8, 2, 0,
+    0, 0, 233, 58, 10, 177, 0, 0, 0, 79, 73, 68, 65, 84, 120, 218, 77, 202, 161, 17, 2, 49, 16, 64,
+    209, 119, 7, 54, 110, 205, 198, 161, 130, 58, 177, 147, 6, 232, 128, 34, 40, 130, 114, 40, 135,
+    178, 200, 57, 102, 190, 122, 243, 225, 206, 147, 55, 31, 190, 108, 106, 81, 251, 111, 95, 164,
+    154, 234, 106, 168, 169, 30, 23, 47, 145, 61, 242, 22, 57, 34, 143, 200, 121, 61, 47, 141, 206,
+    96, 174, 126, 175, 137, 8, 126, 1, 136, 178, 168, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+];
+
+#[test]
+fn test_png_decode() {
+    let maybe_image = decode_png(&FILTERED_PNG);
+    assert!(maybe_image.is_ok());
+    let image = maybe_image.unwrap();
+    assert_eq!(image.width, 6);
+    assert_eq!(image.height, 5);
+    for y in 0..5 {
+        for x in 0..6 {
+            assert_eq!(
+                image.data[(y * 6) + x],
+                Color {
+                    r: ((x * 40) % 256) as u8,
+                    g: ((y * 50) % 256) as u8,
+                    b: ((x * y * 13) % 256) as u8,
+                }
+            );
+        }
+    }
+
+    // a single flipped bit fails the chunk checksum
+    let mut corrupted = FILTERED_PNG;
+    corrupted[60] ^= 1;
+    assert!(decode_png(&corrupted).is_err());
+    assert!(decode_png(&FILTERED_PNG[..40]).is_err());
+
+    // the largest dimensions a header allows at 64 bits per pixel, too many bytes to even count
+    let mut huge = FILTERED_PNG;
+    huge[8..33].copy_from_slice(&[
+        0, 0, 0, 13, 73, 72, 68, 82, 127, 255, 255, 255, 127, 255, 255, 255, 16, 6, 0, 0, 0, 68,
+        89, 215, 37,
+    ]);
+    assert!(decode_png(&huge).is_err());
+}
+
+#[test]
+fn test_png_round_trip() {
+    let mut image = Image::new(5, 3);
+    for (i, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = Color {
+            r: (i * 17) as u8,
+            g: 255 - i as u8,
+            b: (i * i) as u8,
+        };
+    }
+
+    let decoded = decode_png(&encode_png(&image));
+    assert!(decoded.is_ok());
+    assert_eq!(decoded.unwrap(), image);
+}
+
+#[test]
+fn test_load_from_file_dispatch() {
+    let dir = TempDir::new("load_from_file_dispatch");
+    let image = Image::new(2, 2);
+
+    let png_path = dir.join("texture.PNG");
+    image.save_to_png(&png_path).unwrap();
+    assert_eq!(Image::load_from_file(&png_path).unwrap(), image);
+
+    let unsupported = Image::load_from_file(&dir.join("texture.bmp"));
+    assert!(unsupported.is_err());
+    assert!(unsupported.unwrap_err().to_string().contains("texture.bmp"));
+}
+
+#[test]
+fn test_write_ppm() {
+    let mut image = Image::new(2, 1);
+    image.data[1] = Color {
+        r: 255,
+        g: 128,
+        b: 0,
+    };
+
+    let mut output: Vec<u8> = Vec::new();
+    assert!(image.write_ppm(&mut output).is_ok());
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "P3\n2 1\n255\n0 0 0 255 128 0\n"
+    );
+}
+
+#[test]
+fn test_ppm_line_wrapping() {
+    let dir = TempDir::new("ppm_line_wrapping");
+
+    // long three digit samples, enough that every line has to wrap
+    let mut image = Image::new(7, 5);
+    for (idx, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = Color {
+            r: 255 - idx as u8,
+            g: 100 + idx as u8,
+            b: (idx * 7) as u8,
+        };
+    }
+    let path = dir.join("wrapped.ppm");
+    assert!(image.save_to_ppm(&path).is_ok());
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("P3\n7 5\n255\n"));
+    assert!(text.lines().all(|line| line.len() <= 70));
+    // lines are filled up rather than holding a pixel each
+    assert!(text.lines().count() < 3 + image.data.len());
+
+    let maybe_image = Image::load_ppm(&path);
+    assert!(maybe_image.is_ok());
+    assert_eq!(maybe_image.unwrap(), image);
+
+    // pixels split across lines load like any other
+    std::fs::write(&path, "P3\n2 1\n255\n1 2\n3 4 5\n6\n").unwrap();
+    assert_eq!(
+        Image16::load_ppm(&path).unwrap().data,
+        vec![Color16 { r: 1, g: 2, b: 3 }, Color16 { r: 4, g: 5, b: 6 }]
+    );
+}
+
+#[test]
+fn test_to_grayscale() {
+    let mut image = Image::new(5, 1);
+    image.data = vec![
+        Color { r: 255, g: 0, b: 0 },
+        Color { r: 0, g: 255, b: 0 },
+        Color { r: 0, g: 0, b: 255 },
+        Color {
+            r: 255,
+            g: 255,
+            b: 255,
+        },
+        Color { r: 0, g: 0, b: 0 },
+    ];
+
+    assert!((image.data[0].luminance() - 54.213).abs() < 1.0e-3);
+    assert!((image.data[1].luminance() - 182.376).abs() < 1.0e-3);
+    assert!((image.data[2].luminance() - 18.411).abs() < 1.0e-3);
+
+    // 54.2, 182.4, 18.4 and 255.0 round to the nearest integer
+    let lumas: Vec<u8> = image.to_grayscale().data.iter().map(|c| c.r).collect();
+    assert_eq!(lumas, vec![54, 182, 18, 255, 0]);
+    assert!(image
+        .to_grayscale()
+        .data
+        .iter()
+        .all(|c| c.r == c.g && c.g == c.b));
+}
+
+#[test]
+fn test_blur() {
+    let mut image = Image::new(9, 9);
+    image.data[(4 * 9) + 4] = Color {
+        r: 225,
+        g: 225,
+        b: 225,
+    };
+
+    // radius 1 spreads the pixel evenly over a 3x3 square
+    let blurred = image.blur(1);
+    for y in 0..9 {
+        for x in 0..9 {
+            let expected = if (3..=5).contains(&x) && (3..=5).contains(&y) {
+                25
+            } else {
+                0
+            };
+            assert_eq!(blurred.data[(y * 9) + x].r, expected);
+        }
+    }
+    let energy: u32 = blurred.data.iter().map(|c| c.g as u32).sum();
+    assert_eq!(energy, 225);
+
+    // larger radii stay symmetric about the source pixel
+    let blurred = image.blur(2);
+    for offset in 1..=2 {
+        let center = (4 * 9) + 4;
+        let left = blurred.data[center - offset];
+        assert_eq!(left, blurred.data[center + offset]);
+        assert_eq!(left, blurred.data[center - offset * 9]);
+        assert_eq!(left, blurred.data[center + offset * 9]);
+    }
+
+    // clamped edges keep a flat image flat
+    let mut flat = Image::new(3, 2);
+    flat.data = vec![Color { r: 7, g: 8, b: 9 }; 6];
+    assert_eq!(flat.blur(3), flat);
+}
+
+#[test]
+fn test_apply_fxaa() {
+    // left half black, right half white
+    let mut image = Image::new(8, 8);
+    for y in 0..8 {
+        for x in 4..8 {
+            image.data[(y * 8) + x] = Color {
+                r: 255,
+                g: 255,
+                b: 255,
+            };
+        }
+    }
+    let original = image.clone();
+    image.apply_fxaa();
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let (before, after) = (original.data[(y * 8) + x], image.data[(y * 8) + x]);
+            if x == 3 || x == 4 {
+                // both sides of the edge are pulled toward each other
+                assert!(after.r > 0 && after.r < 255, "{x} {y} {after:?}");
+                assert_eq!(after.r, after.g);
+                assert_eq!(after.g, after.b);
+            } else {
+                assert_eq!(after, before);
+            }
+        }
+    }
+
+    // flat images have no edges to smooth
+    let mut flat = Image::new(5, 4);
+    flat.data = vec![
+        Color {
+            r: 90,
+            g: 40,
+            b: 200
+        };
+        20
+    ];
+    let expected = flat.clone();
+    flat.apply_fxaa();
+    assert_eq!(flat, expected);
+}
+
+#[test]
+fn test_ppm16_round_trip() {
+    let dir = TempDir::new("ppm16_round_trip");
+
+    let mut image = Image16::new(3, 2, 1000);
+    image.data[0] = Color16 {
+        r: 1000,
+        g: 999,
+        b: 1,
+    };
+    image.data[4] = Color16 {
+        r: 500,
+        g: 0,
+        b: 333,
+    };
+    let path = dir.join("wide.ppm");
+    assert!(image.save_to_ppm(&path).is_ok());
+
+    let maybe_image = Image16::load_ppm(&path);
+    assert!(maybe_image.is_ok());
+    assert_eq!(maybe_image.unwrap(), image);
+
+    // the 8 bit loader rescales from the declared max value
+    let maybe_image = Image::load_ppm(&path);
+    assert!(maybe_image.is_ok());
+    let image = maybe_image.unwrap();
+    assert_eq!(
+        image.data[0],
+        Color {
+            r: 255,
+            g: 255,
+            b: 0,
+        }
+    );
+    assert_eq!(
+        image.data[4],
+        Color {
+            r: 128,
+            g: 0,
+            b: 85
+        }
+    );
+
+    // values past the max value are rejected
+    std::fs::write(&path, "P3\n1 1\n1000\n1001 0 0\n").unwrap();
+    assert!(Image16::load_ppm(&path).is_err());
+}
+
+#[test]
+fn test_composite_depth() {
+    let red = Color { r: 255, g: 0, b: 0 };
+    let blue = Color { r: 0, g: 0, b: 255 };
+    let mut front = Image::new(2, 2);
+    front.data = vec![red; 4];
+    let mut back = Image::new(2, 2);
+    back.data = vec![blue; 4];
+
+    // the depths cross over, and the last pixel ties
+    let front_depths = [0.1, 0.9, 0.2, 0.5];
+    let back_depths = [0.8, 0.3, f32::MAX, 0.5];
+
+    let maybe_composite = front.composite_depth(&front_depths, &back, &back_depths);
+    assert!(maybe_composite.is_ok());
+    let (image, depths) = maybe_composite.unwrap();
+    assert_eq!(image.data, vec![red, blue, red, red]);
+    assert_eq!(depths, vec![0.1, 0.3, 0.2, 0.5]);
+
+    // buffers have to line up
+    assert!(front
+        .composite_depth(&front_depths, &Image::new(4, 1), &back_depths)
+        .is_err());
+    assert!(front
+        .composite_depth(&front_depths[..3], &back, &back_depths)
+        .is_err());
+}
+
+#[test]
+fn test_blend_over() {
+    let red = Color { r: 255, g: 0, b: 0 };
+    let blue = Color { r: 0, g: 0, b: 255 };
+    let mut image = Image::solid(3, 2, blue);
+    assert!(image.blend_over(&Image::solid(3, 2, red), 0.5).is_ok());
+    for color in image.data.iter() {
+        assert!((127..=128).contains(&color.r));
+        assert_eq!(color.g, 0);
+        assert!((127..=128).contains(&color.b));
+    }
+
+    // fully opaque replaces and fully transparent keeps, with alpha clamped
+    let mut image = Image::solid(3, 2, blue);
+    assert!(image.blend_over(&Image::solid(3, 2, red), 2.0).is_ok());
+    assert_eq!(image, Image::solid(3, 2, red));
+    assert!(image.blend_over(&Image::solid(3, 2, blue), -1.0).is_ok());
+    assert_eq!(image, Image::solid(3, 2, red));
+
+    // layers must match in size
+    assert!(image.blend_over(&Image::solid(2, 3, blue), 0.5).is_err());
+    assert_eq!(image, Image::solid(3, 2, red));
+}
+
+#[test]
+fn test_mse_and_psnr() {
+    let gray = Image::solid(
+        4,
+        4,
+        Color {
+            r: 100,
+            g: 100,
+            b: 100,
+        },
+    );
+    assert_eq!(gray.mse(&gray).unwrap(), 0.0);
+    assert_eq!(gray.psnr(&gray).unwrap(), f32::INFINITY);
+
+    // one channel of one pixel off by 10 out of 48 channels
+    let mut off = gray.clone();
+    off.data[5].g = 110;
+    assert!((gray.mse(&off).unwrap() - 100.0 / 48.0).abs() < 1.0e-5);
+    let expected = 10.0 * (255.0_f32 * 255.0 * 48.0 / 100.0).log10();
+    assert!((gray.psnr(&off).unwrap() - expected).abs() < 1.0e-3);
+    assert!((off.psnr(&gray).unwrap() - 44.9).abs() < 0.1);
+}
+
+#[test]
+fn test_mse_size_mismatch() {
+    let mismatch = Image::new(2, 3).mse(&Image::new(3, 2));
+    assert!(mismatch.unwrap_err().msg.contains("2x3"));
+    assert!(Image::new(2, 3).psnr(&Image::new(3, 2)).is_err());
+}
+
+#[test]
+fn test_uv_origin_flips_rows() {
+    let mut texture = Image::new(2, 4);
+    for (i, pixel) in texture.data.iter_mut().enumerate() {
+        let value = (i / 2 * 50) as u8;
+        *pixel = Color {
+            r: value,
+            g: value,
+            b: value,
+        };
+    }
+
+    for row in 0..4 {
+        let v = (row as f32 + 0.5) / 4.0;
+        let flipped_v = 1.0 - v;
+        for u in [0.25, 0.75] {
+            let bottom_left = UvOrigin::BottomLeft.to_bottom_left(v);
+            let top_left = UvOrigin::TopLeft.to_bottom_left(v);
+            assert_eq!(
+                texture.sample_bilinear(u, top_left, WrapMode::Clamp),
+                texture.sample_bilinear(u, flipped_v, WrapMode::Clamp)
+            );
+            assert_eq!(
+                texture.sample_nearest_neighbor(u, top_left, WrapMode::Clamp),
+                texture.sample_nearest_neighbor(u, flipped_v, WrapMode::Clamp)
+            );
+            // bottom left origin keeps the first image row at v = 1, top left puts it at v = 0
+            assert_eq!(
+                texture.sample_bilinear(u, bottom_left, WrapMode::Clamp).r,
+                (3 - row) * 50
+            );
+            assert_eq!(
+                texture.sample_bilinear(u, top_left, WrapMode::Clamp).r,
+                row * 50
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rgb_bytes_round_trip() {
+    let mut image = Image::new(3, 2);
+    for (i, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = Color {
+            r: i as u8,
+            g: 100 + i as u8,
+            b: 200 + i as u8,
+        };
+    }
+
+    let bytes = image.to_rgb_bytes();
+    assert_eq!(bytes.len(), 3 * 2 * 3);
+    // row-major, so the second row starts after three pixels
+    assert_eq!(&bytes[..3], &[0, 100, 200]);
+    assert_eq!(&bytes[9..12], &[3, 103, 203]);
+    assert_eq!(Image::from_rgb_bytes(&bytes, 3, 2).unwrap(), image);
+
+    assert!(Image::from_rgb_bytes(&bytes, 2, 2).is_err());
+    assert!(Image::from_rgb_bytes(&bytes[1..], 3, 2).is_err());
+}
+
+#[test]
+fn test_sample_with_filter() {
+    let mut texture = Image::new(2, 1);
+    texture.data[1] = Color {
+        r: 200,
+        g: 100,
+        b: 50,
+    };
+
+    for (u, v) in [(0.0, 0.0), (0.4, 0.5), (0.6, 0.9), (1.0, 1.0)] {
+        assert_eq!(
+            texture.sample(u, v, Filter::Nearest, WrapMode::Clamp),
+            texture.sample_nearest_neighbor(u, v, WrapMode::Clamp)
+        );
+        assert_eq!(
+            texture.sample(u, v, Filter::Bilinear, WrapMode::Clamp),
+            texture.sample_bilinear(u, v, WrapMode::Clamp)
+        );
+    }
+
+    // halfway between the two texels nearest snaps to one while bilinear blends them
+    assert_eq!(
+        texture.sample(0.4, 0.5, Filter::Nearest, WrapMode::Clamp),
+        Color::default()
+    );
+    assert_eq!(
+        texture.sample(0.5, 0.5, Filter::Bilinear, WrapMode::Clamp),
+        Color {
+            r: 100,
+            g: 50,
+            b: 25
+        }
+    );
+    assert_eq!(Filter::default(), Filter::Bilinear);
+}
+
+#[test]
+fn test_write_ppm_binary() {
+    let mut image = Image::new(2, 1);
+    image.data[1] = Color {
+        r: 10,
+        g: 20,
+        b: 255,
+    };
+
+    let mut bytes: Vec<u8> = Vec::new();
+    image.write_ppm_binary(&mut bytes).unwrap();
+    let header = b"P6\n2 1\n255\n";
+    assert_eq!(&bytes[..header.len()], header);
+    assert_eq!(&bytes[header.len()..], &[0, 0, 0, 10, 20, 255]);
+}
+
+#[test]
+fn test_hdr_raw_round_trip() {
+    let pixel = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+    let data = vec![
+        pixel(0.0, 0.25, 0.999_999),
+        pixel(1.0, 1.000_001, 37.5),
+        pixel(1.0e-8, 65504.0, -0.5),
+        pixel(0.1, 0.2, 0.3),
+        pixel(f32::MAX, f32::MIN_POSITIVE, 3.0e38),
+        pixel(2.0, 4.0, 8.0),
+    ];
+    let dir = TempDir::new("hdr_raw");
+    let path = dir.join("frame.hdrraw");
+
+    save_hdr_raw(&path, &data, 3, 2).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 16 + 6 * 12);
+    let (loaded, width, height) = load_hdr_raw(&path).unwrap();
+    assert_eq!((width, height), (3, 2));
+    // bit for bit, not just close
+    for (loaded, original) in loaded.iter().zip(data.iter()) {
+        assert_eq!(loaded.x.to_bits(), original.x.to_bits());
+        assert_eq!(loaded.y.to_bits(), original.y.to_bits());
+        assert_eq!(loaded.z.to_bits(), original.z.to_bits());
+    }
+    assert_eq!(loaded.len(), data.len());
+
+    // the pixel count has to match the size on the way out and on the way back in
+    let saved = std::fs::read(&path).unwrap();
+    assert!(save_hdr_raw(&path, &data, 2, 2).is_err());
+    assert!(read_hdr_raw(&mut &saved[..saved.len() - 1]).is_err());
+    // dimensions whose byte count doesn't even fit in a usize
+    let mut huge = saved[..8].to_vec();
+    huge.extend_from_slice(&[0xff; 8]);
+    assert!(read_hdr_raw(&mut huge.as_slice()).is_err());
+    assert!(read_hdr_raw(
+        &mut b"P6
+1 1
+255
+0000000"
+            .as_slice()
+    )
+    .is_err());
+}
+
+#[test]
+fn test_ppm_error_kinds() {
+    let dir = TempDir::new("ppm_error_kinds");
+    let load = |content: &str| {
+        let path = dir.join("broken.ppm");
+        std::fs::write(&path, content).unwrap();
+        Image16::load_ppm(&path)
+    };
+
+    assert!(load("P3\n1 1\n255\n1 2 3\n").is_ok());
+    assert!(matches!(load("P6\n1 1\n255\n"), Err(PpmError::NotP3)));
+    assert!(matches!(load("P3\n1 1\n"), Err(PpmError::BadHeader(_))));
+    assert!(matches!(
+        load("P3\n1 1\n0\n0 0 0\n"),
+        Err(PpmError::BadHeader(_))
+    ));
+    assert!(matches!(
+        load("P3\n1\n255\n0 0 0\n"),
+        Err(PpmError::BadDimensions(_))
+    ));
+    assert!(matches!(
+        load("P3\n1 -1\n255\n0 0 0\n"),
+        Err(PpmError::BadDimensions(_))
+    ));
+    assert!(matches!(
+        load("P3\n1 1\n255\n0 0\n"),
+        Err(PpmError::ValueCountMismatch(_))
+    ));
+    assert!(matches!(
+        load("P3\n1 1\n255\n0 0 0\n0 0 0\n"),
+        Err(PpmError::ValueCountMismatch(_))
+    ));
+    assert!(matches!(
+        load("P3\n2 1\n255\n0 0 0\n"),
+        Err(PpmError::ValueCountMismatch(_))
+    ));
+    assert!(matches!(
+        load("P3\n1 1\n255\n0 red 0\n"),
+        Err(PpmError::BadValue(_))
+    ));
+    assert!(matches!(
+        Image16::load_ppm(&dir.join("missing.ppm")),
+        Err(PpmError::Io(_))
+    ));
+
+    // the messages still say what went wrong
+    assert_eq!(
+        load("P3\n1 1\n255\n0 0 256\n").unwrap_err().to_string(),
+        "Failed Loading PPM Image With Error PPM value 256 is not between 0 and the max value 255"
+    );
+}
+
+#[test]
+fn test_pixels_with_coordinates() {
+    let mut image = Image::new(5, 3);
+    for (x, y, pixel) in image.pixels_mut() {
+        *pixel = Color {
+            r: x as u8,
+            g: y as u8,
+            b: 0,
+        };
+    }
+    assert_eq!(image.data[2 * 5 + 4], Color { r: 4, g: 2, b: 0 });
+
+    // every coordinate shows up once and matches what was written there
+    let mut visits = [0; 5 * 3];
+    let mut coordinate_sum = (0, 0);
+    for (x, y, pixel) in image.pixels() {
+        assert_eq!((pixel.r as usize, pixel.g as usize), (x, y));
+        visits[y * 5 + x] += 1;
+        coordinate_sum.0 += x;
+        coordinate_sum.1 += y;
+    }
+    assert!(visits.iter().all(|&count| count == 1));
+    assert_eq!(coordinate_sum, (10 * 3, 3 * 5));
+}
+
+#[test]
+fn test_get_and_set() {
+    let mut image = Image::new(3, 2);
+    let red = Color { r: 255, g: 0, b: 0 };
+
+    assert!(image.set(2, 1, red));
+    assert_eq!(image.get(2, 1), Some(red));
+    assert_eq!(image.data[5], red);
+    assert_eq!(image.get(0, 0), Some(Color::default()));
+
+    // x past the width must not wrap onto the next row
+    assert_eq!(image.get(3, 0), None);
+    assert_eq!(image.get(0, 2), None);
+    assert!(!image.set(3, 0, red));
+    assert!(!image.set(0, 2, red));
+    assert!(!image.set(usize::MAX, usize::MAX, red));
+    assert_eq!(image.data.iter().filter(|&&pixel| pixel == red).count(), 1);
+}
+
+#[test]
+fn test_solid_and_checkerboard() {
+    let gray = Color {
+        r: 128,
+        g: 128,
+        b: 128,
+    };
+    let solid = Image::solid(4, 3, gray);
+    assert_eq!((solid.width, solid.height), (4, 3));
+    assert_eq!(solid.data.len(), 12);
+    assert!(solid.data.iter().all(|&pixel| pixel == gray));
+
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let checker = Image::checkerboard(7, 5, 2, white, gray);
+    assert_eq!((checker.width, checker.height), (7, 5));
+    for (x, y, &pixel) in checker.pixels() {
+        let expected = if (x / 2 + y / 2) % 2 == 0 {
+            white
+        } else {
+            gray
+        };
+        assert_eq!(pixel, expected);
+    }
+    // colors flip exactly at the tile boundaries, in both directions
+    assert_eq!(checker.get(1, 0), Some(white));
+    assert_eq!(checker.get(2, 0), Some(gray));
+    assert_eq!(checker.get(1, 1), Some(white));
+    assert_eq!(checker.get(1, 2), Some(gray));
+    assert_eq!(checker.get(2, 2), Some(white));
+    // a partial tile at the edge keeps the pattern going
+    assert_eq!(checker.get(6, 4), Some(gray));
+}
@@ -26,6 +26,127 @@ fn test_matrix_mul() {
     );
 }
 
+#[test]
+fn test_frustum_contains_point() {
+    // an identity view projection sees exactly the NDC cube
+    let ndc_cube = Frustum::from_matrix(Mat4::identity());
+    let point = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+    assert!(ndc_cube.contains_point(point(0.0, 0.0, 0.0)));
+    assert!(ndc_cube.contains_point(point(0.99, -0.99, -0.99)));
+    assert!(ndc_cube.contains_point(point(-0.99, 0.99, 0.99)));
+    assert!(!ndc_cube.contains_point(point(0.0, 0.0, -1.01)));
+    assert!(!ndc_cube.contains_point(point(0.0, 0.0, 1.01)));
+    assert!(!ndc_cube.contains_point(point(1.01, 0.0, 0.0)));
+    assert!(!ndc_cube.contains_point(point(0.0, -1.01, 0.0)));
+
+    // a camera at (0, 0, 5) looking down -z at the origin, with near 0.1 and far 100
+    let view = Mat4::translation(0.0, 0.0, -5.0);
+    let frustum =
+        Frustum::from_matrix(Mat4::perspective(1.0, 90_f32.to_radians(), 0.1, 100.0) * view);
+    assert!(frustum.contains_point(Vector3::ORIGIN));
+    assert!(frustum.contains_point(point(0.0, 0.0, 4.89)));
+    assert!(frustum.contains_point(point(0.0, 0.0, -94.0)));
+    assert!(!frustum.contains_point(point(0.0, 0.0, 4.91)));
+    assert!(!frustum.contains_point(point(0.0, 0.0, -96.0)));
+    // far behind the camera, and off to the side
+    assert!(!frustum.contains_point(point(0.0, 0.0, 50.0)));
+    assert!(!frustum.contains_point(point(10.0, 0.0, 0.0)));
+}
+
+#[test]
+fn test_frustum_intersects_aabb() {
+    let view = Mat4::translation(0.0, 0.0, -5.0);
+    let frustum =
+        Frustum::from_matrix(Mat4::perspective(1.0, 90_f32.to_radians(), 0.1, 100.0) * view);
+    let aabb = |min_x: f32, max_x: f32| Aabb {
+        min: Vector3 {
+            x: min_x,
+            y: -1.0,
+            z: -1.0,
+        },
+        max: Vector3 {
+            x: max_x,
+            y: 1.0,
+            z: 1.0,
+        },
+    };
+
+    assert!(frustum.intersects_aabb(&aabb(-1.0, 1.0)));
+    // only its corner pokes into the frustum, past the right edge at x = 5 - z
+    assert!(frustum.intersects_aabb(&aabb(5.5, 8.0)));
+    assert!(!frustum.intersects_aabb(&aabb(6.5, 8.0)));
+    // spheres go by the same planes
+    assert!(frustum.intersects_sphere(Vector3::ORIGIN, 0.5));
+    assert!(!frustum.intersects_sphere(
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 10.0
+        },
+        1.0
+    ));
+}
+
+#[test]
+fn test_orthonormalize_rotation() {
+    let mut transform = Mat4::translation(1.0, 2.0, 3.0) * Mat4::euler_angles(0.3, -0.7, 1.1);
+    let expected = transform;
+    // drift every entry of the rotation a little
+    for (idx, value) in transform.data.iter_mut().enumerate() {
+        if idx % 4 != 3 && idx < 12 {
+            *value += 0.01 * ((idx * 7 % 5) as f32 - 2.0);
+        }
+    }
+    let column = |m: &Mat4, col: usize| Vector3 {
+        x: *m.at(col, 0),
+        y: *m.at(col, 1),
+        z: *m.at(col, 2),
+    };
+    assert!((column(&transform, 0).magnitude() - 1.0).abs() > 1.0e-3);
+
+    transform.orthonormalize_rotation();
+    let (x, y, z) = (
+        column(&transform, 0),
+        column(&transform, 1),
+        column(&transform, 2),
+    );
+    for axis in [x, y, z] {
+        assert!((axis.magnitude() - 1.0).abs() < 1.0e-5);
+    }
+    assert!(Vector3::dot(x, y).abs() < 1.0e-5);
+    assert!(Vector3::dot(x, z).abs() < 1.0e-5);
+    assert!(Vector3::dot(y, z).abs() < 1.0e-5);
+    // still right handed, close to the rotation it drifted from, and in the same place
+    assert!(Vector3::cross(x, y).approx_eq(z, 1.0e-5));
+    for col in 0..3 {
+        assert!(column(&transform, col).approx_eq(column(&expected, col), 0.05));
+    }
+    assert_eq!(transform.translation_part(), expected.translation_part());
+}
+
+#[test]
+fn test_mat4_display() {
+    assert_eq!(
+        Mat4::identity().to_string(),
+        "[ 1.000 0.000 0.000 0.000 ]\n\
+         [ 0.000 1.000 0.000 0.000 ]\n\
+         [ 0.000 0.000 1.000 0.000 ]\n\
+         [ 0.000 0.000 0.000 1.000 ]"
+    );
+
+    // rows read left to right, so a translation shows up in the last column
+    let translation = format!("{:.1}", Mat4::translation(2.0, -13.5, 0.25));
+    assert_eq!(
+        translation.lines().collect::<Vec<&str>>(),
+        [
+            "[   1.0   0.0   0.0   2.0 ]",
+            "[   0.0   1.0   0.0 -13.5 ]",
+            "[   0.0   0.0   1.0   0.2 ]",
+            "[   0.0   0.0   0.0   1.0 ]",
+        ]
+    );
+}
+
 #[test]
 fn test_euler_angles() {
     let t = Mat4::euler_angles(0.0, 0.0, 90_f32.to_radians());
@@ -36,23 +157,57 @@ fn test_euler_angles() {
     };
     let tp = t * p;
 
-    assert!((tp.x - 0.0).abs() < EPSILON);
-    assert!((tp.y - 0.0).abs() < EPSILON);
-    assert!((tp.z + 1.0).abs() < EPSILON);
+    assert!(tp.approx_eq(
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -1.0
+        },
+        EPSILON
+    ));
 
     let t = Mat4::euler_angles(0.0, 90_f32.to_radians(), 0.0);
     let tp = t * p;
 
-    assert!((tp.x - 1.0).abs() < EPSILON);
-    assert!((tp.y - 0.0).abs() < EPSILON);
-    assert!((tp.z - 0.0).abs() < EPSILON);
+    assert!(tp.approx_eq(
+        Vector3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0
+        },
+        EPSILON
+    ));
 
     let t = Mat4::euler_angles(90_f32.to_radians(), 0.0, 0.0);
     let tp = t * p;
 
-    assert!((tp.x - 0.0).abs() < EPSILON);
-    assert!((tp.y - 1.0).abs() < EPSILON);
-    assert!((tp.z - 0.0).abs() < EPSILON);
+    assert!(tp.approx_eq(
+        Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0
+        },
+        EPSILON
+    ));
+}
+
+#[test]
+fn test_vector3_approx_eq() {
+    let a = Vector3 {
+        x: 0.25,
+        y: -0.5,
+        z: 0.0,
+    };
+    let b = a + Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0e-7,
+    };
+    assert_ne!(a, b);
+    assert!(a.approx_eq(b, 1.0e-5));
+    assert!(b.approx_eq(a, 1.0e-5));
+    assert!(!a.approx_eq(b, 1.0e-9));
+    assert!(a.approx_eq(a, 0.0));
 }
 
 #[test]
@@ -79,7 +234,341 @@ fn test_point_transformations() {
     };
     let tp = t * p;
 
-    assert!(tp.x - 1.0 < EPSILON);
-    assert!(tp.y - 0.0 < EPSILON);
-    assert!(tp.z - 2.0 < EPSILON);
+    assert!(tp.approx_eq(
+        Vector3 {
+            x: 1.0,
+            y: 0.0,
+            z: -2.0
+        },
+        EPSILON
+    ));
+}
+
+#[test]
+fn test_point_and_direction_transformations() {
+    let t = Mat4::translation(5.0, -2.0, 3.0) * Mat4::scale(2.0, 2.0, 2.0);
+    let v = Vector3 {
+        x: 1.0,
+        y: 0.0,
+        z: -1.0,
+    };
+
+    // points pick up the translation
+    let point = t * Vector4::point(v);
+    assert_eq!(
+        point,
+        Vector4 {
+            x: 7.0,
+            y: -2.0,
+            z: 1.0,
+            w: 1.0
+        }
+    );
+    assert_eq!(point.perspective_divide(), t * v);
+
+    // directions only see the scale
+    let direction = t * Vector4::direction(v);
+    assert_eq!(
+        direction,
+        Vector4 {
+            x: 2.0,
+            y: 0.0,
+            z: -2.0,
+            w: 0.0
+        }
+    );
+    assert_eq!(direction.xyz(), v * 2.0);
+
+    // no implicit divide, w comes straight out of the projection
+    let projected = Mat4::perspective(1.0, 90_f32.to_radians(), 1.0, 10.0) * Vector4::point(v);
+    assert!((projected.w - 1.0).abs() < EPSILON);
+    let projected =
+        Mat4::perspective(1.0, 90_f32.to_radians(), 1.0, 10.0) * Vector4::point(v * 2.0);
+    assert!((projected.w - 2.0).abs() < EPSILON);
+}
+
+#[test]
+fn test_look_at_degenerate() {
+    let eye = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 5.0,
+    };
+    let up = Vector3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+    assert!(Mat4::look_at(eye, Vector3::ORIGIN, up).is_ok());
+
+    // eye == center leaves no forward direction
+    assert!(Mat4::look_at(eye, eye, up).is_err());
+    // up along or against the forward direction leaves no sideways one, as does no up at all
+    let below = Vector3 {
+        x: 0.0,
+        y: -3.0,
+        z: 0.0,
+    };
+    assert!(Mat4::look_at(Vector3::ORIGIN, below, up).is_err());
+    assert!(Mat4::look_at(Vector3::ORIGIN, below * -1.0, up).is_err());
+    assert!(Mat4::look_at(eye, Vector3::ORIGIN, Vector3::ORIGIN).is_err());
+    let error = Mat4::look_at(eye, Vector3::ORIGIN, eye).unwrap_err();
+    assert!(error.to_string().contains("parallel"));
+}
+
+#[test]
+fn test_color_add_saturates() {
+    let a = Color {
+        r: 200,
+        g: 10,
+        b: 255,
+    };
+    let b = Color {
+        r: 100,
+        g: 20,
+        b: 1,
+    };
+    assert_eq!(
+        a + b,
+        Color {
+            r: 255,
+            g: 30,
+            b: 255
+        }
+    );
+}
+
+#[test]
+fn test_color_scale_and_blend() {
+    let c = Color {
+        r: 200,
+        g: 100,
+        b: 0,
+    };
+    assert_eq!(
+        c * 0.5,
+        Color {
+            r: 100,
+            g: 50,
+            b: 0
+        }
+    );
+    assert_eq!(
+        c * 2.0,
+        Color {
+            r: 255,
+            g: 200,
+            b: 0
+        }
+    );
+    assert_eq!(c * -1.0, Color::default());
+
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    assert_eq!(c.blend(white, 0.0), c);
+    assert_eq!(c.blend(white, 1.0), white);
+    assert_eq!(
+        Color::default().blend(white, 0.5),
+        Color {
+            r: 127,
+            g: 127,
+            b: 127
+        }
+    );
+}
+
+#[test]
+fn test_distance() {
+    let a = Vector3::ORIGIN;
+    let b = Vector3 {
+        x: 3.0,
+        y: 4.0,
+        z: 0.0,
+    };
+    assert_eq!(Vector3::distance(a, b), 5.0);
+    assert_eq!(Vector3::distance(b, a), 5.0);
+    assert_eq!(Vector3::distance_squared(a, b), 25.0);
+    assert_eq!(Vector3::distance_squared(b, b), 0.0);
+}
+
+#[test]
+fn test_component_min_max_clamp() {
+    let a = Vector3 {
+        x: -1.0,
+        y: 2.0,
+        z: -3.0,
+    };
+    let b = Vector3 {
+        x: 4.0,
+        y: -5.0,
+        z: -0.5,
+    };
+    assert_eq!(
+        Vector3::min(a, b),
+        Vector3 {
+            x: -1.0,
+            y: -5.0,
+            z: -3.0
+        }
+    );
+    assert_eq!(
+        Vector3::max(a, b),
+        Vector3 {
+            x: 4.0,
+            y: 2.0,
+            z: -0.5
+        }
+    );
+    assert_eq!(Vector3::min(a, b), Vector3::min(b, a));
+    assert_eq!(Vector3::max(a, a), a);
+
+    let lo = Vector3 {
+        x: -2.0,
+        y: 0.0,
+        z: -1.0,
+    };
+    let hi = Vector3 {
+        x: 0.0,
+        y: 1.0,
+        z: 1.0,
+    };
+    // inside, above, and below the range
+    assert_eq!(
+        a.clamp(lo, hi),
+        Vector3 {
+            x: -1.0,
+            y: 1.0,
+            z: -1.0
+        }
+    );
+    // above, below, and inside the range
+    assert_eq!(
+        b.clamp(lo, hi),
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -0.5
+        }
+    );
+}
+
+#[test]
+#[should_panic]
+fn test_perspective_near_equals_far() {
+    Mat4::perspective(1.0, 1.0, 1.0, 1.0);
+}
+
+#[test]
+#[should_panic]
+fn test_perspective_zero_near() {
+    Mat4::perspective(1.0, 1.0, 0.0, 10.0);
+}
+
+#[test]
+fn test_perspective_infinite() {
+    let infinite = Mat4::perspective_infinite(2.0, 90_f32.to_radians(), 0.5);
+    assert!((infinite.at(0, 0) - 0.5).abs() < EPSILON);
+    assert!((infinite.at(1, 1) - 1.0).abs() < EPSILON);
+    assert_eq!(*infinite.at(2, 2), -1.0);
+    assert_eq!(*infinite.at(2, 3), -1.0);
+    assert_eq!(*infinite.at(3, 2), -1.0);
+    assert_eq!(*infinite.at(3, 3), 0.0);
+
+    // a very distant far plane converges to the infinite matrix
+    let distant = Mat4::perspective(2.0, 90_f32.to_radians(), 0.5, 1.0e7);
+    for (a, b) in distant.data.iter().zip(infinite.data.iter()) {
+        assert!((a - b).abs() < EPSILON);
+    }
+
+    // the near plane still maps to -1 and points infinitely far away approach 1
+    let near_point = infinite
+        * Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -0.5,
+        };
+    assert!((near_point.z + 1.0).abs() < EPSILON);
+    let far_point = infinite
+        * Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: -1.0e6,
+        };
+    assert!(far_point.z < 1.0 && far_point.z > 1.0 - EPSILON);
+}
+
+#[test]
+fn test_to_color_dithered() {
+    // 30% of the way from 127 to 128
+    let gray = (127.3 / 255.0) as f32;
+    let gray = Vector3 {
+        x: gray,
+        y: gray,
+        z: gray,
+    };
+    assert_eq!(gray.to_color().r, 127);
+
+    let mut values: Vec<u8> = Vec::new();
+    for y in 0..4 {
+        for x in 0..4 {
+            let color = gray.to_color_dithered(x, y);
+            assert_eq!(color.r, color.g);
+            values.push(color.r);
+        }
+    }
+    assert!(values.iter().all(|&value| value == 127 || value == 128));
+    let num_high = values.iter().filter(|&&value| value == 128).count();
+    assert_eq!(num_high, 5);
+
+    // the pattern repeats every four pixels, including at negative positions
+    assert_eq!(gray.to_color_dithered(1, 2), gray.to_color_dithered(5, -2));
+
+    // whole levels and the extremes are left alone
+    let white = Vector3 {
+        x: 1.0,
+        y: 1.0,
+        z: 1.0,
+    };
+    assert_eq!(white.to_color_dithered(3, 3).r, 255);
+    assert_eq!(Vector3::ORIGIN.to_color_dithered(3, 3).r, 0);
+}
+
+#[test]
+fn test_halton() {
+    let sequence = |base: u32| {
+        (0..5)
+            .map(|index| halton(index, base))
+            .collect::<Vec<f32>>()
+    };
+    assert_eq!(sequence(2), vec![0.0, 0.5, 0.25, 0.75, 0.125]);
+    let thirds = sequence(3);
+    for (value, expected) in thirds
+        .iter()
+        .zip([0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0])
+    {
+        assert!((value - expected).abs() < 1.0e-6);
+    }
+}
+
+#[test]
+fn test_color_hex() {
+    let orange = Color {
+        r: 255,
+        g: 136,
+        b: 0,
+    };
+    assert_eq!(Color::from_hex("#ff8800"), Some(orange));
+    assert_eq!(orange.to_hex(), "#ff8800");
+    assert_eq!(Color::from_hex(&orange.to_hex()), Some(orange));
+    assert_eq!(Color::from_hex("#FF8800"), Some(orange));
+    assert_eq!(Color::default().to_hex(), "#000000");
+
+    for invalid in [
+        "ff8800", "#ff880", "#ff88000", "#ff88g0", "#+f8800", "#", "",
+    ] {
+        assert_eq!(Color::from_hex(invalid), None, "{invalid}");
+    }
 }
@@ -0,0 +1,917 @@
+use super::TempDir;
+use crate::image::{Image, PalettedImage};
+use crate::math::*;
+use crate::mesh::*;
+use crate::rasterizer::{draw_mesh, RenderOptions};
+use crate::scene::{Camera, Light};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+#[test]
+fn test_load_png_texture_from_material_lib() {
+    let dir = TempDir::new("load_png_texture_from_material_lib");
+
+    let mut texture = Image::new(2, 1);
+    texture.data[1] = Color {
+        r: 10,
+        g: 20,
+        b: 30,
+    };
+    let texture_path = dir.join("checker.png");
+    texture.save_to_png(&texture_path).unwrap();
+
+    fs::write(
+        dir.join("textured.mtl"),
+        format!("newmtl textured\nmap_Kd {}\n", texture_path.display()),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("textured.obj"),
+        "mtllib textured.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/1 2/2 3/3\n",
+    )
+    .unwrap();
+
+    let maybe_mesh = Mesh::from_obj_file(&dir.join("textured.obj"));
+    assert!(maybe_mesh.is_ok());
+    assert_eq!(
+        maybe_mesh
+            .unwrap()
+            .material
+            .diffuse_texture
+            .as_ref()
+            .map(|texture| texture.to_image()),
+        Some(texture)
+    );
+}
+
+#[test]
+fn test_missing_texture_coords() {
+    let dir = TempDir::new("missing_texture_coords");
+
+    let texture_path = dir.join("texture.png");
+    Image::new(2, 2).save_to_png(&texture_path).unwrap();
+    fs::write(
+        dir.join("textured.mtl"),
+        format!("newmtl textured\nmap_Kd {}\n", texture_path.display()),
+    )
+    .unwrap();
+
+    // one face has no uvs at all, the other references a vt past the end of the file
+    fs::write(
+        dir.join("textured.obj"),
+        "mtllib textured.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nf 1 2 3\nf 1/1 2/1 3/7\n",
+    )
+    .unwrap();
+    let mesh = Mesh::from_obj_file(&dir.join("textured.obj")).unwrap();
+    assert_eq!(mesh.vertex_texture_coords.len(), 2);
+    assert_eq!(mesh.vertex_texture_coords[1], Vector3::default());
+    for face in &mesh.face_indicies {
+        for idx in [face.a_texture, face.b_texture, face.c_texture] {
+            assert!(idx < mesh.vertex_texture_coords.len());
+        }
+    }
+    assert_eq!(mesh.face_indicies[1].a_texture, 0);
+
+    // without any vt entries every corner falls back, and the mesh can be drawn
+    fs::write(
+        dir.join("textured.obj"),
+        "mtllib textured.mtl\nv -1 -1 -2\nv 1 -1 -2\nv 0 1 -2\nf 1/1 3/3 2/2\n",
+    )
+    .unwrap();
+    let mut mesh = Mesh::from_obj_file(&dir.join("textured.obj")).unwrap();
+    assert_eq!(mesh.vertex_texture_coords, vec![Vector3::default()]);
+    assert_eq!(mesh.fill_missing_texture_coords(), 0);
+    let camera = Camera::new(8, 8, std::f32::consts::FRAC_PI_2, 0.1, 10.0);
+    let mut pixel_buffer = vec![Color::default(); 64];
+    let mut depth_buffer = vec![f32::MAX; 64];
+    draw_mesh(
+        &mesh,
+        Mat4::identity(),
+        &[Light::default()],
+        camera,
+        RenderOptions::default(),
+        &mut pixel_buffer,
+        &mut depth_buffer,
+    );
+    assert!(depth_buffer.iter().any(|&depth| depth < f32::MAX));
+}
+
+fn single_triangle(offset: f32) -> Mesh {
+    let vertex = |x: f32, y: f32| Vector3 { x, y, z: 0.0 };
+    Mesh {
+        verticies: vec![
+            vertex(offset, 0.0),
+            vertex(offset + 1.0, 0.0),
+            vertex(offset, 1.0),
+        ],
+        face_indicies: vec![Triangle {
+            a: 0,
+            b: 1,
+            c: 2,
+            a_normal: 0,
+            b_normal: 0,
+            c_normal: 0,
+            a_texture: 0,
+            b_texture: 1,
+            c_texture: 2,
+        }],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        vertex_texture_coords: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+        ..Default::default()
+    }
+}
+
+// a size by size grid of unit squares on the xy plane, each split into two triangles
+fn grid_mesh(size: usize) -> Mesh {
+    let mut mesh = Mesh::default();
+    for y in 0..=size {
+        for x in 0..=size {
+            mesh.verticies.push(Vector3 {
+                x: x as f32,
+                y: y as f32,
+                z: 0.0,
+            });
+        }
+    }
+    let idx = |x: usize, y: usize| y * (size + 1) + x;
+    for y in 0..size {
+        for x in 0..size {
+            for (a, b, c) in [
+                (idx(x, y), idx(x, y + 1), idx(x + 1, y)),
+                (idx(x + 1, y), idx(x, y + 1), idx(x + 1, y + 1)),
+            ] {
+                mesh.face_indicies.push(Triangle {
+                    a,
+                    b,
+                    c,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    mesh
+}
+
+#[test]
+fn test_bvh_query() {
+    let mesh = grid_mesh(16);
+    let bvh = Bvh::new(&mesh);
+    let face_bounds = |face: usize| {
+        let t = mesh.face_indicies[face];
+        Aabb::from_points([t.a, t.b, t.c].map(|idx| mesh.verticies[idx])).unwrap()
+    };
+
+    // strictly inside of the squares from (3, 5) to (5, 6), so both triangles of those 3 squares
+    let region = Aabb {
+        min: Vector3 {
+            x: 3.25,
+            y: 5.25,
+            z: -1.0,
+        },
+        max: Vector3 {
+            x: 5.75,
+            y: 5.75,
+            z: 1.0,
+        },
+    };
+    let faces = bvh.query_aabb(region);
+    assert_eq!(faces.len(), 6);
+    assert!(faces.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(faces.iter().all(|&face| face_bounds(face).overlaps(region)));
+    let expected: Vec<usize> = (0..mesh.face_indicies.len())
+        .filter(|&face| face_bounds(face).overlaps(region))
+        .collect();
+    assert_eq!(faces, expected);
+
+    // everything, nothing, and a mesh without faces
+    let all_faces: Vec<usize> = (0..mesh.face_indicies.len()).collect();
+    assert_eq!(bvh.query(|_| true), all_faces);
+    let above = Aabb {
+        min: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.5,
+        },
+        max: Vector3 {
+            x: 16.0,
+            y: 16.0,
+            z: 1.0,
+        },
+    };
+    assert!(bvh.query_aabb(above).is_empty());
+    assert!(Bvh::new(&Mesh::default()).query(|_| true).is_empty());
+}
+
+#[test]
+fn test_merge() {
+    let mut merged = single_triangle(0.0);
+    let other = single_triangle(5.0);
+    assert!(merged.merge(&other).is_ok());
+
+    assert_eq!(merged.verticies.len(), 6);
+    assert_eq!(merged.vertex_normals.len(), 2);
+    assert_eq!(merged.vertex_texture_coords.len(), 6);
+    assert_eq!(merged.face_indicies.len(), 2);
+    assert_eq!(
+        merged.face_indicies[0],
+        single_triangle(0.0).face_indicies[0]
+    );
+
+    let rebased = merged.face_indicies[1];
+    assert_eq!((rebased.a, rebased.b, rebased.c), (3, 4, 5));
+    assert_eq!(
+        (rebased.a_normal, rebased.b_normal, rebased.c_normal),
+        (1, 1, 1)
+    );
+    assert_eq!(
+        (rebased.a_texture, rebased.b_texture, rebased.c_texture),
+        (3, 4, 5)
+    );
+    assert_eq!(merged.verticies[rebased.a], other.verticies[0]);
+}
+
+#[test]
+fn test_merge_textures() {
+    let mut untextured = single_triangle(0.0);
+    let mut textured = single_triangle(1.0);
+    textured.material.diffuse_texture = Some(Arc::new(Image::new(1, 1)));
+
+    // self picks up the texture if it has none
+    assert!(untextured.merge(&textured).is_ok());
+    assert_eq!(
+        untextured
+            .material
+            .diffuse_texture
+            .as_ref()
+            .map(|texture| texture.to_image()),
+        Some(Image::new(1, 1))
+    );
+
+    // but two textures cannot be combined
+    assert!(untextured.merge(&textured).is_err());
+}
+
+#[test]
+fn test_weld_vertices() {
+    // a cube where every triangle has its own three verticies, like an STL import
+    let corner = |idx: usize| Vector3 {
+        x: (idx & 1) as f32,
+        y: ((idx >> 1) & 1) as f32,
+        z: ((idx >> 2) & 1) as f32,
+    };
+    let quads = [
+        [0, 1, 3, 2],
+        [4, 6, 7, 5],
+        [0, 4, 5, 1],
+        [2, 3, 7, 6],
+        [0, 2, 6, 4],
+        [1, 5, 7, 3],
+    ];
+    let mut cube = Mesh::default();
+    for quad in quads {
+        for triangle in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+            let first = cube.verticies.len();
+            // nudge each copy a little so welding has to respect epsilon
+            for (offset, corner_idx) in triangle.into_iter().enumerate() {
+                cube.verticies.push(
+                    corner(corner_idx)
+                        + Vector3 {
+                            x: offset as f32 * 1.0e-5,
+                            y: 0.0,
+                            z: 0.0,
+                        },
+                );
+            }
+            cube.face_indicies.push(Triangle {
+                a: first,
+                b: first + 1,
+                c: first + 2,
+                a_normal: first,
+                b_normal: first + 1,
+                c_normal: first + 2,
+                ..Default::default()
+            });
+        }
+    }
+    assert_eq!(cube.verticies.len(), 36);
+
+    cube.weld_vertices(1.0e-3);
+    assert_eq!(cube.verticies.len(), 8);
+    assert_eq!(cube.vertex_normals.len(), 8);
+    assert_eq!(cube.face_indicies.len(), 12);
+    for face in cube.face_indicies.iter() {
+        assert!(face.a < 8 && face.b < 8 && face.c < 8);
+        assert_ne!(face.a, face.b);
+        assert_ne!(face.b, face.c);
+        assert_eq!(
+            (face.a_normal, face.b_normal, face.c_normal),
+            (face.a, face.b, face.c)
+        );
+    }
+
+    // the shared corner normals now point diagonally out of the cube
+    for (vertex, normal) in cube.verticies.iter().zip(cube.vertex_normals.iter()) {
+        assert!((normal.magnitude() - 1.0).abs() < 1.0e-4);
+        assert!(normal.x.abs() > 0.1 && normal.y.abs() > 0.1 && normal.z.abs() > 0.1);
+        assert_eq!(normal.x > 0.0, vertex.x > 0.5);
+    }
+}
+
+#[test]
+fn test_convert_up_axis() {
+    let mut mesh = single_triangle(0.0);
+    // a vertex one unit up and two units towards the front view in Z-up space, normals face up
+    mesh.verticies[2] = Vector3 {
+        x: 3.0,
+        y: -2.0,
+        z: 1.0,
+    };
+    let mut upright = mesh.clone();
+    upright.convert_up_axis(UpAxis::Y);
+    assert_eq!(upright.verticies, mesh.verticies);
+
+    mesh.convert_up_axis(UpAxis::Z);
+    assert_eq!(
+        mesh.verticies[2],
+        Vector3 {
+            x: 3.0,
+            y: 1.0,
+            z: 2.0,
+        }
+    );
+    assert_eq!(
+        mesh.vertex_normals[0],
+        Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        }
+    );
+}
+
+#[test]
+fn test_face_area_and_centroid() {
+    let mut mesh = single_triangle(0.0);
+    assert!((mesh.face_area(0) - 0.5).abs() < 1.0e-6);
+    let centroid = mesh.face_centroid(0);
+    assert!((centroid.x - 1.0 / 3.0).abs() < 1.0e-6);
+    assert!((centroid.y - 1.0 / 3.0).abs() < 1.0e-6);
+    assert_eq!(centroid.z, 0.0);
+
+    // faces are looked up by index, so a merged-in face reports its own geometry
+    assert!(mesh.merge(&single_triangle(2.0)).is_ok());
+    assert!((mesh.face_area(1) - 0.5).abs() < 1.0e-6);
+    assert!((mesh.face_centroid(1).x - 7.0 / 3.0).abs() < 1.0e-6);
+}
+
+#[test]
+fn test_bounding_sphere() {
+    let (center, radius) = single_triangle(2.0).bounding_sphere();
+    assert_eq!(
+        center,
+        Vector3 {
+            x: 2.5,
+            y: 0.5,
+            z: 0.0
+        }
+    );
+    assert!((radius - 0.5_f32.sqrt()).abs() < 1.0e-6);
+
+    let (center, radius) = Mesh::default().bounding_sphere();
+    assert_eq!(center, Vector3::ORIGIN);
+    assert_eq!(radius, 0.0);
+}
+
+#[test]
+fn test_normalize_winding() {
+    let dir = TempDir::new("normalize_winding");
+
+    // both faces have normals facing +z, but only the second is wound to face +z
+    fs::write(
+        dir.join("wound.obj"),
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 2/2/1\n",
+    )
+    .unwrap();
+
+    let maybe_mesh = Mesh::from_obj_file(&dir.join("wound.obj"));
+    assert!(maybe_mesh.is_ok());
+    let mut mesh = maybe_mesh.unwrap();
+    let flipped = mesh.face_indicies[0];
+    assert_eq!((flipped.a, flipped.b, flipped.c), (0, 2, 1));
+    assert_eq!(
+        (flipped.a_texture, flipped.b_texture, flipped.c_texture),
+        (0, 2, 1)
+    );
+    assert_eq!(mesh.face_indicies[1], flipped);
+
+    // an already consistent mesh is left alone
+    assert_eq!(mesh.normalize_winding(), 0);
+
+    // flipping the normal flips both faces back
+    mesh.vertex_normals[0] = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: -1.0,
+    };
+    assert_eq!(mesh.normalize_winding(), 2);
+    assert_eq!(mesh.face_indicies[0].b, 1);
+}
+
+#[test]
+fn test_obj_line_elements() {
+    let dir = TempDir::new("obj_line_elements");
+    let load = |content: &str| {
+        let path = dir.join("lines.obj");
+        fs::write(&path, content).unwrap();
+        Mesh::from_obj_file(&path)
+    };
+
+    // a polyline through three verticies plus a closing segment with texture coordinates
+    let mesh =
+        load("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nf 1 2 3\nl 1 2 3\nl 4/1 1/1\n").unwrap();
+    assert_eq!(mesh.edges, vec![(0, 1), (1, 2), (3, 0)]);
+    assert_eq!(mesh.face_indicies.len(), 1);
+
+    // edges follow their verticies through a merge
+    let mut merged = single_triangle(0.0);
+    merged.merge(&mesh).unwrap();
+    assert_eq!(merged.edges, vec![(3, 4), (4, 5), (6, 3)]);
+
+    assert!(load("v 0 0 0\nl 1\n").is_err());
+    assert!(load("v 0 0 0\nv 1 0 0\nl 0 1\n").is_err());
+    assert!(load("v 0 0 0\nv 1 0 0\nl 1 two\n").is_err());
+
+    // p elements list any number of single verticies
+    let mesh = load("v 0 0 0\nv 1 0 0\nv 1 1 0\nl 1 2 3\np 1\np 2 3\n").unwrap();
+    assert_eq!(mesh.edges.len(), 2);
+    assert_eq!(mesh.points, vec![0, 1, 2]);
+    assert!(mesh.face_indicies.is_empty());
+    assert!(load("v 0 0 0\np\n").is_err());
+    assert!(load("v 0 0 0\np 0\n").is_err());
+}
+
+#[test]
+fn test_obj_blank_lines_and_comments() {
+    let dir = TempDir::new("obj_blank_lines_and_comments");
+    let path = dir.join("commented.obj");
+    fs::write(
+        &path,
+        "# exported by hand\n\nv 0 0 0\n   \nv 1 0 0 # trailing comment\n#v 5 5 5\n\t\nv 0 1 0\n\n  # indented comment\nf 1 2 3 #the only face\n\n",
+    )
+    .unwrap();
+
+    let mesh = Mesh::from_obj_file(&path).unwrap();
+    assert_eq!(mesh.verticies.len(), 3);
+    assert_eq!(mesh.verticies[1].x, 1.0);
+    assert_eq!(mesh.face_indicies.len(), 1);
+    assert_eq!(
+        (
+            mesh.face_indicies[0].a,
+            mesh.face_indicies[0].b,
+            mesh.face_indicies[0].c
+        ),
+        (0, 1, 2)
+    );
+
+    // the bundled teapot is split up by blank lines
+    let teapot = Mesh::from_obj_file(Path::new("data/teapot.obj")).unwrap();
+    assert!(!teapot.face_indicies.is_empty());
+}
+
+#[test]
+fn test_obj_vertex_weights() {
+    let dir = TempDir::new("obj_vertex_weights");
+    let load = |content: &str| {
+        let path = dir.join("weights.obj");
+        fs::write(&path, content).unwrap();
+        Mesh::from_obj_file(&path)
+    };
+
+    let mesh = load("v 2 4 6 2\nv 1 2 3\nv 1 2 3 1.0\nv -1 0.5 2 -0.5\n").unwrap();
+    let expected = Vector3 {
+        x: 1.0,
+        y: 2.0,
+        z: 3.0,
+    };
+    assert_eq!(mesh.verticies[0], expected);
+    assert_eq!(mesh.verticies[1], expected);
+    assert_eq!(mesh.verticies[2], expected);
+    assert_eq!(
+        mesh.verticies[3],
+        Vector3 {
+            x: 2.0,
+            y: -1.0,
+            z: -4.0
+        }
+    );
+
+    assert!(load("v 1 2 3 0\n").is_err());
+    assert!(load("v 1 2 3 heavy\n").is_err());
+}
+
+#[test]
+fn test_obj_round_trip() {
+    let dir = TempDir::new("obj_round_trip");
+
+    let teapot = Mesh::from_obj_file(Path::new("data/teapot.obj")).unwrap();
+    let path = dir.join("teapot.obj");
+    assert!(teapot.save_to_obj(&path).is_ok());
+    let reloaded = Mesh::from_obj_file(&path).unwrap();
+    assert_eq!(reloaded.verticies, teapot.verticies);
+    assert_eq!(reloaded.face_indicies, teapot.face_indicies);
+    // normals from a file are renormalized, which can move them by a rounding error
+    assert_eq!(reloaded.vertex_normals.len(), teapot.vertex_normals.len());
+    for (reloaded_normal, normal) in reloaded
+        .vertex_normals
+        .iter()
+        .zip(teapot.vertex_normals.iter())
+    {
+        assert!(reloaded_normal.approx_eq(*normal, 1.0e-6));
+    }
+
+    // texture coordinates, split normals and lines come back too
+    let path = dir.join("quad.obj");
+    fs::write(
+        &path,
+        "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+         vn 0 0 1\nvn 0 0.6 0.8\nf 1/1/1 3/3/1 2/2/2\nf 1/1/1 4/4/2 3/3/2\nl 1 2 3\n",
+    )
+    .unwrap();
+    let quad = Mesh::from_obj_file(&path).unwrap();
+    assert!(quad.save_to_obj(&path).is_ok());
+    let reloaded = Mesh::from_obj_file(&path).unwrap();
+    assert_eq!(reloaded.verticies, quad.verticies);
+    assert_eq!(reloaded.face_indicies, quad.face_indicies);
+    assert_eq!(reloaded.vertex_normals, quad.vertex_normals);
+    assert_eq!(reloaded.vertex_texture_coords, quad.vertex_texture_coords);
+    assert_eq!(reloaded.edges, vec![(0, 1), (1, 2)]);
+}
+
+#[test]
+fn test_vertex_colors() {
+    let dir = TempDir::new("vertex_colors");
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+
+    // colors follow the position, a 4th value alone is still a weight
+    let path = dir.join("colored.obj");
+    fs::write(
+        &path,
+        "v 0 0 0 1 0 0\nv 2 0 0 2\nv 0 1 0 0.2 0.5 1\nf 1 3 2\n",
+    )
+    .unwrap();
+    let colored = Mesh::from_obj_file(&path).unwrap();
+    assert_eq!(colored.verticies[1].x, 1.0);
+    assert_eq!(
+        colored.vertex_colors,
+        vec![
+            Color { r: 255, g: 0, b: 0 },
+            white,
+            Color {
+                r: 51,
+                g: 128,
+                b: 255
+            }
+        ]
+    );
+
+    // and survive being written back out
+    let path = dir.join("written.obj");
+    assert!(colored.save_to_obj(&path).is_ok());
+    assert_eq!(
+        Mesh::from_obj_file(&path).unwrap().vertex_colors,
+        colored.vertex_colors
+    );
+
+    // no colors in the file means none on the mesh
+    let plain_path = dir.join("plain.obj");
+    fs::write(&plain_path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n").unwrap();
+    let plain = Mesh::from_obj_file(&plain_path).unwrap();
+    assert!(plain.vertex_colors.is_empty());
+
+    // merging pads whichever side has no colors with white
+    let mut merged = plain.clone();
+    assert!(merged.merge(&colored).is_ok());
+    assert_eq!(merged.vertex_colors.len(), 6);
+    assert_eq!(merged.vertex_colors[..3], [white; 3]);
+    assert_eq!(merged.vertex_colors[3..], colored.vertex_colors[..]);
+    let mut merged = colored.clone();
+    assert!(merged.merge(&plain).is_ok());
+    assert_eq!(merged.vertex_colors[3..], [white; 3]);
+
+    // welding keeps the first color of every welded group
+    let mut welded = colored.clone();
+    assert!(welded.merge(&colored).is_ok());
+    welded.vertex_colors[3] = Color::default();
+    welded.weld_vertices(1.0e-4);
+    assert_eq!(welded.vertex_colors, colored.vertex_colors);
+}
+
+#[test]
+fn test_mixed_face_normals() {
+    let dir = TempDir::new("mixed_face_normals");
+    let path = dir.join("mixed.obj");
+    // the first face lists a (deliberately tilted) normal, the second none at all
+    fs::write(
+        &path,
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nv 0 1 1\nv 1 0 1\nvn 0 0.6 -0.8\n\
+         f 1//1 2//1 3//1\nf 4 5 6\n",
+    )
+    .unwrap();
+    let mesh = Mesh::from_obj_file(&path).unwrap();
+
+    // the file's normal is kept as is
+    let provided = mesh.face_indicies[0];
+    assert_eq!(
+        (provided.a_normal, provided.b_normal, provided.c_normal),
+        (0, 0, 0)
+    );
+    assert!(mesh.vertex_normals[0].approx_eq(
+        Vector3 {
+            x: 0.0,
+            y: 0.6,
+            z: -0.8
+        },
+        1.0e-6
+    ));
+
+    // and the other face gets generated ones after it, facing the way it winds
+    let generated = mesh.face_indicies[1];
+    for normal_idx in [generated.a_normal, generated.b_normal, generated.c_normal] {
+        assert!(normal_idx > 0 && normal_idx < mesh.vertex_normals.len());
+        assert!(mesh.vertex_normals[normal_idx].approx_eq(
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0
+            },
+            1.0e-6
+        ));
+    }
+}
+
+#[test]
+fn test_smoothing_groups() {
+    let dir = TempDir::new("smoothing_groups");
+
+    // a floor and a wall meeting along the edge between verticies 3 and 4
+    let geometry = "v 0 0 0\nv 1 0 0\nv 1 0 -1\nv 0 0 -1\nv 0 1 -1\nv 1 1 -1\n";
+    let floor = "f 1 3 2\nf 1 4 3\n";
+    let wall = "f 4 6 3\nf 4 5 6\n";
+    let load = |name: &str, content: String| {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        Mesh::from_obj_file(&path).unwrap()
+    };
+    let crease_normals = |mesh: &Mesh, face_idx: usize| {
+        let face = mesh.face_indicies[face_idx];
+        [face.a, face.b, face.c]
+            .into_iter()
+            .zip([face.a_normal, face.b_normal, face.c_normal])
+            .filter(|(vertex_idx, _)| *vertex_idx == 2 || *vertex_idx == 3)
+            .map(|(_, normal_idx)| mesh.vertex_normals[normal_idx])
+            .collect::<Vec<Vector3>>()
+    };
+
+    // separate groups keep a hard crease
+    let creased = load(
+        "creased.obj",
+        format!("{}s 1\n{}s 2\n{}", geometry, floor, wall),
+    );
+    for normal in crease_normals(&creased, 1) {
+        assert!((normal.y.abs() - 1.0).abs() < 1.0e-5);
+    }
+    for normal in crease_normals(&creased, 2) {
+        assert!((normal.z.abs() - 1.0).abs() < 1.0e-5);
+    }
+
+    // s off gives every face its own flat normal
+    let flat = load("flat.obj", format!("{}s off\n{}{}", geometry, floor, wall));
+    assert_eq!(flat.vertex_normals.len(), 4);
+    for normal in crease_normals(&flat, 1) {
+        assert!((normal.y.abs() - 1.0).abs() < 1.0e-5);
+    }
+
+    // one group smooths across the crease just like having no directive at all
+    let smooth = load("smooth.obj", format!("{}s 1\n{}{}", geometry, floor, wall));
+    let unspecified = load("unspecified.obj", format!("{}{}{}", geometry, floor, wall));
+    for face_idx in 0..4 {
+        assert_eq!(
+            crease_normals(&smooth, face_idx),
+            crease_normals(&unspecified, face_idx)
+        );
+    }
+    for normal in crease_normals(&smooth, 2) {
+        assert!(normal.y.abs() > 0.1 && normal.z.abs() > 0.1);
+    }
+}
+
+#[test]
+fn test_recompute_normals() {
+    let dir = TempDir::new("recompute_normals");
+    // a unit cube wound so its faces point outward
+    let path = dir.join("cube.obj");
+    fs::write(
+        &path,
+        "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\n\
+         f 1 2 3\nf 1 3 4\nf 5 7 6\nf 5 8 7\nf 1 6 2\nf 1 5 6\n\
+         f 4 3 7\nf 4 7 8\nf 1 8 5\nf 1 4 8\nf 2 7 3\nf 2 6 7\n",
+    )
+    .unwrap();
+    let loaded = Mesh::from_obj_file(&path).unwrap();
+
+    let mut cube = loaded.clone();
+    cube.vertex_normals = vec![Vector3::default(); 1];
+    for face in cube.face_indicies.iter_mut() {
+        (face.a_normal, face.b_normal, face.c_normal) = (0, 0, 0);
+    }
+    cube.recompute_normals();
+
+    // the same normals the loader made, each pointing out of the cube
+    assert_eq!(cube.vertex_normals, loaded.vertex_normals);
+    let center = Vector3 {
+        x: 0.5,
+        y: 0.5,
+        z: 0.5,
+    };
+    for face in cube.face_indicies.iter() {
+        for (vertex_idx, normal_idx) in [
+            (face.a, face.a_normal),
+            (face.b, face.b_normal),
+            (face.c, face.c_normal),
+        ] {
+            let normal = cube.vertex_normals[normal_idx];
+            assert!((normal.magnitude() - 1.0).abs() < 1.0e-5);
+            let outward = (cube.verticies[vertex_idx] - center).normalized();
+            assert!(Vector3::dot(normal, outward) > 0.5);
+        }
+    }
+
+    // moving a vertex changes the normals around it
+    cube.verticies[6] = cube.verticies[6] * 3.0;
+    cube.recompute_normals();
+    assert_ne!(cube.vertex_normals[2], loaded.vertex_normals[2]);
+}
+
+#[test]
+fn test_smoothing_angle() {
+    let dir = TempDir::new("smoothing_angle");
+    let path = dir.join("cube.obj");
+    fs::write(
+        &path,
+        "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\n\
+         f 1 3 2\nf 1 4 3\nf 5 6 7\nf 5 7 8\nf 1 2 6\nf 1 6 5\n\
+         f 4 7 3\nf 4 8 7\nf 1 5 8\nf 1 8 4\nf 2 3 7\nf 2 7 6\n",
+    )
+    .unwrap();
+
+    // at 30 degrees every corner keeps the flat normal of its own face
+    let creased = Mesh::from_obj_file_with_smoothing_angle(&path, 30_f32.to_radians()).unwrap();
+    for face in creased.face_indicies.iter() {
+        let v0 = creased.verticies[face.a];
+        let v1 = creased.verticies[face.b];
+        let v2 = creased.verticies[face.c];
+        let face_normal = Vector3::cross(v2 - v0, v1 - v0).normalized();
+        for normal_idx in [face.a_normal, face.b_normal, face.c_normal] {
+            assert_eq!(creased.vertex_normals[normal_idx], face_normal);
+        }
+    }
+    // the two triangles of each side share normals, so a cube has 4 per side
+    assert_eq!(creased.vertex_normals.len(), 24);
+    let corner_normals: Vec<usize> = creased
+        .face_indicies
+        .iter()
+        .flat_map(|face| {
+            [
+                (face.a, face.a_normal),
+                (face.b, face.b_normal),
+                (face.c, face.c_normal),
+            ]
+        })
+        .filter(|(vertex_idx, _)| *vertex_idx == 0)
+        .map(|(_, normal_idx)| normal_idx)
+        .collect();
+    let mut distinct = corner_normals.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 3);
+
+    // a wide enough angle smooths everything like the default loader
+    let smooth = Mesh::from_obj_file(&path).unwrap();
+    let wide = Mesh::from_obj_file_with_smoothing_angle(&path, 180_f32.to_radians()).unwrap();
+    for (smooth_face, wide_face) in smooth.face_indicies.iter().zip(wide.face_indicies.iter()) {
+        for (smooth_idx, wide_idx) in [
+            (smooth_face.a_normal, wide_face.a_normal),
+            (smooth_face.b_normal, wide_face.b_normal),
+            (smooth_face.c_normal, wide_face.c_normal),
+        ] {
+            let difference = smooth.vertex_normals[smooth_idx] - wide.vertex_normals[wide_idx];
+            assert!(difference.magnitude() < 1.0e-5);
+        }
+    }
+}
+
+#[test]
+fn test_load_material() {
+    let dir = TempDir::new("load_material");
+
+    let texture = Image::new(1, 2);
+    texture.save_to_png(&dir.join("diffuse.png")).unwrap();
+    // only the first material is used
+    fs::write(
+        dir.join("shiny.mtl"),
+        "# exported\nnewmtl shiny\nNs 96.5\nKd 0.5 1.0 0.0\nKs 1 1 1\nKe 0 0 1\n\nmap_Kd diffuse.png\nnewmtl other\nKd 0 0 0\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("shiny.obj"),
+        "mtllib shiny.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n",
+    )
+    .unwrap();
+
+    let maybe_mesh = Mesh::from_obj_file(&dir.join("shiny.obj"));
+    assert!(maybe_mesh.is_ok());
+    assert_eq!(
+        maybe_mesh.unwrap().material,
+        Material {
+            diffuse_color: Color {
+                r: 127,
+                g: 255,
+                b: 0,
+            },
+            diffuse_texture: Some(Arc::new(texture)),
+            specular_color: Color {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+            shininess: 96.5,
+            emissive_color: Color { r: 0, g: 0, b: 255 },
+            ..Default::default()
+        }
+    );
+
+    // a mesh without a material lib gets the default white material
+    fs::write(
+        dir.join("plain.obj"),
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n",
+    )
+    .unwrap();
+    assert_eq!(
+        Mesh::from_obj_file(&dir.join("plain.obj"))
+            .unwrap()
+            .material,
+        Material::default()
+    );
+
+    // d is opacity and Tr is its inverse, both from 0 to 1
+    let opacity_of = |mtl: &str| {
+        fs::write(dir.join("glass.mtl"), format!("newmtl glass\n{}\n", mtl)).unwrap();
+        fs::write(
+            dir.join("glass.obj"),
+            "mtllib glass.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n",
+        )
+        .unwrap();
+        Mesh::from_obj_file(&dir.join("glass.obj")).map(|mesh| mesh.material.opacity)
+    };
+    assert_eq!(opacity_of("d 0.25").unwrap(), 0.25);
+    assert_eq!(opacity_of("Tr 0.25").unwrap(), 0.75);
+    assert!(!Material::default().is_transparent());
+    assert!(opacity_of("d 1.5").is_err());
+    assert!(opacity_of("d").is_err());
+}
+
+#[test]
+fn test_material_diffuse_mipmap() {
+    let black = Color::default();
+    let red = Color { r: 255, g: 0, b: 0 };
+    let image = Image::checkerboard(8, 8, 2, black, red);
+    let mut material = Material {
+        diffuse_texture: Some(Arc::new(PalettedImage::from_image(&image).unwrap())),
+        ..Default::default()
+    };
+
+    // a paletted texture is expanded into the mipmap once, every draw after that reuses it
+    let mipmap = material.diffuse_mipmap().unwrap();
+    assert_eq!(mipmap.levels[0], image);
+    assert!(Arc::ptr_eq(&mipmap, &material.diffuse_mipmap().unwrap()));
+    assert!(Arc::ptr_eq(
+        &mipmap,
+        &material.clone().diffuse_mipmap().unwrap()
+    ));
+
+    // replacing the texture builds a new one
+    material.diffuse_texture = Some(Arc::new(image.clone()));
+    assert!(!Arc::ptr_eq(&mipmap, &material.diffuse_mipmap().unwrap()));
+
+    material.diffuse_texture = None;
+    assert!(material.diffuse_mipmap().is_none());
+}
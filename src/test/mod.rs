@@ -1,2 +1,50 @@
+mod anim_tests;
+#[cfg(feature = "gltf")]
+mod gltf_tests;
 mod image_tests;
 mod math_tests;
+mod mesh_tests;
+mod rasterizer_tests;
+mod scene_tests;
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/*
+ * An empty directory for one test to write its files into, named after the test under the system
+ * temp directory. It's removed again when dropped, so a failing assert doesn't leave it behind.
+ */
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("rasterboy_test_{name}"));
+        // a test that was killed before it could clean up may have left the directory behind
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,1560 @@
+use crate::image::*;
+use crate::math::*;
+use crate::mesh::*;
+use crate::rasterizer::*;
+use crate::scene::*;
+use std::sync::Arc;
+
+const WIDTH: i32 = 16;
+const HEIGHT: i32 = 16;
+
+fn test_camera() -> Camera {
+    Camera::new(WIDTH, HEIGHT, 90_f32.to_radians(), 0.1, 100.0)
+}
+
+fn test_light() -> Light {
+    Light {
+        position: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 10.0,
+        },
+        color: Color {
+            r: 255,
+            g: 255,
+            b: 255,
+        }
+        .to_vector3(),
+        ambient_strength: 0.2,
+    }
+}
+
+// a camera facing quad at the given (negative) depth that exactly covers the view of test_camera()
+fn screen_quad(z: f32) -> Mesh {
+    // the fov is 90 degrees so the view is exactly as wide as it is deep
+    let extent = -z;
+    let normal = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    };
+    Mesh {
+        verticies: vec![
+            Vector3 {
+                x: -extent,
+                y: -extent,
+                z,
+            },
+            Vector3 {
+                x: extent,
+                y: -extent,
+                z,
+            },
+            Vector3 {
+                x: extent,
+                y: extent,
+                z,
+            },
+            Vector3 {
+                x: -extent,
+                y: extent,
+                z,
+            },
+        ],
+        face_indicies: vec![
+            Triangle {
+                a: 0,
+                b: 2,
+                c: 1,
+                ..Default::default()
+            },
+            Triangle {
+                a: 0,
+                b: 3,
+                c: 2,
+                ..Default::default()
+            },
+        ],
+        vertex_normals: vec![normal],
+        ..Default::default()
+    }
+}
+
+fn render_mesh(mesh: &Mesh, options: RenderOptions) -> Vec<Color> {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let mut pixel_buffer = vec![Color::default(); num_pixels];
+    let mut depth_buffer = vec![f32::MAX; num_pixels];
+    draw_mesh(
+        mesh,
+        Mat4::identity(),
+        &[test_light()],
+        test_camera(),
+        options,
+        &mut pixel_buffer,
+        &mut depth_buffer,
+    );
+    pixel_buffer
+}
+
+#[test]
+fn test_full_frame_render() {
+    let pixels = render_mesh(&screen_quad(-2.0), RenderOptions::default());
+    assert!(pixels.iter().all(|&p| p != Color::default()));
+}
+
+#[test]
+fn test_scissor_render() {
+    let options = RenderOptions {
+        scissor: Some((0, 0, WIDTH / 2, HEIGHT)),
+        ..Default::default()
+    };
+    let pixels = render_mesh(&screen_quad(-2.0), options);
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let pixel = pixels[(y * WIDTH + x) as usize];
+            if x < WIDTH / 2 {
+                assert_ne!(pixel, Color::default());
+            } else {
+                assert_eq!(pixel, Color::default());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_clear_helpers() {
+    let red = Color { r: 255, g: 0, b: 0 };
+    let mut pixels = vec![Color::default(); 4];
+    let mut depths = vec![0.5; 4];
+    clear_color(&mut pixels, red);
+    clear_depth(&mut depths, f32::MAX);
+    assert!(pixels.iter().all(|&pixel| pixel == red));
+    assert!(depths.iter().all(|&depth| depth == f32::MAX));
+}
+
+#[test]
+fn test_multi_pass_clears() {
+    let background = Color { r: 0, g: 0, b: 90 };
+    let scene_with = |mesh: Mesh| Scene {
+        camera: test_camera(),
+        lights: vec![test_light()],
+        models: vec![Model {
+            mesh,
+            transform: Mat4::identity(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let near = scene_with(screen_quad(-1.0));
+    let far = scene_with(screen_quad(-4.0));
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let mut pixels = vec![Color::default(); num_pixels];
+    let mut depths = vec![f32::MAX; num_pixels];
+
+    // the first pass only leaves depth behind on the left half
+    let left_half = RenderOptions {
+        scissor: Some((0, 0, WIDTH / 2, HEIGHT)),
+        ..Default::default()
+    };
+    near.render(left_half, &mut pixels, &mut depths);
+    let far_color = render_mesh(&screen_quad(-4.0), RenderOptions::default())[0];
+
+    // clearing only color keeps the near depth, so the far quad only shows up on the right
+    let keep_depth = RenderOptions {
+        clear_color: Some(background),
+        ..Default::default()
+    };
+    far.render(keep_depth, &mut pixels, &mut depths);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let pixel = pixels[(y * WIDTH + x) as usize];
+            assert_eq!(pixel, if x < WIDTH / 2 { background } else { far_color });
+        }
+    }
+
+    // clearing only depth keeps the colors outside the scissor and lets the far quad through inside
+    let keep_color = RenderOptions {
+        clear_depth: Some(f32::MAX),
+        scissor: Some((0, 0, WIDTH / 4, HEIGHT)),
+        ..Default::default()
+    };
+    far.render(keep_color, &mut pixels, &mut depths);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let pixel = pixels[(y * WIDTH + x) as usize];
+            let expected = if (WIDTH / 4..WIDTH / 2).contains(&x) {
+                background
+            } else {
+                far_color
+            };
+            assert_eq!(pixel, expected);
+        }
+    }
+}
+
+// a floor quad receding steeply away from test_camera() textured with a gradient along v
+fn textured_floor() -> Mesh {
+    let mut texture = Image::new(1, 8);
+    for (i, pixel) in texture.data.iter_mut().enumerate() {
+        let value = (i * 32) as u8;
+        *pixel = Color {
+            r: value,
+            g: value,
+            b: value,
+        };
+    }
+
+    Mesh {
+        verticies: vec![
+            Vector3 {
+                x: -1.0,
+                y: -1.0,
+                z: -1.1,
+            },
+            Vector3 {
+                x: 1.0,
+                y: -1.0,
+                z: -1.1,
+            },
+            Vector3 {
+                x: 1.0,
+                y: -1.0,
+                z: -20.0,
+            },
+            Vector3 {
+                x: -1.0,
+                y: -1.0,
+                z: -20.0,
+            },
+        ],
+        face_indicies: vec![
+            Triangle {
+                a: 0,
+                b: 2,
+                c: 1,
+                a_texture: 0,
+                b_texture: 2,
+                c_texture: 1,
+                ..Default::default()
+            },
+            Triangle {
+                a: 0,
+                b: 3,
+                c: 2,
+                a_texture: 0,
+                b_texture: 3,
+                c_texture: 2,
+                ..Default::default()
+            },
+        ],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        }],
+        vertex_texture_coords: vec![
+            Vector3::ORIGIN,
+            Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        ],
+        material: Material {
+            diffuse_texture: Some(Arc::new(texture)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_affine_texture_mapping() {
+    let floor = textured_floor();
+    let perspective = render_mesh(&floor, RenderOptions::default());
+    let affine = render_mesh(
+        &floor,
+        RenderOptions {
+            affine_texture_mapping: true,
+            ..Default::default()
+        },
+    );
+
+    let interior_pixel = ((11 * WIDTH) + 8) as usize;
+    assert_ne!(perspective[interior_pixel], Color::default());
+    assert_ne!(affine[interior_pixel], Color::default());
+    assert_ne!(perspective[interior_pixel], affine[interior_pixel]);
+
+    // affine mapping stretches the near part of the texture further up the screen
+    assert!(affine[interior_pixel].r < perspective[interior_pixel].r);
+}
+
+#[test]
+fn test_edge_antialiasing() {
+    // the lower right half of the screen quad with its bottom left corner moved to the middle, so
+    // the steep edge runs across every pixel row, between the samples of every other row
+    let mut triangle = screen_quad(-2.0);
+    triangle.face_indicies.truncate(1);
+    triangle.verticies[0].x = 0.0;
+
+    let aliased = render_mesh(&triangle, RenderOptions::default());
+    let antialiased = render_mesh(
+        &triangle,
+        RenderOptions {
+            edge_antialiasing: true,
+            ..Default::default()
+        },
+    );
+
+    let mut num_fringe_pixels = 0;
+    for (aliased_pixel, antialiased_pixel) in aliased.iter().zip(antialiased.iter()) {
+        if *aliased_pixel != Color::default() {
+            // pixels that were already covered stay exactly the same
+            assert_eq!(aliased_pixel, antialiased_pixel);
+        } else if *antialiased_pixel != Color::default() {
+            num_fringe_pixels += 1;
+        }
+    }
+    assert!(num_fringe_pixels >= HEIGHT / 2);
+
+    // a fringe pixel is only partially blended over the background
+    let fringe_idx = antialiased
+        .iter()
+        .zip(aliased.iter())
+        .position(|(antialiased_pixel, aliased_pixel)| {
+            *aliased_pixel == Color::default() && *antialiased_pixel != Color::default()
+        })
+        .unwrap();
+    assert!(antialiased[fringe_idx].r < aliased[fringe_idx + WIDTH as usize].r);
+}
+
+// a sliver one pixel tall that only has samples on its bottom and right edges, which are excluded
+fn sliver_triangle() -> Mesh {
+    // test_camera() maps world x and y at z = -1 onto 8 pixels per unit, nudge the positions into
+    // the pixel so truncation lands on the intended coordinate
+    let at_pixel = |x: f32, y: f32| Vector3 {
+        x: (x + 0.25) / 8.0 - 1.0,
+        y: 1.0 - (y + 0.25) / 8.0,
+        z: -1.0,
+    };
+    Mesh {
+        verticies: vec![at_pixel(4.0, 5.0), at_pixel(12.0, 4.0), at_pixel(12.0, 5.0)],
+        face_indicies: vec![Triangle {
+            a: 0,
+            b: 1,
+            c: 2,
+            ..Default::default()
+        }],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_conservative_rasterization() {
+    let covered = |pixels: &[Color]| pixels.iter().filter(|&&p| p != Color::default()).count();
+
+    let pixels = render_mesh(&sliver_triangle(), RenderOptions::default());
+    assert_eq!(covered(&pixels), 0);
+
+    let options = RenderOptions {
+        conservative: true,
+        ..Default::default()
+    };
+    let pixels = render_mesh(&sliver_triangle(), options);
+    assert!(covered(&pixels) > 0);
+
+    // only pixels on the rows the sliver spans are touched, it runs from y = 4.25 to 5.25
+    for (idx, pixel) in pixels.iter().enumerate() {
+        if *pixel != Color::default() {
+            assert!([4, 5].contains(&(idx as i32 / WIDTH)));
+        }
+    }
+
+    // pixels that were already covered stay covered
+    let normal = render_mesh(&screen_quad(-2.0), RenderOptions::default());
+    let conservative = render_mesh(&screen_quad(-2.0), options);
+    assert_eq!(normal, conservative);
+}
+
+#[test]
+fn test_conservative_subpixel_triangles() {
+    // test_camera() maps world x and y at z = -1 onto 8 pixels per unit
+    let at = |x: f32, y: f32| Vector3 {
+        x: x / 8.0 - 1.0,
+        y: 1.0 - y / 8.0,
+        z: -1.0,
+    };
+    let tiny = |v0: Vector3, v1: Vector3, v2: Vector3| Mesh {
+        verticies: vec![v0, v1, v2],
+        face_indicies: vec![Triangle {
+            a: 0,
+            b: 1,
+            c: 2,
+            ..Default::default()
+        }],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        ..Default::default()
+    };
+    let options = RenderOptions {
+        conservative: true,
+        ..Default::default()
+    };
+    let covered = |pixels: &[Color]| -> Vec<usize> {
+        (0..pixels.len())
+            .filter(|&idx| pixels[idx] != Color::default())
+            .collect()
+    };
+
+    // a triangle inside of a single pixel has no sample in it, but covers that pixel
+    let inside = tiny(at(6.2, 9.7), at(6.8, 9.2), at(6.8, 9.7));
+    assert!(covered(&render_mesh(&inside, RenderOptions::default())).is_empty());
+    assert_eq!(
+        covered(&render_mesh(&inside, options)),
+        vec![(9 * WIDTH + 6) as usize]
+    );
+
+    // one straddling the edge between two pixels covers both
+    let straddling = tiny(at(7.6, 9.7), at(8.4, 9.2), at(8.4, 9.7));
+    assert_eq!(
+        covered(&render_mesh(&straddling, options)),
+        vec![(9 * WIDTH + 7) as usize, (9 * WIDTH + 8) as usize]
+    );
+
+    // and one collapsed onto a single point still covers the pixel it's in
+    let point = tiny(at(3.5, 2.5), at(3.5, 2.5), at(3.5, 2.5));
+    assert_eq!(
+        covered(&render_mesh(&point, options)),
+        vec![(2 * WIDTH + 3) as usize]
+    );
+}
+
+#[test]
+fn test_base_color() {
+    let white = render_mesh(&screen_quad(-2.0), RenderOptions::default());
+    let mut red_quad = screen_quad(-2.0);
+    red_quad.material.diffuse_color = Color { r: 255, g: 0, b: 0 };
+    let red = render_mesh(&red_quad, RenderOptions::default());
+
+    for (white_pixel, red_pixel) in white.iter().zip(red.iter()) {
+        assert_eq!(red_pixel.r, white_pixel.r);
+        assert_eq!(red_pixel.g, 0);
+        assert_eq!(red_pixel.b, 0);
+    }
+}
+
+#[test]
+fn test_depth_bias() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let mut decal = screen_quad(-2.0);
+    decal.material.diffuse_color = Color { r: 255, g: 0, b: 0 };
+    let biased = RenderOptions {
+        depth_bias: -1.0e-4,
+        ..Default::default()
+    };
+
+    // the biased decal wins regardless of which coplanar quad is drawn first
+    for decal_first in [true, false] {
+        let mut pixel_buffer = vec![Color::default(); num_pixels];
+        let mut depth_buffer = vec![f32::MAX; num_pixels];
+        let mut draw = |mesh: &Mesh, options: RenderOptions| {
+            draw_mesh(
+                mesh,
+                Mat4::identity(),
+                &[test_light()],
+                test_camera(),
+                options,
+                &mut pixel_buffer,
+                &mut depth_buffer,
+            )
+        };
+        if decal_first {
+            draw(&decal, biased);
+            draw(&screen_quad(-2.0), RenderOptions::default());
+        } else {
+            draw(&screen_quad(-2.0), RenderOptions::default());
+            draw(&decal, biased);
+        }
+
+        assert!(pixel_buffer.iter().all(|p| p.r > 0 && p.g == 0 && p.b == 0));
+    }
+}
+
+#[test]
+fn test_dithering() {
+    // dim the quad so the lighting falls off across it rather than saturating
+    let mut quad = screen_quad(-2.0);
+    quad.material.diffuse_color = Color {
+        r: 100,
+        g: 100,
+        b: 100,
+    };
+    let plain = render_mesh(&quad, RenderOptions::default());
+    let options = RenderOptions {
+        dithering: true,
+        ..Default::default()
+    };
+    let dithered = render_mesh(&quad, options);
+
+    // dithering only ever rounds up by a level, and does so somewhere in the frame
+    assert_ne!(plain, dithered);
+    for (plain_pixel, dithered_pixel) in plain.iter().zip(dithered.iter()) {
+        assert!(dithered_pixel.r - plain_pixel.r <= 1);
+    }
+
+    // more distinct levels show up across the frame than without dithering
+    let levels = |pixels: &[Color]| {
+        let mut levels: Vec<u8> = pixels.iter().map(|pixel| pixel.r).collect();
+        levels.sort();
+        levels.dedup();
+        levels.len()
+    };
+    assert!(levels(&dithered) > levels(&plain));
+}
+
+#[test]
+fn test_dithering_gradient_histogram() {
+    // a gradient only a few levels deep from the left edge to the right, lit evenly by the ambient
+    // light alone so it comes out as wide flat bands
+    let mut quad = screen_quad(-2.0);
+    let (dark, light) = (Color { r: 60, g: 0, b: 0 }, Color { r: 66, g: 0, b: 0 });
+    quad.vertex_colors = vec![dark, light, light, dark];
+    let gradient = |options: RenderOptions| {
+        let num_pixels = (WIDTH * HEIGHT) as usize;
+        let mut pixels = vec![Color::default(); num_pixels];
+        let mut depths = vec![f32::MAX; num_pixels];
+        draw_mesh(
+            &quad,
+            Mat4::identity(),
+            &[],
+            test_camera(),
+            RenderOptions {
+                ambient: Some(Vector3 {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                }),
+                ..options
+            },
+            &mut pixels,
+            &mut depths,
+        );
+        pixels
+    };
+    let histogram = |pixels: &[Color]| {
+        let mut counts = [0; 256];
+        for pixel in pixels {
+            counts[pixel.r as usize] += 1;
+        }
+        counts
+    };
+
+    let plain = histogram(&gradient(RenderOptions::default()));
+    let dithered = histogram(&gradient(RenderOptions {
+        dithering: true,
+        ..Default::default()
+    }));
+    assert_ne!(plain, dithered);
+
+    // the bands break up, spreading the pixels over more levels than the plain gradient reaches
+    let levels_used = |counts: &[usize; 256]| counts.iter().filter(|&&count| count > 0).count();
+    assert!(levels_used(&dithered) > levels_used(&plain));
+}
+
+#[test]
+fn test_bvh_culling_matches_unculled_render() {
+    let mut culled = Mesh::from_obj_file(std::path::Path::new("data/teapot.obj")).unwrap();
+    assert!(culled.bvh.is_some());
+    let mut unculled = culled.clone();
+    unculled.bvh = None;
+
+    // only the spout and part of the body are in view, the rest is culled
+    let transform = Mat4::translation(1.5, -1.0, -3.0);
+    let render = |mesh: &Mesh| {
+        let num_pixels = (WIDTH * HEIGHT) as usize;
+        let mut pixel_buffer = vec![Color::default(); num_pixels];
+        let mut depth_buffer = vec![f32::MAX; num_pixels];
+        draw_mesh(
+            mesh,
+            transform,
+            &[test_light()],
+            test_camera(),
+            RenderOptions::default(),
+            &mut pixel_buffer,
+            &mut depth_buffer,
+        );
+        (pixel_buffer, depth_buffer)
+    };
+    let (pixels, depths) = render(&culled);
+    assert!(depths.iter().any(|&depth| depth < f32::MAX));
+    assert!(depths.contains(&f32::MAX));
+    assert_eq!((pixels.clone(), depths.clone()), render(&unculled));
+
+    // merging keeps the bvh up to date with the new faces
+    culled.merge(&unculled).unwrap();
+    let bvh_faces = culled.bvh.as_ref().unwrap().query(|_| true);
+    assert_eq!(bvh_faces.len(), culled.face_indicies.len());
+    assert_eq!(render(&culled).1, depths);
+}
+
+#[test]
+fn test_depth_sort_transparent() {
+    // one mesh holding a red quad in front of a blue one, with the nearer quad listed first
+    let mut quads = screen_quad(-2.0);
+    for vertex in quads.verticies.iter_mut() {
+        vertex.x += 1.0;
+    }
+    quads.merge(&screen_quad(-4.0)).unwrap();
+    let mut texture = Image::new(2, 1);
+    texture.data = vec![Color { r: 255, g: 0, b: 0 }, Color { r: 0, g: 0, b: 255 }];
+    quads.material.diffuse_texture = Some(Arc::new(texture));
+    quads.vertex_texture_coords = vec![
+        Vector3 {
+            x: 0.25,
+            y: 0.5,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 0.75,
+            y: 0.5,
+            z: 0.0,
+        },
+    ];
+    for face in quads.face_indicies[2..].iter_mut() {
+        face.a_texture = 1;
+        face.b_texture = 1;
+        face.c_texture = 1;
+    }
+
+    // each quad drawn alone and opaque gives its lit color at every pixel it covers
+    let only = |faces: std::ops::Range<usize>| {
+        let mut mesh = quads.clone();
+        mesh.face_indicies = mesh.face_indicies[faces].to_vec();
+        render_mesh(&mesh, RenderOptions::default())
+    };
+    let red = only(0..2);
+    let blue = only(2..4);
+
+    quads.material.opacity = 0.5;
+    let sorted = render_mesh(
+        &quads,
+        RenderOptions {
+            depth_sort_transparent: true,
+            ..Default::default()
+        },
+    );
+    let unsorted = render_mesh(&quads, RenderOptions::default());
+
+    // transparent triangles don't write depth, so only the order they are drawn in matters
+    let black = Color::default();
+    let mut num_overlapping = 0;
+    for i in 0..sorted.len() {
+        let behind = black.blend(blue[i], 0.5);
+        if red[i] == black {
+            assert_eq!(sorted[i], behind);
+            assert_eq!(unsorted[i], behind);
+            continue;
+        }
+        num_overlapping += 1;
+        assert_eq!(sorted[i], behind.blend(red[i], 0.5));
+        assert_eq!(unsorted[i], black.blend(red[i], 0.5).blend(blue[i], 0.5));
+        assert_ne!(sorted[i], unsorted[i]);
+    }
+    assert!(num_overlapping > 0 && num_overlapping < sorted.len());
+}
+
+#[test]
+fn test_tiled_render_matches_draw_mesh() {
+    // several tiles wide and tall, and not a multiple of the tile size
+    let camera = Camera::new(
+        3 * TILE_SIZE - 5,
+        2 * TILE_SIZE + 7,
+        90_f32.to_radians(),
+        0.1,
+        100.0,
+    );
+    let num_pixels = (camera.canvas_width * camera.canvas_height) as usize;
+    let mut blue_quad = screen_quad(-4.0);
+    blue_quad.material.diffuse_color = Color {
+        r: 20,
+        g: 40,
+        b: 255,
+    };
+    let floor = textured_floor();
+    let meshes = [
+        (&floor, Mat4::identity()),
+        (&blue_quad, Mat4::translation(1.5, 0.5, 0.0)),
+        (
+            &blue_quad,
+            Mat4::translation(-2.0, -1.0, -1.0) * Mat4::scale(0.5, 0.5, 1.0),
+        ),
+    ];
+    let options = RenderOptions {
+        edge_antialiasing: true,
+        trilinear_filtering: true,
+        scissor: Some((3, 2, camera.canvas_width - 4, camera.canvas_height - 1)),
+        ..Default::default()
+    };
+
+    let mut expected_pixels = vec![Color::default(); num_pixels];
+    let mut expected_depths = vec![f32::MAX; num_pixels];
+    for (mesh, transform) in meshes.iter() {
+        draw_mesh(
+            mesh,
+            *transform,
+            &[test_light()],
+            camera,
+            options,
+            &mut expected_pixels,
+            &mut expected_depths,
+        );
+    }
+    assert!(expected_pixels.iter().any(|&p| p != Color::default()));
+
+    for threads in [1, 3, 8] {
+        let mut pixels = vec![Color::default(); num_pixels];
+        let mut depths = vec![f32::MAX; num_pixels];
+        let options = RenderOptions { threads, ..options };
+        draw_meshes_tiled(
+            &meshes,
+            &[test_light()],
+            camera,
+            options,
+            &mut pixels,
+            &mut depths,
+            &mut |_| {},
+        );
+        assert_eq!(pixels, expected_pixels);
+        assert_eq!(depths, expected_depths);
+    }
+}
+
+#[test]
+fn test_barycentric_and_interpolate() {
+    let at = |x: i32, y: i32| ScreenCoordinate { x, y };
+    let (v0, v1, v2) = (at(0, 0), at(3, 0), at(0, 3));
+    let third = 1.0 / 3.0;
+    // twice the area, negative when wound the other way
+    let area = 9.0;
+
+    let (w0, w1, w2) = barycentric(at(1, 1), v0, v1, v2, area);
+    assert!((w0 - third).abs() < 1.0e-6);
+    assert!((w1 - third).abs() < 1.0e-6);
+    assert!((w2 - third).abs() < 1.0e-6);
+
+    // each vertex gets all of the weight at itself, and the winding doesn't matter
+    assert_eq!(barycentric(v1, v0, v1, v2, area), (0.0, 1.0, 0.0));
+    assert_eq!(barycentric(v2, v0, v2, v1, -area), (0.0, 1.0, 0.0));
+    // outside of the triangle a weight goes negative
+    assert!(barycentric(at(3, 3), v0, v1, v2, area).0 < 0.0);
+
+    let red = Vector3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let green = Vector3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+    let blue = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    };
+    let mixed = interpolate((0.5, 0.25, 0.25), red, green, blue);
+    assert_eq!(
+        mixed,
+        Vector3 {
+            x: 0.5,
+            y: 0.25,
+            z: 0.25
+        }
+    );
+}
+
+#[test]
+fn test_draw_lines() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let green = Color { r: 0, g: 255, b: 0 };
+    let at = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+    // a horizontal line across the middle of the screen and a vertical one that goes behind the
+    // quad, both without any faces
+    let lines = Mesh {
+        verticies: vec![
+            at(-1.0, 0.0, -2.0),
+            at(1.0, 0.0, -2.0),
+            at(0.5, 1.0, -3.0),
+            at(0.5, -1.0, -3.0),
+        ],
+        edges: vec![(0, 1), (2, 3)],
+        ..Default::default()
+    };
+    // draw_mesh draws them unlit in the material's diffuse color
+    let white = lines.material.diffuse_color;
+    let drawn = render_mesh(&lines, RenderOptions::default());
+    assert_eq!(drawn[(8 * WIDTH + 4) as usize], white);
+    assert_eq!(drawn[(4 * WIDTH + 4) as usize], Color::default());
+
+    let mut pixel_buffer = vec![Color::default(); num_pixels];
+    let mut depth_buffer = vec![f32::MAX; num_pixels];
+    draw_mesh(
+        &screen_quad(-2.5),
+        Mat4::identity(),
+        &[test_light()],
+        test_camera(),
+        RenderOptions::default(),
+        &mut pixel_buffer,
+        &mut depth_buffer,
+    );
+    draw_lines(
+        &lines,
+        Mat4::identity(),
+        test_camera(),
+        RenderOptions::default(),
+        green,
+        &mut pixel_buffer,
+        &mut depth_buffer,
+    );
+
+    // the first line is in front of the quad across the whole middle row
+    for x in 4..12 {
+        assert_eq!(pixel_buffer[(8 * WIDTH + x) as usize], green);
+    }
+    // the second line is behind the quad so the depth test hides it
+    let quad_color = pixel_buffer[(4 * WIDTH + 4) as usize];
+    assert_ne!(quad_color, green);
+    for y in 4..7 {
+        for x in 9..13 {
+            assert_eq!(pixel_buffer[(y * WIDTH + x) as usize], quad_color);
+        }
+    }
+}
+
+#[test]
+fn test_draw_point_elements() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let red = Color { r: 255, g: 0, b: 0 };
+    let at = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+    // one point in front of the quad and one behind it, without any faces
+    let points = Mesh {
+        verticies: vec![at(0.0, 0.0, -1.0), at(-2.0, 2.0, -4.0)],
+        points: vec![0, 1],
+        material: Material {
+            diffuse_color: red,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let mut pixel_buffer = vec![Color::default(); num_pixels];
+    let mut depth_buffer = vec![f32::MAX; num_pixels];
+    for mesh in [&screen_quad(-2.0), &points] {
+        draw_mesh(
+            mesh,
+            Mat4::identity(),
+            &[test_light()],
+            test_camera(),
+            RenderOptions::default(),
+            &mut pixel_buffer,
+            &mut depth_buffer,
+        );
+    }
+
+    // a single pixel for the point in front, the one behind is hidden by the depth test
+    let red_pixels: Vec<usize> = (0..num_pixels)
+        .filter(|&idx| pixel_buffer[idx] == red)
+        .collect();
+    assert_eq!(red_pixels, vec![(8 * WIDTH + 8) as usize]);
+}
+
+#[test]
+fn test_draw_points() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    // a point cloud without any faces, the second point sits behind the first
+    let cloud = Mesh {
+        verticies: vec![
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: -2.0,
+            },
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: -5.0,
+            },
+            Vector3 {
+                x: -1.0,
+                y: 1.0,
+                z: -2.0,
+            },
+        ],
+        vertex_normals: vec![
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            };
+            2
+        ],
+        ..Default::default()
+    };
+    assert!(render_mesh(&cloud, RenderOptions::default())
+        .iter()
+        .all(|&p| p == Color::default()));
+
+    let mut pixel_buffer = vec![Color::default(); num_pixels];
+    let mut depth_buffer = vec![f32::MAX; num_pixels];
+    draw_points(
+        &cloud,
+        Mat4::identity(),
+        test_camera(),
+        RenderOptions::default(),
+        3,
+        PointColor::Normal,
+        &mut pixel_buffer,
+        &mut depth_buffer,
+    );
+
+    // the nearest point covers a 3x3 square in the middle colored by its +z normal
+    let facing = Color {
+        r: 127,
+        g: 127,
+        b: 255,
+    };
+    for y in 7..10 {
+        for x in 7..10 {
+            assert_eq!(pixel_buffer[(y * WIDTH + x) as usize], facing);
+        }
+    }
+    assert_eq!(pixel_buffer[(6 * WIDTH + 8) as usize], Color::default());
+    // the third point has no normal and lands halfway to the top left corner
+    let gray = Color {
+        r: 128,
+        g: 128,
+        b: 128,
+    };
+    assert_eq!(pixel_buffer[(4 * WIDTH + 4) as usize], gray);
+    assert_eq!(pixel_buffer.iter().filter(|&&p| p == gray).count(), 9);
+    assert_eq!(pixel_buffer.iter().filter(|&&p| p == facing).count(), 9);
+
+    // flat colors fill the same pixels
+    let red = Color { r: 255, g: 0, b: 0 };
+    let mut flat_pixels = vec![Color::default(); num_pixels];
+    let mut flat_depths = vec![f32::MAX; num_pixels];
+    draw_points(
+        &cloud,
+        Mat4::identity(),
+        test_camera(),
+        RenderOptions::default(),
+        1,
+        PointColor::Flat(red),
+        &mut flat_pixels,
+        &mut flat_depths,
+    );
+    assert_eq!(flat_pixels[(8 * WIDTH + 8) as usize], red);
+    assert_eq!(flat_pixels.iter().filter(|&&p| p == red).count(), 2);
+}
+
+#[test]
+fn test_uv_origin_matches_flipped_texture() {
+    let floor = textured_floor();
+    let mut top_left_floor = floor.clone();
+    top_left_floor.material.uv_origin = UvOrigin::TopLeft;
+
+    // a top left origin should look exactly like the bottom left one with the image upside down
+    let mut flipped_floor = floor.clone();
+    let mut texture = flipped_floor.material.diffuse_texture.unwrap().to_image();
+    let rows: Vec<Vec<Color>> = texture
+        .data
+        .chunks(texture.width)
+        .rev()
+        .map(|row| row.to_vec())
+        .collect();
+    texture.data = rows.concat();
+    flipped_floor.material.diffuse_texture = Some(Arc::new(texture));
+
+    let options = RenderOptions::default();
+    let top_left = render_mesh(&top_left_floor, options);
+    assert_eq!(top_left, render_mesh(&flipped_floor, options));
+    assert_ne!(top_left, render_mesh(&floor, options));
+}
+
+#[test]
+fn test_alpha_mask_cutout() {
+    let black = Color::default();
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let red = Color { r: 200, g: 0, b: 0 };
+
+    // a 2x2 checkerboard mask stretched over the whole screen, so the top left and bottom right
+    // quarters are cut out
+    let mut fence = screen_quad(-1.0);
+    fence.vertex_texture_coords = vec![
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        },
+    ];
+    for face in fence.face_indicies.iter_mut() {
+        (face.a_texture, face.b_texture, face.c_texture) = (face.a, face.b, face.c);
+    }
+    fence.material.alpha_mask = Some(Arc::new(Image::checkerboard(2, 2, 1, black, white)));
+    let mut background = screen_quad(-4.0);
+    background.material.diffuse_color = red;
+
+    let options = RenderOptions {
+        texture_filter: Filter::Nearest,
+        ..Default::default()
+    };
+    let render = |fence: &Mesh| {
+        let num_pixels = (WIDTH * HEIGHT) as usize;
+        let mut pixels = vec![Color::default(); num_pixels];
+        let mut depths = vec![f32::MAX; num_pixels];
+        // the fence goes first so the cut out texels must not have written any depth
+        for mesh in [fence, &background] {
+            draw_mesh(
+                mesh,
+                Mat4::identity(),
+                &[test_light()],
+                test_camera(),
+                options,
+                &mut pixels,
+                &mut depths,
+            );
+        }
+        pixels
+    };
+    let pixels = render(&fence);
+    let background_color = pixels[(4 * WIDTH + 4) as usize];
+    let fence_color = pixels[(4 * WIDTH + 12) as usize];
+    assert_ne!(background_color, fence_color);
+    assert_eq!(background_color.g, 0);
+    assert_eq!(pixels[(12 * WIDTH + 4) as usize], fence_color);
+    assert_eq!(pixels[(12 * WIDTH + 12) as usize], background_color);
+
+    // nothing passes a cutoff above white, and everything passes a cutoff of 0
+    fence.material.alpha_cutoff = 1.1;
+    let pixels = render(&fence);
+    assert!(pixels.iter().all(|&pixel| pixel == background_color));
+    fence.material.alpha_cutoff = 0.0;
+    let pixels = render(&fence);
+    assert!(pixels.iter().all(|&pixel| pixel == fence_color));
+}
+
+#[test]
+fn test_procedural_texture() {
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    // a 2x2 checker stretched over the whole screen, lit the same everywhere
+    let mut quad = screen_quad(-1.0);
+    quad.vertex_texture_coords = vec![
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 0.0,
+        },
+        Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        },
+    ];
+    for face in quad.face_indicies.iter_mut() {
+        (face.a_texture, face.b_texture, face.c_texture) = (face.a, face.b, face.c);
+    }
+    quad.material.diffuse_texture = Some(Arc::new(Checker {
+        tiles: 2,
+        color_a: white,
+        color_b: Color::default(),
+    }));
+
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let mut pixels = vec![Color::default(); num_pixels];
+    let mut depths = vec![f32::MAX; num_pixels];
+    draw_mesh(
+        &quad,
+        Mat4::identity(),
+        &[test_light()],
+        test_camera(),
+        RenderOptions::default(),
+        &mut pixels,
+        &mut depths,
+    );
+    let lit = pixels[(4 * WIDTH + 4) as usize];
+    assert_ne!(lit, Color::default());
+    assert_eq!(pixels[(12 * WIDTH + 12) as usize], lit);
+    assert_eq!(pixels[(4 * WIDTH + 12) as usize], Color::default());
+    assert_eq!(pixels[(12 * WIDTH + 4) as usize], Color::default());
+    assert!(depths.iter().all(|&depth| depth < f32::MAX));
+}
+
+#[test]
+fn test_vertex_colors() {
+    // the bottom corners and the middle of the top edge of a screen quad, red, green and blue
+    let mut triangle = screen_quad(-1.0);
+    triangle.verticies[2].x = 0.0;
+    triangle.verticies.truncate(3);
+    triangle.face_indicies.truncate(1);
+    triangle.vertex_colors = vec![
+        Color { r: 255, g: 0, b: 0 },
+        Color { r: 0, g: 255, b: 0 },
+        Color { r: 0, g: 0, b: 255 },
+    ];
+
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let mut pixels = vec![Color::default(); num_pixels];
+    let mut depths = vec![f32::MAX; num_pixels];
+    draw_mesh(
+        &triangle,
+        Mat4::identity(),
+        &[test_light()],
+        test_camera(),
+        RenderOptions::default(),
+        &mut pixels,
+        &mut depths,
+    );
+
+    // the centroid sits a third of the way up from the bottom edge and mixes all three evenly
+    let center = pixels[(10 * WIDTH + 8) as usize];
+    let channels = [center.r, center.g, center.b];
+    let (lowest, highest) = (
+        *channels.iter().min().unwrap(),
+        *channels.iter().max().unwrap(),
+    );
+    assert!(lowest > 40, "{center:?}");
+    assert!(highest - lowest < 30, "{center:?}");
+
+    // each corner is dominated by its own color
+    let bottom_left = pixels[(15 * WIDTH + 1) as usize];
+    assert!(bottom_left.r > 2 * bottom_left.g.max(bottom_left.b));
+    let bottom_right = pixels[(15 * WIDTH + 14) as usize];
+    assert!(bottom_right.g > 2 * bottom_right.r.max(bottom_right.b));
+    let top = pixels[(WIDTH + 8) as usize];
+    assert!(top.b > 2 * top.r.max(top.g));
+}
+
+#[test]
+fn test_draw_screen_triangle_and_line() {
+    let camera = test_camera();
+    let options = RenderOptions::default();
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let mut pixels = vec![Color::default(); num_pixels];
+    let mut depths = vec![f32::MAX; num_pixels];
+    let red = Color { r: 255, g: 0, b: 0 };
+    let blue = Color { r: 0, g: 0, b: 255 };
+
+    // a rectangle from (2, 3) to (10, 8) as two triangles wound opposite ways, which shouldn't
+    // matter. The shared diagonal and the right and bottom edges belong to nothing extra.
+    let corner = |x: i32, y: i32| ScreenCoordinate { x, y };
+    let rectangle = |color: Color, depth: Option<f32>, pixels: &mut [Color], depths: &mut [f32]| {
+        for (v0, v1, v2) in [
+            (corner(2, 3), corner(10, 3), corner(10, 8)),
+            (corner(2, 3), corner(2, 8), corner(10, 8)),
+        ] {
+            draw_screen_triangle(v0, v1, v2, color, depth, camera, options, pixels, depths);
+        }
+    };
+    rectangle(red, Some(0.5), &mut pixels, &mut depths);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let idx = (y * WIDTH + x) as usize;
+            if (2..10).contains(&x) && (3..8).contains(&y) {
+                assert_eq!(pixels[idx], red);
+                assert_eq!(depths[idx], 0.5);
+            } else {
+                assert_eq!(pixels[idx], Color::default());
+                assert_eq!(depths[idx], f32::MAX);
+            }
+        }
+    }
+
+    // farther away fails the depth test, no depth at all draws over everything
+    rectangle(blue, Some(0.75), &mut pixels, &mut depths);
+    assert_eq!(pixels[(3 * WIDTH + 2) as usize], red);
+    rectangle(blue, None, &mut pixels, &mut depths);
+    assert_eq!(pixels[(3 * WIDTH + 2) as usize], blue);
+    assert_eq!(depths[(3 * WIDTH + 2) as usize], 0.5);
+
+    // lines include both ends and are clipped to the canvas
+    let mut pixels = vec![Color::default(); num_pixels];
+    draw_screen_line(
+        corner(-4, 5),
+        corner(20, 5),
+        red,
+        None,
+        camera,
+        options,
+        &mut pixels,
+        &mut depths,
+    );
+    draw_screen_line(
+        corner(3, 0),
+        corner(3, 2),
+        blue,
+        None,
+        camera,
+        options,
+        &mut pixels,
+        &mut depths,
+    );
+    for (idx, pixel) in pixels.iter().enumerate() {
+        let (x, y) = ((idx as i32) % WIDTH, (idx as i32) / WIDTH);
+        let expected = match (x, y) {
+            (_, 5) => red,
+            (3, 0..=2) => blue,
+            _ => Color::default(),
+        };
+        assert_eq!(*pixel, expected);
+    }
+}
+
+#[test]
+fn test_texture_filter_option() {
+    let floor = textured_floor();
+    let bilinear = render_mesh(&floor, RenderOptions::default());
+    let nearest = render_mesh(
+        &floor,
+        RenderOptions {
+            texture_filter: Filter::Nearest,
+            ..Default::default()
+        },
+    );
+    assert_ne!(bilinear, nearest);
+    assert_eq!(
+        render_mesh(
+            &floor,
+            RenderOptions {
+                texture_filter: Filter::Bilinear,
+                ..Default::default()
+            },
+        ),
+        bilinear
+    );
+}
+
+#[test]
+fn test_degenerate_triangles_are_skipped() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let at = |x: f32, y: f32| Vector3 { x, y, z: -1.0 };
+    let degenerate = Mesh {
+        verticies: vec![
+            // a line across the screen
+            at(-0.5, -0.5),
+            at(0.0, 0.0),
+            at(0.5, 0.5),
+            // three points inside of the same pixel
+            at(0.01, 0.01),
+            at(0.02, 0.01),
+            at(0.01, 0.02),
+        ],
+        face_indicies: vec![
+            Triangle {
+                a: 0,
+                b: 2,
+                c: 1,
+                ..Default::default()
+            },
+            Triangle {
+                a: 3,
+                b: 5,
+                c: 4,
+                ..Default::default()
+            },
+            // every corner is the same vertex
+            Triangle {
+                a: 1,
+                b: 1,
+                c: 1,
+                ..Default::default()
+            },
+        ],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        ..Default::default()
+    };
+
+    // conservative rasterization is left out, it covers the pixels these touch on purpose
+    for options in [
+        RenderOptions::default(),
+        RenderOptions {
+            edge_antialiasing: true,
+            ..Default::default()
+        },
+    ] {
+        let mut pixel_buffer = vec![Color::default(); num_pixels];
+        let mut depth_buffer = vec![f32::MAX; num_pixels];
+        draw_mesh(
+            &degenerate,
+            Mat4::identity(),
+            &[test_light()],
+            test_camera(),
+            options,
+            &mut pixel_buffer,
+            &mut depth_buffer,
+        );
+        assert!(pixel_buffer.iter().all(|&p| p == Color::default()));
+        assert!(depth_buffer.iter().all(|&depth| depth == f32::MAX));
+    }
+}
+
+#[test]
+fn test_two_sided_lighting() {
+    // a quad facing the camera and light whose normal points away from both
+    let mut backwards = screen_quad(-2.0);
+    for normal in backwards.vertex_normals.iter_mut() {
+        *normal = *normal * -1.0;
+    }
+
+    let one_sided = render_mesh(&backwards, RenderOptions::default());
+    let two_sided = render_mesh(
+        &backwards,
+        RenderOptions {
+            two_sided_lighting: true,
+            ..Default::default()
+        },
+    );
+    let center = ((HEIGHT / 2) * WIDTH + WIDTH / 2) as usize;
+    // only the ambient term lights the back of the quad
+    assert_eq!(one_sided[center].r, (255.0 * 0.2) as u8);
+    assert!(two_sided[center].r > 200);
+
+    // normals already facing the camera are left alone
+    let quad = screen_quad(-2.0);
+    assert_eq!(
+        render_mesh(&quad, RenderOptions::default()),
+        render_mesh(
+            &quad,
+            RenderOptions {
+                two_sided_lighting: true,
+                ..Default::default()
+            },
+        )
+    );
+    assert_eq!(render_mesh(&quad, RenderOptions::default()), two_sided);
+}
+
+#[test]
+fn test_emissive_without_lights() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    let glow = Color {
+        r: 200,
+        g: 100,
+        b: 50,
+    };
+    let mut quad = screen_quad(-2.0);
+    quad.material.emissive_color = glow;
+
+    let mut pixel_buffer = vec![Color::default(); num_pixels];
+    let mut depth_buffer = vec![f32::MAX; num_pixels];
+    draw_mesh(
+        &quad,
+        Mat4::identity(),
+        &[],
+        test_camera(),
+        RenderOptions::default(),
+        &mut pixel_buffer,
+        &mut depth_buffer,
+    );
+    assert!(pixel_buffer.iter().all(|&p| p == glow));
+
+    // emission is added on top of the lit color
+    let dim = Color {
+        r: 100,
+        g: 100,
+        b: 100,
+    };
+    let mut plain = screen_quad(-2.0);
+    plain.material.diffuse_color = dim;
+    quad.material.diffuse_color = dim;
+    let unlit = render_mesh(&plain, RenderOptions::default());
+    let lit = render_mesh(&quad, RenderOptions::default());
+    let center = ((HEIGHT / 2) * WIDTH + WIDTH / 2) as usize;
+    assert!(unlit[center].b < 200);
+    assert!((lit[center].b as i32 - (unlit[center].b + glow.b) as i32).abs() <= 1);
+    assert_eq!(lit[center].r, 255);
+}
+
+// a single triangle facing the camera, verticies given in the same order as screen_quad's faces
+fn facing_triangle(v0: Vector3, v1: Vector3, v2: Vector3) -> Mesh {
+    Mesh {
+        verticies: vec![v0, v1, v2],
+        face_indicies: vec![Triangle {
+            a: 0,
+            b: 1,
+            c: 2,
+            ..Default::default()
+        }],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_clip_triangle_crossing_left_edge() {
+    // at z = -2 x and y are twice NDC, so the first vertex is far off the left of the screen
+    let at = |x: f32, y: f32| Vector3 { x, y, z: -2.0 };
+    let mesh = facing_triangle(at(-6.0, -1.0), at(1.0, 1.0), at(1.0, -1.0));
+    let pixels = render_mesh(&mesh, RenderOptions::default());
+    let covered = |x: i32, y: i32| pixels[(y * WIDTH + x) as usize] != Color::default();
+
+    // in pixels the triangle is (-16, 12), (12, 4), (12, 12), about 75 of them are on screen
+    let count = pixels.iter().filter(|&&p| p != Color::default()).count();
+    assert!((68..=82).contains(&count));
+    assert!(covered(0, 11) && covered(11, 5) && covered(11, 11));
+    assert!(!covered(0, 5) && !covered(0, 12) && !covered(12, 8));
+}
+
+#[test]
+fn test_clip_triangle_crossing_top_left_corner() {
+    // every vertex is off screen, but the triangle still covers the top left corner
+    let at = |x: f32, y: f32| Vector3 { x, y, z: -2.0 };
+    let mesh = facing_triangle(at(-2.2, 0.5), at(-3.0, 3.0), at(0.5, 2.2));
+    let pixels = render_mesh(&mesh, RenderOptions::default());
+    let covered = |x: i32, y: i32| pixels[(y * WIDTH + x) as usize] != Color::default();
+
+    // its one on screen edge runs from about (0, 5) to (8.6, 0) in pixels
+    assert!(covered(0, 0) && covered(7, 0) && covered(1, 4));
+    assert!(!covered(9, 0) && !covered(0, 5) && !covered(8, 8));
+}
+
+#[test]
+fn test_gbuffer() {
+    let num_pixels = (WIDTH * HEIGHT) as usize;
+    // normals lean left on the left edge of the quad and right on the right edge
+    let mut quad = screen_quad(-2.0);
+    quad.vertex_normals = vec![
+        Vector3 {
+            x: -0.6,
+            y: 0.0,
+            z: 0.8,
+        },
+        Vector3 {
+            x: 0.6,
+            y: 0.0,
+            z: 0.8,
+        },
+    ];
+    quad.face_indicies = vec![
+        Triangle {
+            a: 0,
+            b: 2,
+            c: 1,
+            a_normal: 0,
+            b_normal: 1,
+            c_normal: 1,
+            ..Default::default()
+        },
+        Triangle {
+            a: 0,
+            b: 3,
+            c: 2,
+            a_normal: 0,
+            b_normal: 0,
+            c_normal: 1,
+            ..Default::default()
+        },
+    ];
+    quad.material.diffuse_color = Color {
+        r: 10,
+        g: 20,
+        b: 30,
+    };
+
+    let mut pixel_buffer = vec![Color::default(); num_pixels];
+    let mut depth_buffer = vec![f32::MAX; num_pixels];
+    let mut gbuffer = GBuffer::new(WIDTH as usize, HEIGHT as usize);
+    draw_mesh_with_gbuffer(
+        &quad,
+        Mat4::identity(),
+        &[test_light()],
+        test_camera(),
+        RenderOptions::default(),
+        &mut pixel_buffer,
+        &mut depth_buffer,
+        &mut gbuffer,
+    );
+
+    // the quad is 4 units wide and covers the whole view, so the pixel a quarter of the way
+    // across sees a point a quarter of the way from its left edge
+    let idx = ((HEIGHT / 2) * WIDTH + WIDTH / 4) as usize;
+    let position = gbuffer.positions[idx];
+    assert!((position.z + 2.0).abs() < 1.0e-4);
+    assert!((position.x + 1.0).abs() <= 0.25);
+
+    // the normal there is the vertex normals blended by how far across the quad it is
+    let t = (position.x + 2.0) / 4.0;
+    let expected = Vector3 {
+        x: -0.6 + 1.2 * t,
+        y: 0.0,
+        z: 0.8,
+    }
+    .normalized();
+    let normal = gbuffer.normals[idx];
+    assert!((normal - expected).magnitude() < 1.0e-4);
+    assert!(normal.x < 0.0);
+    assert_eq!(gbuffer.albedo[idx], quad.material.diffuse_color);
+
+    // the color and depth match a plain draw_mesh
+    assert_eq!(pixel_buffer, render_mesh(&quad, RenderOptions::default()));
+}
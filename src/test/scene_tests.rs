@@ -0,0 +1,1360 @@
+use super::TempDir;
+use crate::image::Image;
+use crate::math::*;
+use crate::mesh::*;
+use crate::rasterizer::RenderOptions;
+use crate::scene::*;
+use std::fs;
+
+fn triangle_mesh() -> Mesh {
+    Mesh {
+        verticies: vec![
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 0.0,
+                y: 2.0,
+                z: -1.0,
+            },
+        ],
+        face_indicies: vec![Triangle {
+            a: 0,
+            b: 2,
+            c: 1,
+            ..Default::default()
+        }],
+        vertex_normals: vec![Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_scene_bounding_box() {
+    let mut scene = Scene::default();
+    assert_eq!(scene.bounding_box(), Aabb::default());
+
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::identity(),
+        ..Default::default()
+    });
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::translation(-3.0, 0.0, 5.0),
+        ..Default::default()
+    });
+
+    let bounding_box = scene.bounding_box();
+    assert_eq!(
+        bounding_box.min,
+        Vector3 {
+            x: -3.0,
+            y: 0.0,
+            z: -1.0
+        }
+    );
+    assert_eq!(
+        bounding_box.max,
+        Vector3 {
+            x: 1.0,
+            y: 2.0,
+            z: 5.0
+        }
+    );
+    assert_eq!(
+        bounding_box.center(),
+        Vector3 {
+            x: -1.0,
+            y: 1.0,
+            z: 2.0
+        }
+    );
+}
+
+#[test]
+fn test_threaded_render_matches_single_threaded() {
+    let mut scene = Scene {
+        camera: Camera::new(13, 11, 90_f32.to_radians(), 0.1, 100.0),
+        lights: vec![Light {
+            position: Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            color: Color {
+                r: 255,
+                g: 200,
+                b: 100,
+            }
+            .to_vector3(),
+            ambient_strength: 0.1,
+        }],
+        ..Default::default()
+    };
+    for offset in [-1.0, 0.0, 0.5] {
+        scene.models.push(Model {
+            mesh: triangle_mesh(),
+            transform: Mat4::translation(offset, -1.0, -3.0 + offset),
+            ..Default::default()
+        });
+    }
+
+    let render_with_threads = |threads: usize| {
+        let mut pixels = vec![Color::default(); 13 * 11];
+        let mut depths = vec![f32::MAX; 13 * 11];
+        let options = RenderOptions {
+            threads,
+            ..Default::default()
+        };
+        scene.render(options, &mut pixels, &mut depths);
+        (pixels, depths)
+    };
+
+    let single_threaded = render_with_threads(1);
+    assert!(single_threaded
+        .0
+        .iter()
+        .any(|&pixel| pixel != Color::default()));
+    for threads in [2, 3, 4, 16] {
+        assert_eq!(render_with_threads(threads), single_threaded);
+    }
+}
+
+#[test]
+fn test_render_scissor_region() {
+    let (width, height) = (160, 140);
+    let mut scene = Scene {
+        camera: Camera::new(width, height, 90_f32.to_radians(), 0.1, 100.0),
+        lights: vec![Light {
+            position: Vector3::ORIGIN,
+            color: Color {
+                r: 255,
+                g: 255,
+                b: 255,
+            }
+            .to_vector3(),
+            ambient_strength: 0.5,
+        }],
+        ..Default::default()
+    };
+    // a triangle spilling past the left and bottom of the region, but still inside the view
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::translation(-3.2, -2.8, -3.0) * Mat4::scale(6.2, 3.1, 1.0),
+        ..Default::default()
+    });
+
+    let num_pixels = (width * height) as usize;
+    let full_frame = {
+        let mut pixels = vec![Color::default(); num_pixels];
+        let mut depths = vec![f32::MAX; num_pixels];
+        scene.render(RenderOptions::default(), &mut pixels, &mut depths);
+        pixels
+    };
+    let drawn = |x: i32, y: i32| full_frame[(y * width + x) as usize] != Color::default();
+    assert!(drawn(50, 100) && drawn(20, 100) && drawn(50, 130));
+
+    for threads in [1, 4] {
+        let options = RenderOptions {
+            threads,
+            ..Default::default()
+        }
+        .with_scissor_rect(30, 20, 100, 100);
+        assert_eq!(options.scissor, Some((30, 20, 130, 120)));
+
+        let mut pixels = vec![Color::default(); num_pixels];
+        let mut depths = vec![f32::MAX; num_pixels];
+        scene.render(options, &mut pixels, &mut depths);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = (y * width + x) as usize;
+                if (30..130).contains(&x) && (20..120).contains(&y) {
+                    assert_eq!(pixels[idx], full_frame[idx]);
+                } else {
+                    assert_eq!(pixels[idx], Color::default());
+                    assert_eq!(depths[idx], f32::MAX);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_render_model() {
+    let (width, height) = (100, 80);
+    let mut scene = Scene {
+        camera: Camera::new(width, height, 90_f32.to_radians(), 0.1, 100.0),
+        lights: vec![Light {
+            position: Vector3::ORIGIN,
+            color: Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            ambient_strength: 0.5,
+        }],
+        ..Default::default()
+    };
+    // one triangle on each side of the view
+    for x in [-2.5, 1.0] {
+        scene.models.push(Model {
+            mesh: triangle_mesh(),
+            transform: Mat4::translation(x, -1.0, -3.0) * Mat4::scale(1.5, 1.0, 1.0),
+            ..Default::default()
+        });
+    }
+
+    let num_pixels = (width * height) as usize;
+    let mut full_frame = vec![Color::default(); num_pixels];
+    let mut full_depths = vec![f32::MAX; num_pixels];
+    scene.render(RenderOptions::default(), &mut full_frame, &mut full_depths);
+    let mut pixels = vec![Color::default(); num_pixels];
+    let mut depths = vec![f32::MAX; num_pixels];
+    scene.render_model(0, RenderOptions::default(), &mut pixels, &mut depths);
+
+    let (mut left, mut right) = (0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            let idx = (y * width + x) as usize;
+            if full_frame[idx] == Color::default() {
+                continue;
+            }
+            // the first model's pixels are untouched and the second's are gone
+            if x < width / 2 {
+                left += 1;
+                assert_eq!(pixels[idx], full_frame[idx]);
+                assert_eq!(depths[idx], full_depths[idx]);
+            } else {
+                right += 1;
+                assert_eq!(pixels[idx], Color::default());
+                assert_eq!(depths[idx], f32::MAX);
+            }
+        }
+    }
+    assert!(left > 0 && right > 0);
+}
+
+#[test]
+fn test_render_progress() {
+    let mut scene = Scene {
+        camera: Camera::new(70, 40, 90_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    for offset in [-1.0, 0.0, 0.5] {
+        scene.models.push(Model {
+            mesh: triangle_mesh(),
+            transform: Mat4::translation(offset, -1.0, -3.0),
+            ..Default::default()
+        });
+    }
+
+    for threads in [1, 3] {
+        let mut pixels = vec![Color::default(); 70 * 40];
+        let mut depths = vec![f32::MAX; 70 * 40];
+        let options = RenderOptions {
+            threads,
+            ..Default::default()
+        };
+        let mut reports: Vec<f32> = Vec::new();
+        scene.render_with_progress(options, &mut pixels, &mut depths, |fraction| {
+            reports.push(fraction)
+        });
+
+        // one report per model or per tile (3x2 of them), each at least as far as the last
+        assert!(reports.len() >= 3);
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(reports
+            .iter()
+            .all(|&fraction| (0.0..=1.0).contains(&fraction)));
+        // the frame is only done once, at the very end
+        assert_eq!(
+            reports.iter().filter(|&&fraction| fraction == 1.0).count(),
+            1
+        );
+        assert_eq!(reports.last(), Some(&1.0));
+    }
+}
+
+#[test]
+fn test_depth_prepass() {
+    // the same triangle stacked five deep and listed farthest first, so without a prepass every
+    // layer is shaded and then drawn over by the next
+    let mut scene = Scene {
+        camera: Camera::new(70, 40, 90_f32.to_radians(), 0.1, 100.0),
+        lights: vec![Light {
+            position: Vector3::ORIGIN,
+            color: Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            ambient_strength: 0.1,
+        }],
+        ..Default::default()
+    };
+    for layer in 0..5 {
+        scene.models.push(Model {
+            mesh: triangle_mesh(),
+            transform: Mat4::translation(0.0, -1.0, -3.0 + layer as f32 * 0.2),
+            ..Default::default()
+        });
+    }
+
+    for threads in [1, 3] {
+        let render = |depth_prepass: bool| {
+            let mut pixels = vec![Color::default(); 70 * 40];
+            let mut depths = vec![f32::MAX; 70 * 40];
+            let options = RenderOptions {
+                threads,
+                depth_prepass,
+                ..Default::default()
+            };
+            let stats = scene.render(options, &mut pixels, &mut depths);
+            (pixels, depths, stats)
+        };
+        let (pixels, depths, stats) = render(false);
+        let (prepass_pixels, prepass_depths, prepass_stats) = render(true);
+        assert_eq!(pixels, prepass_pixels);
+        assert_eq!(depths, prepass_depths);
+
+        // only the nearest layer is shaded, rather than every layer (the farther ones are smaller)
+        let covered = depths.iter().filter(|&&depth| depth != f32::MAX).count();
+        assert!(covered > 0);
+        assert_eq!(prepass_stats.pixels_shaded, covered);
+        assert!(stats.pixels_shaded > 3 * covered);
+    }
+
+    // two models sharing the same triangle, where the first one drawn keeps every pixel and the
+    // second one at the same depth is never shaded, with or without the prepass
+    let red = Color { r: 255, g: 0, b: 0 };
+    let green = Color { r: 0, g: 255, b: 0 };
+    scene.models = [red, green]
+        .into_iter()
+        .map(|color| Model {
+            mesh: triangle_mesh(),
+            transform: Mat4::translation(0.0, -1.0, -3.0),
+            color_override: Some(color),
+        })
+        .collect();
+    for threads in [1, 4] {
+        let render = |depth_prepass: bool| {
+            let mut pixels = vec![Color::default(); 70 * 40];
+            let mut depths = vec![f32::MAX; 70 * 40];
+            let options = RenderOptions {
+                threads,
+                depth_prepass,
+                ..Default::default()
+            };
+            let stats = scene.render(options, &mut pixels, &mut depths);
+            (pixels, stats)
+        };
+        let (pixels, stats) = render(false);
+        let (prepass_pixels, prepass_stats) = render(true);
+        assert_eq!(pixels, prepass_pixels);
+
+        let covered = pixels.iter().filter(|&&pixel| pixel == red).count();
+        assert!(covered > 0);
+        assert!(!pixels.contains(&green));
+        assert_eq!(stats.pixels_shaded, covered);
+        assert_eq!(prepass_stats.pixels_shaded, covered);
+    }
+}
+
+#[test]
+fn test_load_from_reader() {
+    let scene_xml = "<scene>\n<camera>\n<projection>32 16 1.0 0.5 50</projection>\n<position>0 0 0</position>\n<lookat>0 0 -1</lookat>\n<up>0 1 0</up>\n</camera>\n<light>\n<strength>0.1</strength>\n<position>0 5 0</position>\n<color>255 255 255</color>\n</light>\n</scene>\n";
+
+    let maybe_scene = Scene::load_from_reader(scene_xml.as_bytes());
+    assert!(maybe_scene.is_ok());
+    // a camera looking straight down its own up vector can't be built
+    let degenerate = scene_xml.replace("<lookat>0 0 -1</lookat>", "<lookat>0 1 0</lookat>");
+    assert!(Scene::load_from_reader(degenerate.as_bytes()).is_err());
+    let scene = maybe_scene.unwrap();
+    assert_eq!(scene.camera.canvas_width, 32);
+    assert_eq!(scene.camera.canvas_height, 16);
+    assert_eq!(scene.lights.len(), 1);
+    assert!(scene.models.is_empty());
+}
+
+// the xml of a scene with a small camera looking down -z, followed by xml
+fn scene_with(xml: &str) -> String {
+    format!(
+        "<scene><camera><projection>32 16 1.0 0.5 50</projection><position>0 0 0</position><lookat>0 0 -1</lookat><up>0 1 0</up></camera>{}</scene>",
+        xml
+    )
+}
+
+#[test]
+fn test_light_intensity() {
+    let light_color = |color_xml: &str| {
+        Scene::load_from_str(&scene_with(&format!(
+            "<light><strength>0.1</strength><position>0 5 0</position>{}</light>",
+            color_xml
+        )))
+        .map(|scene| scene.lights[0].color)
+    };
+    let overbright = Vector3 {
+        x: 2.0,
+        y: 2.0,
+        z: 2.0,
+    };
+
+    // intensities are taken as is, color tags are still out of 255 but may go past it
+    assert_eq!(
+        light_color("<intensity>2 2 2</intensity>").unwrap(),
+        overbright
+    );
+    assert_eq!(
+        light_color("<color>510 510 510</color>").unwrap(),
+        overbright
+    );
+    let white = light_color("<color>255 255 255</color>").unwrap();
+    assert!(
+        (white
+            - Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0
+            })
+        .magnitude()
+            < 1.0e-6
+    );
+
+    assert_eq!(
+        light_color("<color>#ff8800</color>").unwrap(),
+        Color {
+            r: 255,
+            g: 136,
+            b: 0,
+        }
+        .to_vector3()
+    );
+    assert!(light_color("<intensity>#ff8800</intensity>").is_err());
+    assert!(light_color("<intensity>1 -1 1</intensity>").is_err());
+    assert!(light_color("<intensity>1 1</intensity>").is_err());
+    assert!(light_color("<color>1 1 1</color><intensity>1 1 1</intensity>").is_err());
+}
+
+#[test]
+fn test_scene_ambient() {
+    let scene_with_ambient = |ambient_xml: &str| Scene::load_from_str(&scene_with(ambient_xml));
+    let brightest_with = |ambient_xml: &str, options: RenderOptions| {
+        let mut scene = scene_with_ambient(ambient_xml).unwrap();
+        scene.camera = Camera::new(70, 40, 90_f32.to_radians(), 0.1, 100.0);
+        scene.models.push(Model {
+            mesh: triangle_mesh(),
+            transform: Mat4::translation(0.0, -1.0, -3.0),
+            ..Default::default()
+        });
+        let mut pixels = vec![Color::default(); 70 * 40];
+        let mut depths = vec![f32::MAX; 70 * 40];
+        scene.render(options, &mut pixels, &mut depths);
+        pixels.iter().map(|pixel| pixel.r).max().unwrap()
+    };
+    let brightest = |ambient_xml: &str| brightest_with(ambient_xml, RenderOptions::default());
+
+    // with no lights at all the white triangle is only lit by the scene's ambient
+    assert_eq!(brightest(""), 0);
+    let faint = brightest("<ambient>40 40 40</ambient>");
+    assert!(faint > 30 && faint <= 40);
+
+    // an ambient passed in the options is kept over the scene's
+    let options = RenderOptions {
+        ambient: Some(
+            Color {
+                r: 80,
+                g: 80,
+                b: 80,
+            }
+            .to_vector3(),
+        ),
+        ..Default::default()
+    };
+    let brighter = brightest_with("<ambient>40 40 40</ambient>", options);
+    assert!(brighter > 70 && brighter <= 80);
+    // even when it's no ambient at all
+    let options = RenderOptions {
+        ambient: Some(Vector3::default()),
+        ..Default::default()
+    };
+    assert_eq!(brightest_with("<ambient>40 40 40</ambient>", options), 0);
+    assert_eq!(
+        scene_with_ambient("<ambient>#ff8800</ambient>")
+            .unwrap()
+            .ambient,
+        Color {
+            r: 255,
+            g: 136,
+            b: 0,
+        }
+        .to_vector3()
+    );
+    assert!(scene_with_ambient("<ambient>1 1</ambient>").is_err());
+    assert!(scene_with_ambient("<ambient>1 1 1</ambient><ambient>1 1 1</ambient>").is_err());
+}
+
+#[test]
+fn test_model_missing_mesh() {
+    let scene_xml =
+        scene_with("<model><rotation>0 0 0</rotation><position>0 0 -2</position></model>");
+    let maybe_scene = Scene::load_from_reader(scene_xml.as_bytes());
+    assert!(maybe_scene.is_err());
+    assert_eq!(
+        maybe_scene.err().unwrap().to_string(),
+        "Failed Scene Loading with error model tag did not contain a mesh value"
+    );
+}
+
+#[test]
+fn test_model_numeric_mesh_path() {
+    let scene_xml = scene_with(
+        "<model><mesh>42</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+    );
+    let maybe_scene = Scene::load_from_reader(scene_xml.as_bytes());
+    assert!(maybe_scene.is_err());
+    assert_eq!(
+        maybe_scene.err().unwrap().to_string(),
+        "Failed Scene Loading with error mesh tag path was not a quoted file name"
+    );
+}
+
+#[test]
+fn test_load_from_str() {
+    let dir = TempDir::new("load_from_str");
+    let obj_path = dir.join("triangle.obj");
+    fs::write(&obj_path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n").unwrap();
+
+    let scene_xml = scene_with(&format!(
+        "<model><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+        obj_path.display()
+    ));
+    let maybe_scene = Scene::load_from_str(&scene_xml);
+    assert!(maybe_scene.is_ok());
+    let scene = maybe_scene.unwrap();
+    assert_eq!(scene.camera.canvas_width, 32);
+    assert_eq!(scene.models.len(), 1);
+    assert_eq!(scene.models[0].mesh.verticies.len(), 3);
+    assert_eq!(scene.models[0].mesh.face_indicies.len(), 1);
+}
+
+#[test]
+fn test_scene_summary() {
+    let dir = TempDir::new("scene_summary");
+    Image::new(2, 2)
+        .save_to_png(&dir.join("texture.png"))
+        .unwrap();
+    fs::write(
+        dir.join("textured.mtl"),
+        "newmtl textured\nmap_Kd texture.png\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("textured.obj"),
+        "mtllib textured.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("quad.obj"),
+        "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 3 2\nf 1 4 3\n",
+    )
+    .unwrap();
+    let scene_path = dir.join("scene.xml");
+    fs::write(
+        &scene_path,
+        scene_with(
+            "<model><mesh>\"textured.obj\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model><model><mesh>\"quad.obj\"</mesh><rotation>0 0 0</rotation><position>0 0 -3</position></model>",
+        ),
+    )
+    .unwrap();
+
+    let summary = Scene::load_from_file(scene_path.to_str().unwrap())
+        .unwrap()
+        .summary();
+    assert_eq!(
+        summary,
+        SceneSummary {
+            models: 2,
+            textured_models: 1,
+            verticies: 7,
+            triangles: 3,
+            lights: 0,
+            canvas_width: 32,
+            canvas_height: 16,
+        }
+    );
+    assert_eq!(
+        summary.to_string(),
+        "models: 2 (1 textured)\nverticies: 7\ntriangles: 3\nlights: 0\nresolution: 32x16"
+    );
+}
+
+#[test]
+fn test_load_from_file_resolves_relative_paths() {
+    let dir = TempDir::new("load_from_file_resolves_relative_paths");
+    let scene_dir = dir.join("scenes");
+    let mesh_dir = scene_dir.join("meshes");
+    fs::create_dir_all(&mesh_dir).unwrap();
+
+    let mut texture = Image::new(1, 1);
+    texture.data[0] = Color { r: 1, g: 2, b: 3 };
+    texture.save_to_png(&mesh_dir.join("texture.png")).unwrap();
+    fs::write(
+        mesh_dir.join("triangle.mtl"),
+        "newmtl textured\nmap_Kd texture.png\n",
+    )
+    .unwrap();
+    fs::write(
+        mesh_dir.join("triangle.obj"),
+        "mtllib triangle.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n",
+    )
+    .unwrap();
+    let scene_path = scene_dir.join("scene.xml");
+    fs::write(
+        &scene_path,
+        scene_with(
+            "<model><mesh>\"meshes/triangle.obj\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+        ),
+    )
+    .unwrap();
+
+    // the working directory is not the scene directory, so this only works if paths are resolved
+    let maybe_scene = Scene::load_from_file(scene_path.to_str().unwrap());
+    assert!(maybe_scene.is_ok());
+    let scene = maybe_scene.unwrap();
+    assert_eq!(scene.models.len(), 1);
+    assert_eq!(scene.models[0].mesh.verticies.len(), 3);
+    assert_eq!(
+        scene.models[0]
+            .mesh
+            .material
+            .diffuse_texture
+            .as_ref()
+            .map(|texture| texture.to_image()),
+        Some(texture)
+    );
+}
+
+#[test]
+fn test_model_color() {
+    let dir = TempDir::new("model_color");
+    let obj_path = dir.join("triangle.obj");
+    fs::write(&obj_path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 3 2\n").unwrap();
+
+    // the color tag may come before the mesh is loaded
+    let scene_xml = scene_with(&format!(
+        "<model><color>10 20 30</color><emissive>1 2 3</emissive><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model><model><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+        obj_path.display(),
+        obj_path.display()
+    ));
+    let maybe_scene = Scene::load_from_str(&scene_xml);
+    assert!(maybe_scene.is_ok());
+    let scene = maybe_scene.unwrap();
+    assert_eq!(
+        scene.models[0].mesh.material.diffuse_color,
+        Color {
+            r: 10,
+            g: 20,
+            b: 30,
+        }
+    );
+    assert_eq!(
+        scene.models[0].mesh.material.emissive_color,
+        Color { r: 1, g: 2, b: 3 }
+    );
+    // and defaults to white without any emission
+    assert_eq!(
+        scene.models[1].mesh.material.diffuse_color,
+        Color {
+            r: 255,
+            g: 255,
+            b: 255,
+        }
+    );
+    assert_eq!(
+        scene.models[1].mesh.material.emissive_color,
+        Color::default()
+    );
+
+    let scene_xml = scene_with(&format!(
+        "<model><color>10 20 300</color><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+        obj_path.display()
+    ));
+    assert!(Scene::load_from_str(&scene_xml).is_err());
+
+    // colors can be written as hex too, bare or quoted
+    let model_colors = |color: &str, emissive: &str| {
+        let scene_xml = scene_with(&format!(
+            "<model><color>{}</color><emissive>{}</emissive><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+            color,
+            emissive,
+            obj_path.display()
+        ));
+        Scene::load_from_str(&scene_xml).map(|scene| {
+            let material = &scene.models[0].mesh.material;
+            (material.diffuse_color, material.emissive_color)
+        })
+    };
+    assert_eq!(
+        model_colors("#ff8800", "\"#0A0B0C\"").unwrap(),
+        (
+            Color {
+                r: 255,
+                g: 136,
+                b: 0,
+            },
+            Color {
+                r: 10,
+                g: 11,
+                b: 12,
+            }
+        )
+    );
+    assert!(model_colors("#ff880", "1 2 3").is_err());
+    assert!(model_colors("#ff8800 #ff8800", "1 2 3").is_err());
+    assert!(model_colors("\"ff8800\"", "1 2 3").is_err());
+    assert!(model_colors("#ff8800", "7").is_err());
+}
+
+#[test]
+fn test_model_color_override() {
+    let dir = TempDir::new("model_color_override");
+    let obj_path = dir.join("triangle.obj");
+    fs::write(&obj_path, "v -1 -1 0\nv 1 -1 0\nv 0 1 0\nf 1 3 2\n").unwrap();
+
+    let scene_xml = scene_with(&format!(
+        "<model><color>10 20 30</color><override_color>#ff8800</override_color><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model><model><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+        obj_path.display(),
+        obj_path.display()
+    ));
+    let mut scene = Scene::load_from_str(&scene_xml).unwrap();
+    let orange = Color {
+        r: 255,
+        g: 136,
+        b: 0,
+    };
+    assert_eq!(scene.models[0].color_override, Some(orange));
+    assert_eq!(scene.models[1].color_override, None);
+
+    let duplicate_xml = scene_with(&format!(
+        "<model><override_color>1 2 3</override_color><override_color>1 2 3</override_color><mesh>\"{}\"</mesh><rotation>0 0 0</rotation><position>0 0 -2</position></model>",
+        obj_path.display()
+    ));
+    assert!(Scene::load_from_str(&duplicate_xml).is_err());
+
+    // only the overridden model, lit and looking straight at it, fills its pixels with exactly the
+    // override color whether it's drawn on one thread or in tiles
+    scene.models.truncate(1);
+    scene.camera = Camera::new(16, 16, 90_f32.to_radians(), 0.1, 100.0);
+    scene.lights = vec![Light {
+        position: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 5.0,
+        },
+        color: Vector3 {
+            x: 0.7,
+            y: 0.2,
+            z: 0.4,
+        },
+        ambient_strength: 0.1,
+    }];
+    for threads in [1, 4] {
+        let options = RenderOptions {
+            threads,
+            ..Default::default()
+        };
+        let mut pixels = vec![Color::default(); 16 * 16];
+        let mut depths = vec![f32::MAX; 16 * 16];
+        scene.render(options, &mut pixels, &mut depths);
+        let covered = pixels.iter().filter(|&&pixel| pixel == orange).count();
+        assert!(covered > 16);
+        assert_eq!(
+            covered,
+            depths.iter().filter(|&&depth| depth != f32::MAX).count()
+        );
+        assert!(pixels
+            .iter()
+            .all(|&pixel| pixel == orange || pixel == Color::default()));
+    }
+
+    // without the override the same model is lit
+    scene.models[0].color_override = None;
+    let mut pixels = vec![Color::default(); 16 * 16];
+    let mut depths = vec![f32::MAX; 16 * 16];
+    scene.render(RenderOptions::default(), &mut pixels, &mut depths);
+    assert!(pixels.iter().all(|&pixel| pixel != orange));
+}
+
+#[test]
+fn test_camera_frame() {
+    let mut scene = Scene {
+        camera: Camera::new(32, 16, 60_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::translation(5.0, -3.0, 8.0) * Mat4::scale(4.0, 1.0, 2.0),
+        ..Default::default()
+    });
+    scene.camera.view_mat = Mat4::euler_angles(0.3, -0.2, 0.5) * Mat4::translation(1.0, 2.0, 3.0);
+
+    let aabb = scene.bounding_box();
+    scene.camera.frame(&aabb);
+
+    for corner in aabb.corners() {
+        let ndc = scene.camera.projection_mat * scene.camera.view_mat * corner;
+        assert!(ndc.x >= -1.0 && ndc.x <= 1.0);
+        assert!(ndc.y >= -1.0 && ndc.y <= 1.0);
+        assert!(ndc.z >= -1.0 && ndc.z <= 1.0);
+    }
+
+    // the box is centered in view
+    let center = scene.camera.projection_mat * scene.camera.view_mat * aabb.center();
+    assert!(center.x.abs() < 1.0e-4);
+    assert!(center.y.abs() < 1.0e-4);
+}
+
+#[test]
+fn test_camera_frame_scene() {
+    let mut scene = Scene {
+        camera: Camera::new(40, 30, 50_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    for transform in [
+        Mat4::translation(-6.0, 2.0, 9.0) * Mat4::scale(3.0, 1.0, 2.0),
+        Mat4::translation(4.0, -1.0, 15.0),
+    ] {
+        scene.models.push(Model {
+            mesh: triangle_mesh(),
+            transform,
+            ..Default::default()
+        });
+    }
+    // looking away from everything
+    scene.camera.view_mat = Mat4::translation(0.0, 0.0, -50.0);
+
+    let mut camera = scene.camera;
+    camera.frame_scene(&scene);
+    let aabb = scene.bounding_box();
+    for corner in aabb.corners() {
+        let ndc = camera.projection_mat * camera.view_mat * corner;
+        assert!(ndc.x >= -1.0 && ndc.x <= 1.0);
+        assert!(ndc.y >= -1.0 && ndc.y <= 1.0);
+        assert!(ndc.z >= -1.0 && ndc.z <= 1.0);
+    }
+    let center = camera.projection_mat * camera.view_mat * aabb.center();
+    assert!(center.x.abs() < 1.0e-4);
+    assert!(center.y.abs() < 1.0e-4);
+
+    // and both models end up on screen
+    scene.camera = camera;
+    assert_eq!(scene.visible_models().count(), 2);
+}
+
+#[test]
+fn test_camera_set_fov_and_aspect() {
+    let mut camera = Camera::new(32, 16, 60_f32.to_radians(), 0.1, 100.0);
+    camera.view_mat = Mat4::euler_angles(0.3, -0.2, 0.5) * Mat4::translation(1.0, 2.0, 3.0);
+    let view_mat = camera.view_mat;
+    assert!((camera.fov() - 60_f32.to_radians()).abs() < 1.0e-5);
+
+    camera.set_fov(90_f32.to_radians());
+    assert_eq!(camera.view_mat, view_mat);
+    let expected = Camera::new(32, 16, 90_f32.to_radians(), 0.1, 100.0).projection_mat;
+    assert_eq!(camera.projection_mat, expected);
+
+    camera.set_aspect(16, 16);
+    assert_eq!(camera.view_mat, view_mat);
+    assert_eq!((camera.canvas_width, camera.canvas_height), (16, 16));
+    let expected = Camera::new(16, 16, 90_f32.to_radians(), 0.1, 100.0).projection_mat;
+    for (actual, expected) in camera.projection_mat.data.iter().zip(expected.data) {
+        assert!((actual - expected).abs() < 1.0e-5);
+    }
+
+    // an infinite far plane stays infinite
+    let mut camera = Camera::new(32, 16, 60_f32.to_radians(), 0.1, f32::INFINITY);
+    camera.set_fov(45_f32.to_radians());
+    let expected = Camera::new(32, 16, 45_f32.to_radians(), 0.1, f32::INFINITY).projection_mat;
+    assert_eq!(camera.projection_mat, expected);
+}
+
+#[test]
+fn test_transparent_draw_order() {
+    // a camera facing quad at depth z, far bigger than the view
+    let quad = |z: f32, color: Color, opacity: f32| {
+        let corner = |x: f32, y: f32| Vector3 { x, y, z };
+        Model {
+            mesh: Mesh {
+                verticies: vec![
+                    corner(-10.0, -10.0),
+                    corner(10.0, -10.0),
+                    corner(10.0, 10.0),
+                    corner(-10.0, 10.0),
+                ],
+                face_indicies: vec![
+                    Triangle {
+                        a: 0,
+                        b: 2,
+                        c: 1,
+                        ..Default::default()
+                    },
+                    Triangle {
+                        a: 0,
+                        b: 3,
+                        c: 2,
+                        ..Default::default()
+                    },
+                ],
+                vertex_normals: vec![Vector3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                }],
+                material: Material {
+                    diffuse_color: color,
+                    opacity,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            transform: Mat4::identity(),
+            ..Default::default()
+        }
+    };
+    let red = Color { r: 255, g: 0, b: 0 };
+    let green = Color { r: 0, g: 255, b: 0 };
+    let blue = Color { r: 0, g: 0, b: 255 };
+    let light = Light {
+        position: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 10.0,
+        },
+        color: Vector3 {
+            x: 0.8,
+            y: 0.8,
+            z: 0.8,
+        },
+        ambient_strength: 0.2,
+    };
+    let render = |models: Vec<Model>, options: RenderOptions| {
+        let scene = Scene {
+            camera: Camera::new(8, 8, 90_f32.to_radians(), 0.1, 100.0),
+            models,
+            lights: vec![light],
+            ..Default::default()
+        };
+        let mut pixels = vec![Color::default(); 64];
+        let mut depths = vec![f32::MAX; 64];
+        scene.render(options, &mut pixels, &mut depths);
+        pixels
+    };
+
+    // the lit color of each quad on its own
+    let alone = |z: f32, color: Color| render(vec![quad(z, color, 1.0)], RenderOptions::default());
+    let (near, middle, back) = (alone(-2.0, red), alone(-4.0, blue), alone(-6.0, green));
+
+    // listed nearest first with the opaque backdrop last, the worst order for blending
+    let models = || {
+        vec![
+            quad(-2.0, red, 0.5),
+            quad(-4.0, blue, 0.5),
+            quad(-6.0, green, 1.0),
+        ]
+    };
+    for threads in [1, 4] {
+        let options = RenderOptions {
+            threads,
+            depth_sort_transparent: true,
+            ..Default::default()
+        };
+        let pixels = render(models(), options);
+        for i in 0..pixels.len() {
+            assert_eq!(pixels[i], back[i].blend(middle[i], 0.5).blend(near[i], 0.5));
+        }
+
+        // without sorting the opaque backdrop still goes first, but the others keep their order
+        let options = RenderOptions {
+            depth_sort_transparent: false,
+            ..options
+        };
+        let pixels = render(models(), options);
+        for i in 0..pixels.len() {
+            assert_eq!(pixels[i], back[i].blend(near[i], 0.5).blend(middle[i], 0.5));
+        }
+    }
+}
+
+#[test]
+fn test_camera_jittered() {
+    let camera = Camera::new(16, 8, 90_f32.to_radians(), 0.1, 100.0);
+    let jittered = camera.jittered(0.5, -0.25);
+    assert_eq!(jittered.view_mat, camera.view_mat);
+    assert!((jittered.fov() - camera.fov()).abs() < 1.0e-6);
+
+    // half a pixel right and a quarter pixel up is the same shift in NDC at any depth
+    for z in [-0.5, -3.0, -40.0] {
+        let point = Vector3 { x: 0.3, y: -0.2, z };
+        let shift = jittered.projection_mat * point - camera.projection_mat * point;
+        assert!((shift.x - 2.0 * 0.5 / 16.0).abs() < 1.0e-5);
+        assert!((shift.y - 2.0 * 0.25 / 8.0).abs() < 1.0e-5);
+        assert!(shift.z.abs() < 1.0e-5);
+    }
+}
+
+#[test]
+fn test_accumulate() {
+    // a flat white triangle, emissive with no lights so every covered pixel is exactly white. At
+    // z = -1 with a 90 degree fov its x and y are its NDC position.
+    let corner = |x: f32, y: f32| Vector3 { x, y, z: -1.0 };
+    let (v0, v1, v2) = (corner(-0.8, -0.8), corner(-0.3, 0.9), corner(0.7, -0.6));
+    let scene = Scene {
+        camera: Camera::new(16, 16, 90_f32.to_radians(), 0.1, 100.0),
+        models: vec![Model {
+            mesh: Mesh {
+                verticies: vec![v0, v1, v2],
+                face_indicies: vec![Triangle {
+                    a: 0,
+                    b: 1,
+                    c: 2,
+                    ..Default::default()
+                }],
+                vertex_normals: vec![Vector3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                }],
+                material: Material {
+                    emissive_color: Color {
+                        r: 255,
+                        g: 255,
+                        b: 255,
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            transform: Mat4::identity(),
+            ..Default::default()
+        }],
+        lights: Vec::new(),
+        ..Default::default()
+    };
+    let pixel_area = Vector3::cross(v1 - v0, v2 - v0).magnitude() * 0.5 * 8.0 * 8.0;
+    // a single sample is a plain render looking through the first jitter
+    let mut accumulator = Accumulator::for_camera(scene.camera);
+    assert_eq!((accumulator.width, accumulator.height), (16, 16));
+    let (jitter_x, jitter_y) = accumulator.next_jitter();
+    scene.accumulate(RenderOptions::default(), &mut accumulator);
+    assert_eq!(accumulator.sample_count, 1);
+    let mut expected = Image::new(16, 16);
+    let mut depths = vec![f32::MAX; 16 * 16];
+    Scene {
+        camera: scene.camera.jittered(jitter_x, jitter_y),
+        ..scene.clone()
+    }
+    .render(RenderOptions::default(), &mut expected.data, &mut depths);
+    assert_eq!(accumulator.resolve(), expected);
+
+    // every sample lands somewhere new inside of the pixel
+    let mut jitters = vec![(jitter_x, jitter_y)];
+    for _ in 1..64 {
+        let jitter = accumulator.next_jitter();
+        assert!(jitter.0.abs() <= 0.5 && jitter.1.abs() <= 0.5);
+        assert!(!jitters.contains(&jitter));
+        jitters.push(jitter);
+        scene.accumulate(RenderOptions::default(), &mut accumulator);
+    }
+    assert_eq!(accumulator.sample_count, 64);
+
+    // the edges come out anti-aliased, with the covered area close to the triangle's. Verticies
+    // still snap to whole pixels on every sample, which skews the average slightly.
+    let resolved = accumulator.resolve();
+    let is_gray = |pixel: &Color| pixel.r > 0 && pixel.r < 255;
+    assert!(resolved.data.iter().filter(|pixel| is_gray(pixel)).count() > 8);
+    assert_eq!(resolved.data[8 * 16 + 7].r, 255);
+    assert_eq!(resolved.data[0].r, 0);
+    let coverage: f32 = resolved
+        .data
+        .iter()
+        .map(|pixel| pixel.r as f32 / 255.0)
+        .sum();
+    assert!((coverage - pixel_area).abs() < 0.05 * pixel_area);
+
+    accumulator.reset();
+    assert_eq!(accumulator.sample_count, 0);
+    assert_eq!(accumulator.next_jitter(), (jitter_x, jitter_y));
+    assert_eq!(accumulator.resolve(), Image::new(16, 16));
+}
+
+#[test]
+#[should_panic]
+fn test_accumulate_size_mismatch() {
+    let scene = Scene {
+        camera: Camera::new(64, 64, 90_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    scene.accumulate(RenderOptions::default(), &mut Accumulator::new(16, 16));
+}
+
+#[test]
+fn test_frustum_culling() {
+    let mut scene = Scene {
+        camera: Camera::new(32, 16, 90_f32.to_radians(), 0.1, 100.0),
+        lights: vec![Light {
+            position: Vector3::ORIGIN,
+            color: Color {
+                r: 255,
+                g: 255,
+                b: 255,
+            }
+            .to_vector3(),
+            ambient_strength: 0.5,
+        }],
+        ..Default::default()
+    };
+    // the camera looks down -z, so this is right behind it
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::translation(0.0, -1.0, 5.0),
+        ..Default::default()
+    });
+    assert_eq!(scene.visible_models().count(), 0);
+
+    // nothing is rasterized, so the buffers come back untouched
+    for threads in [1, 4] {
+        let options = RenderOptions {
+            threads,
+            ..Default::default()
+        };
+        let mut pixels = vec![Color::default(); 32 * 16];
+        let mut depths = vec![f32::MAX; 32 * 16];
+        scene.render(options, &mut pixels, &mut depths);
+        assert!(pixels.iter().all(|&pixel| pixel == Color::default()));
+        assert!(depths.iter().all(|&depth| depth == f32::MAX));
+    }
+
+    // past the far plane and far off to the side are culled too, unless scaled back into view
+    scene.models[0].transform = Mat4::translation(0.0, 0.0, -200.0);
+    assert_eq!(scene.visible_models().count(), 0);
+    scene.models[0].transform = Mat4::translation(50.0, 0.0, -3.0);
+    assert_eq!(scene.visible_models().count(), 0);
+    scene.models[0].transform = Mat4::translation(50.0, 0.0, -3.0) * Mat4::scale(100.0, 1.0, 1.0);
+    assert_eq!(scene.visible_models().count(), 1);
+
+    // in front of the camera, and poking in from behind the left edge
+    scene.models[0].transform = Mat4::translation(0.0, -1.0, -3.0);
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::translation(-6.5, 0.0, -3.0),
+        ..Default::default()
+    });
+    assert_eq!(scene.visible_models().count(), 2);
+}
+
+#[test]
+fn test_camera_unproject() {
+    let eye = Vector3 {
+        x: 3.0,
+        y: 2.0,
+        z: 4.0,
+    };
+    let up = Vector3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+    let mut camera = Camera::new(64, 48, 70_f32.to_radians(), 0.5, 50.0);
+    camera.view_mat = Mat4::look_at(eye, Vector3::ORIGIN, up).unwrap();
+
+    // the middle of a pixel survives the round trip exactly, up to float error
+    let pixel = ScreenCoordinate { x: 20, y: 31 };
+    let world = camera.unproject(pixel, 0.9);
+    let ndc = camera.projection_mat * camera.view_mat * world;
+    assert_eq!(ndc.ndc_to_pixel(64, 48), pixel);
+    assert!((ndc.z - 0.9).abs() < 1.0e-4);
+
+    // any other point comes back within the footprint of the pixel it landed in
+    let point = Vector3 {
+        x: 0.3,
+        y: -0.2,
+        z: 0.1,
+    };
+    let ndc = camera.projection_mat * camera.view_mat * point;
+    let unprojected = camera.unproject(ndc.ndc_to_pixel(64, 48), ndc.z);
+    let pixel_size = 2.0 * Vector3::distance(eye, point) * 35_f32.to_radians().tan() / 48.0;
+    assert!(Vector3::distance(unprojected, point) < pixel_size);
+}
+
+#[test]
+fn test_unproject_rendered_depth() {
+    let (width, height) = (40, 30);
+    let mut scene = Scene {
+        camera: Camera::new(width, height, 90_f32.to_radians(), 0.1, 100.0),
+        ..Default::default()
+    };
+    // flattened onto z = -3 facing the camera, so every pixel holds the same depth no matter where
+    // inside of the pixel the rasterizer sampled it
+    scene.models.push(Model {
+        mesh: triangle_mesh(),
+        transform: Mat4::translation(-0.5, -1.0, -3.0) * Mat4::scale(1.0, 1.0, 0.0),
+        ..Default::default()
+    });
+    let mut pixels = vec![Color::default(); (width * height) as usize];
+    let mut depths = vec![f32::MAX; (width * height) as usize];
+    scene.render(RenderOptions::default(), &mut pixels, &mut depths);
+
+    let mut num_covered = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let depth = depths[(y * width + x) as usize];
+            if depth == f32::MAX {
+                continue;
+            }
+            num_covered += 1;
+            let world = scene.camera.unproject(ScreenCoordinate { x, y }, depth);
+            assert!((world.z + 3.0).abs() < 1.0e-4);
+            assert!(world.x > -0.6 && world.x < 0.6 && world.y > -1.1 && world.y < 1.1);
+        }
+    }
+    assert!(num_covered > 10);
+}
+
+#[test]
+fn test_camera_depth_image() {
+    let gray = |image: &Image, idx: usize| {
+        let pixel = image.data[idx];
+        assert!(pixel.r == pixel.g && pixel.g == pixel.b);
+        pixel.r
+    };
+    let ndc_depth = |camera: &Camera, distance: f32| {
+        (camera.projection_mat
+            * Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: -distance,
+            })
+        .z
+    };
+
+    // near is black, far and empty pixels are white, and the gray is linear in distance between
+    let camera = Camera::new(2, 2, 90_f32.to_radians(), 1.0, 11.0);
+    let depths = [
+        ndc_depth(&camera, 1.0),
+        ndc_depth(&camera, 6.0),
+        ndc_depth(&camera, 11.0),
+        f32::MAX,
+    ];
+    let image = camera.depth_image(&depths);
+    assert_eq!((image.width, image.height), (2, 2));
+    assert_eq!(gray(&image, 0), 0);
+    assert!((gray(&image, 1) as i32 - 128).abs() <= 1);
+    assert_eq!(gray(&image, 2), 255);
+    assert_eq!(gray(&image, 3), 255);
+
+    // without a far plane the farthest drawn depth is white
+    let camera = Camera::new(2, 2, 90_f32.to_radians(), 1.0, f32::INFINITY);
+    let depths = [
+        ndc_depth(&camera, 1.0),
+        ndc_depth(&camera, 3.0),
+        ndc_depth(&camera, 5.0),
+        f32::MAX,
+    ];
+    let image = camera.depth_image(&depths);
+    assert_eq!(gray(&image, 0), 0);
+    assert!((gray(&image, 1) as i32 - 128).abs() <= 1);
+    assert_eq!(gray(&image, 2), 255);
+}
+
+#[test]
+fn test_camera_depth_at() {
+    let camera = Camera::new(2, 2, 90_f32.to_radians(), 0.1, 100.0);
+    let mut depths = vec![0.1, 0.2, 0.3, 0.4];
+
+    // pixel middles give back the pixel, between them the depths blend
+    assert_eq!(camera.depth_at(&depths, 0.5, 0.5), 0.1);
+    assert_eq!(camera.depth_at(&depths, 1.5, 1.5), 0.4);
+    assert!((camera.depth_at(&depths, 1.0, 1.0) - 0.25).abs() < 1.0e-6);
+    assert!((camera.depth_at(&depths, 1.0, 0.5) - 0.15).abs() < 1.0e-6);
+    // past the edges the outermost pixels are repeated
+    assert_eq!(camera.depth_at(&depths, -3.0, 0.5), 0.1);
+    assert_eq!(camera.depth_at(&depths, 9.0, 9.0), 0.4);
+
+    // next to an empty pixel the nearest depth is used rather than blending toward the background
+    depths[3] = f32::MAX;
+    assert_eq!(camera.depth_at(&depths, 0.9, 0.9), 0.1);
+    assert_eq!(camera.depth_at(&depths, 1.1, 0.9), 0.2);
+}
+
+#[test]
+fn test_is_occluded() {
+    // a box in front of the camera far bigger than a small box that starts out behind it
+    let bounds = |min: (f32, f32, f32), max: (f32, f32, f32)| Aabb {
+        min: Vector3 {
+            x: min.0,
+            y: min.1,
+            z: min.2,
+        },
+        max: Vector3 {
+            x: max.0,
+            y: max.1,
+            z: max.2,
+        },
+    };
+    let options = RenderOptions::default();
+    let mut scene = Scene {
+        camera: Camera::new(32, 32, 90_f32.to_radians(), 0.1, 100.0),
+        models: vec![
+            Model {
+                mesh: box_mesh(bounds((-2.0, -2.0, -4.0), (2.0, 2.0, -3.0))),
+                ..Default::default()
+            },
+            Model {
+                mesh: box_mesh(bounds((-0.5, -0.5, -0.5), (0.5, 0.5, 0.5))),
+                transform: Mat4::translation(0.0, 0.0, -8.0),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+
+    // nothing drawn yet hides anything
+    let mut pixels = vec![Color::default(); 32 * 32];
+    let mut depths = vec![f32::MAX; 32 * 32];
+    assert!(!scene.is_occluded(&scene.models[1], options, &depths));
+
+    // with the front box in the depth buffer, the box fully behind it is hidden
+    scene.render_model(0, options, &mut pixels, &mut depths);
+    assert!(scene.is_occluded(&scene.models[1], options, &depths));
+
+    // but not once it's moved in front of it
+    scene.models[1].transform = Mat4::translation(0.0, 0.0, -2.0);
+    assert!(!scene.is_occluded(&scene.models[1], options, &depths));
+
+    // or out from behind it
+    scene.models[1].transform = Mat4::translation(5.0, 0.0, -8.0);
+    assert!(!scene.is_occluded(&scene.models[1], options, &depths));
+
+    // a camera inside of the bounding box can't rule anything out
+    scene.models[1].transform = Mat4::identity();
+    assert!(!scene.is_occluded(&scene.models[1], options, &depths));
+
+    // and neither can a depth buffer of the wrong size
+    scene.models[1].transform = Mat4::translation(0.0, 0.0, -8.0);
+    assert!(!scene.is_occluded(&scene.models[1], options, &depths[..16]));
+
+    // a model without any geometry never shows up
+    assert!(scene.is_occluded(&Model::default(), options, &depths));
+}